@@ -0,0 +1,27 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// 可在线程间共享的取消标记
+///
+/// 克隆、解析和建索引的循环会定期检查它，发现取消后尽快返回错误
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// 新操作开始前清除上一次的取消请求
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
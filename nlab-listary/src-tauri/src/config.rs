@@ -0,0 +1,139 @@
+use crate::REPO_URL;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to access config file: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Invalid config file: {0}")]
+    ParseError(#[from] serde_json::Error),
+}
+
+/// 一个 nLab 镜像：GitHub、Gitee、内网 HTTP 镜像或 `file://` 裸仓库
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mirror {
+    pub url: String,
+    /// HTTPS 镜像的用户名；未设置时尝试 git 的 credential helper
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// 密码或访问令牌
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+}
+
+impl Mirror {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            username: None,
+            password: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MirrorConfig {
+    /// 按顺序尝试的镜像，前一个失败时使用下一个
+    pub mirrors: Vec<Mirror>,
+    /// 代理地址，例如 `http://127.0.0.1:7890`；未设置时沿用 git 配置和环境变量中的代理
+    pub proxy: Option<String>,
+    /// 浅克隆（只获取最新提交）并只检出 `pages/**/content.html`，用于磁盘空间较小的机器
+    ///
+    /// 只在首次克隆时生效，已有的完整仓库不会被转换
+    pub shallow: bool,
+    /// 把镜像固定在某个提交、标签或日期（`YYYY-MM-DD`），用于让团队使用同一版本的索引
+    pub pin: Option<String>,
+}
+
+impl Default for MirrorConfig {
+    fn default() -> Self {
+        Self {
+            mirrors: vec![Mirror::new(REPO_URL)],
+            proxy: None,
+            shallow: false,
+            pin: None,
+        }
+    }
+}
+
+/// 后台定时同步和开机启动
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScheduleConfig {
+    /// 两次自动同步之间的小时数，例如 24 为每天一次；为 0 时只在启动时同步一次
+    pub interval_hours: u64,
+    /// 登录系统时启动应用
+    pub launch_at_login: bool,
+}
+
+/// 应用配置，保存在 `CONFIG_PATH`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub mirror: MirrorConfig,
+    pub schedule: ScheduleConfig,
+    /// 不使用 git 时的页面来源：导出的页面目录，或 `.tar.gz`/`.zip` 压缩包
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+impl Config {
+    /// 读取配置文件；文件不存在时返回默认配置
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_missing_config_uses_default_mirror() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = Config::load(&temp_dir.path().join("missing.json")).unwrap();
+
+        assert_eq!(config.mirror.mirrors, vec![Mirror::new(REPO_URL)]);
+        assert!(config.mirror.proxy.is_none());
+        assert!(!config.mirror.shallow);
+        assert_eq!(config.schedule.interval_hours, 0);
+    }
+
+    #[test]
+    fn test_partial_config_and_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.json");
+        fs::write(
+            &path,
+            r#"{ "mirror": { "mirrors": [
+                { "url": "https://gitee.com/example/nlab-content-html.git" },
+                { "url": "file:///mnt/nas/nlab.git" }
+            ] } }"#,
+        )
+        .unwrap();
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.mirror.mirrors.len(), 2);
+        assert_eq!(config.mirror.mirrors[1].url, "file:///mnt/nas/nlab.git");
+        assert!(config.mirror.proxy.is_none());
+
+        config.save(&path).unwrap();
+        assert_eq!(Config::load(&path).unwrap(), config);
+    }
+}
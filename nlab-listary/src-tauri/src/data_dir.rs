@@ -0,0 +1,91 @@
+use crate::{CONFIG_PATH, DB_PATH, GIT_REPO_PATH, INDEX_PATH, SNAPSHOTS_PATH, SOURCE_PATH};
+use std::path::{Path, PathBuf};
+
+/// 数据目录，镜像、数据库、索引和配置都在其中
+///
+/// 路径都从这里拼出，不依赖进程的当前目录：后台的同步和定时线程可能正在读写数据
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataDir {
+    root: PathBuf,
+}
+
+impl DataDir {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// git 镜像
+    pub fn mirror(&self) -> PathBuf {
+        self.root.join(GIT_REPO_PATH)
+    }
+
+    /// 数据库所在的目录
+    pub fn database(&self) -> PathBuf {
+        self.root.join(DB_PATH)
+    }
+
+    /// sled 数据库
+    pub fn storage(&self) -> PathBuf {
+        self.database().join("storage")
+    }
+
+    /// 搜索索引所在的目录
+    pub fn search_index(&self) -> PathBuf {
+        self.root.join(INDEX_PATH)
+    }
+
+    /// tantivy 索引
+    pub fn index(&self) -> PathBuf {
+        self.search_index().join("index")
+    }
+
+    pub fn config(&self) -> PathBuf {
+        self.root.join(CONFIG_PATH)
+    }
+
+    pub fn snapshots(&self) -> PathBuf {
+        self.root.join(SNAPSHOTS_PATH)
+    }
+
+    /// 压缩包来源解压到的目录
+    pub fn source(&self) -> PathBuf {
+        self.root.join(SOURCE_PATH)
+    }
+
+    /// 数据库和索引都已存在，启动时直接打开
+    pub fn has_local_data(&self) -> bool {
+        self.storage().exists() && self.index().exists()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_paths_are_under_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let data_dir = DataDir::new(temp_dir.path());
+        assert_eq!(data_dir.mirror(), temp_dir.path().join("nlab_mirror"));
+        assert_eq!(
+            data_dir.storage(),
+            temp_dir.path().join("nlab_page_data.db/storage")
+        );
+        assert_eq!(
+            data_dir.index(),
+            temp_dir.path().join("nlab_page_index/index")
+        );
+        assert_eq!(data_dir.config(), temp_dir.path().join("nlab_config.json"));
+
+        assert!(!data_dir.has_local_data());
+        std::fs::create_dir_all(data_dir.storage()).unwrap();
+        assert!(!data_dir.has_local_data());
+        std::fs::create_dir_all(data_dir.index()).unwrap();
+        assert!(data_dir.has_local_data());
+    }
+}
@@ -1,171 +1,1372 @@
-use super::REPO_URL;
+use crate::cancel::CancelToken;
+use crate::config::{Mirror, MirrorConfig};
+use crate::progress::{NoProgress, Phase, Progress, ProgressSink};
 use git2::build::CheckoutBuilder;
 use git2::{build::RepoBuilder, Repository};
-use git2::{FetchOptions, RemoteCallbacks};
-use std::io::Write;
+use git2::{
+    Cred, CredentialType, Delta, DiffOptions, FetchOptions, Oid, ProxyOptions, RemoteCallbacks,
+};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
 use std::path::Path;
 
-pub fn update_local_repository(path: &Path) -> Result<Repository, git2::Error> {
-    if path.exists() {
-        println!("本地仓库已存在，正在更新...");
-        let repo = Repository::open(path)?;
+/// 页面文件所在的目录（相对于仓库根目录）
+const PAGES_DIR: &str = "pages";
+/// 每个页面目录下的 HTML 文件名
+const PAGE_FILE_NAME: &str = "content.html";
+/// 稀疏检出时只检出的路径
+const SPARSE_PATHSPEC: &str = "pages/**/content.html";
+/// 仓库配置中标记稀疏检出的键，之后的每次检出都会读取它
+const SPARSE_CONFIG_KEY: &str = "nlab.sparse";
+/// 仓库配置中标记工作目录已删除的键；为 false 时检出只更新 HEAD，不写文件
+const WORKTREE_CONFIG_KEY: &str = "nlab.worktree";
+/// 浅克隆时获取的提交深度
+const SHALLOW_DEPTH: i32 = 1;
+/// 克隆前检查磁盘空间时使用的估计值（对象库加检出的工作目录）
+const FULL_CLONE_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+const SHALLOW_CLONE_SIZE: u64 = 1024 * 1024 * 1024;
+/// git bundle（v2）文件的第一行
+const BUNDLE_SIGNATURE: &str = "# v2 git bundle";
+/// 导出和导入 bundle 时使用的分支
+const BUNDLE_BRANCH: &str = "refs/heads/master";
 
-        // 检查是否有有效的 HEAD
-        if repo.head().is_err() {
-            println!("仓库状态异常，重新初始化...");
-            std::fs::remove_dir_all(path).ok();
-            return clone_with_progress(REPO_URL, path);
-        }
-
-        // 1. 执行 FETCH (获取远程最新状态)
-        fetch_repo(&repo)?;
-
-        // 2. 获取 FETCH_HEAD 并分析合并类型
-        let (analysis, oid) = get_fetch_head(&repo)?;
-
-        if analysis.0.is_up_to_date() {
-            println!("本地仓库已是最新版本。");
-        } else if analysis.0.is_fast_forward() {
-            println!("正在执行快进合并...");
-            // 3. 执行快进合并
-            // 获取 HEAD 指向的引用 (例如 refs/heads/main)
-            let mut reference = repo.head()?.resolve()?;
-            fast_forward(&repo, &mut reference, oid)?;
-            println!("更新完成。");
-        } else if analysis.0.is_normal() {
-            // 如果是普通合并（需要产生一个新的合并提交），逻辑会复杂得多
-            println!("发现需要普通合并的情况，请手动处理或使用更复杂的合并逻辑。");
-        } else {
-            println!("发现复杂或不可处理的 Git 状态。");
+/// 两次提交之间 `pages/**/content.html` 的变化，路径均相对于仓库根目录
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PageChanges {
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    pub deleted: Vec<String>,
+}
+
+impl PageChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.deleted.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.added.len() + self.modified.len() + self.deleted.len()
+    }
+
+    /// 需要重新解析的文件（新增 + 修改）
+    pub fn changed_files(&self) -> impl Iterator<Item = &String> {
+        self.added.iter().chain(self.modified.iter())
+    }
+}
+
+/// `update_local_repository` 的结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum RepoUpdate {
+    /// 新克隆的仓库，需要全量解析
+    Cloned,
+    /// 本地已是最新版本
+    UpToDate,
+    /// 已快进到远程提交，附带页面变化
+    FastForwarded {
+        old_head: Oid,
+        new_head: Oid,
+        changes: PageChanges,
+    },
+    /// 本地历史与远程分叉（例如上游 force-push），已硬重置到远程提交
+    Reset {
+        old_head: Oid,
+        new_head: Oid,
+        changes: PageChanges,
+    },
+    /// HEAD 无效的仓库（例如中断的克隆）已通过重新获取修复，需要全量解析
+    Repaired,
+    /// 仓库无法打开或修复，已删除并重新克隆，需要全量解析
+    Recloned,
+}
+
+impl RepoUpdate {
+    /// 用于同步记录的名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            RepoUpdate::Cloned => "cloned",
+            RepoUpdate::UpToDate => "up_to_date",
+            RepoUpdate::FastForwarded { .. } => "fast_forwarded",
+            RepoUpdate::Reset { .. } => "reset",
+            RepoUpdate::Repaired => "repaired",
+            RepoUpdate::Recloned => "recloned",
         }
+    }
 
-        Ok(repo)
-    } else {
+    /// 无法得知具体变化、需要全量解析的情况
+    pub fn needs_full_reindex(&self) -> bool {
+        matches!(
+            self,
+            RepoUpdate::Cloned | RepoUpdate::Repaired | RepoUpdate::Recloned
+        )
+    }
+}
+
+/// 一次成功的更新：结果和实际使用的镜像
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateReport {
+    pub update: RepoUpdate,
+    pub mirror: String,
+}
+
+pub fn update_local_repository(
+    path: &Path,
+    config: &MirrorConfig,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<UpdateReport, git2::Error> {
+    if !path.exists() {
         // 路径不存在：执行克隆 (Clone) 操作
         println!("本地仓库不存在，正在克隆...");
-        let repo = clone_with_progress(REPO_URL, path)?;
-        println!("克隆完成。");
-        Ok(repo)
+        let (repo, mirror) = clone_with_progress(config, path, progress, cancel)?;
+        apply_pin(&repo, config, progress)?;
+        return Ok(UpdateReport {
+            update: RepoUpdate::Cloned,
+            mirror,
+        });
     }
+
+    println!("本地仓库已存在，正在更新...");
+    let repo = match Repository::open(path) {
+        Ok(repo) => repo,
+        Err(e) => {
+            println!("无法打开本地仓库: {}", e);
+            return reclone(path, config, progress, cancel);
+        }
+    };
+
+    // 检查是否有有效的 HEAD；没有时先尝试在原仓库中重新获取，失败再重新克隆
+    let Some(old_head) = repo.head().ok().and_then(|head| head.target()) else {
+        println!("仓库状态异常，尝试重新获取...");
+        return match repair_repository(&repo, config, progress, cancel) {
+            Ok(mirror) => Ok(UpdateReport {
+                update: RepoUpdate::Repaired,
+                mirror,
+            }),
+            Err(e) if cancel.is_cancelled() => Err(e),
+            Err(e) => {
+                println!("修复失败: {}", e);
+                drop(repo);
+                reclone(path, config, progress, cancel)
+            }
+        };
+    };
+
+    // 1. 执行 FETCH (获取远程最新状态)
+    let mirror = fetch_repo(&repo, config, progress, cancel)?;
+    check_cancelled(cancel)?;
+
+    // 2. 确定目标提交（FETCH_HEAD 或固定的版本）并分析合并类型
+    let oid = target_commit(&repo, config)?;
+
+    if oid == old_head {
+        println!("本地仓库已是最新版本。");
+        return Ok(UpdateReport {
+            update: RepoUpdate::UpToDate,
+            mirror,
+        });
+    }
+
+    // 固定到更早的版本时不是快进，同样通过重置完成
+    let analysis = repo.merge_analysis(&[&repo.find_annotated_commit(oid)?])?;
+    let is_fast_forward = analysis.0.is_fast_forward();
+    if is_fast_forward {
+        println!("正在执行快进合并...");
+        // 3. 执行快进合并
+        // 获取 HEAD 指向的引用 (例如 refs/heads/main)
+        let mut reference = repo.head()?.resolve()?;
+        fast_forward(&repo, &mut reference, oid, progress)?;
+    } else {
+        // 镜像是只读的，不需要保留本地历史：分叉时直接重置到远程提交
+        println!("本地历史与远程分叉，重置到远程提交...");
+        hard_reset(&repo, oid, progress)?;
+    }
+
+    // 4. 比较更新前后的 HEAD，找出变化的页面
+    let changes = diff_pages(&repo, old_head, oid)?;
+    println!(
+        "页面变化: 新增 {}, 修改 {}, 删除 {}",
+        changes.added.len(),
+        changes.modified.len(),
+        changes.deleted.len()
+    );
+
+    let update = if is_fast_forward {
+        RepoUpdate::FastForwarded {
+            old_head,
+            new_head: oid,
+            changes,
+        }
+    } else {
+        RepoUpdate::Reset {
+            old_head,
+            new_head: oid,
+            changes,
+        }
+    };
+    Ok(UpdateReport { update, mirror })
 }
 
-pub fn clone_with_progress(url: &str, path: &Path) -> Result<Repository, git2::Error> {
-    let mut callbacks = RemoteCallbacks::new();
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+}
 
-    // 使用 sideband_progress 来实时显示下载进度
-    callbacks.sideband_progress(|data| {
-        print!("\r远程: {}", String::from_utf8_lossy(data));
-        std::io::stdout().flush().unwrap();
-        true
-    });
+/// 预览中的一个页面变化
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PreviewPage {
+    pub kind: ChangeKind,
+    pub file_path: String,
+    /// 页面标题；删除的页面取自旧版本，无法解析时为空
+    pub title: String,
+}
+
+/// 同步预览：fetch 之后、HEAD 尚未移动时，更新将带来的页面变化
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UpdatePreview {
+    pub mirror: String,
+    pub head: String,
+    pub target: String,
+    pub pages: Vec<PreviewPage>,
+}
+
+/// 只 fetch，不移动 HEAD：比较 HEAD 和将要更新到的提交（FETCH_HEAD 或固定的版本）
+///
+/// 页面标题直接从对象库中的 blob 解析，不需要检出
+pub fn preview_update(
+    path: &Path,
+    config: &MirrorConfig,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<UpdatePreview, git2::Error> {
+    let repo = Repository::open(path)?;
+    let head = repo.refname_to_id("HEAD")?;
+
+    let mirror = fetch_repo(&repo, config, progress, cancel)?;
+    check_cancelled(cancel)?;
+    let target = target_commit(&repo, config)?;
+
+    let changes = if target == head {
+        PageChanges::default()
+    } else {
+        diff_pages(&repo, head, target)?
+    };
+
+    let new_tree = repo.find_commit(target)?.tree()?;
+    let old_tree = repo.find_commit(head)?.tree()?;
+    let mut pages = Vec::with_capacity(changes.len());
+    for (kind, files, tree) in [
+        (ChangeKind::Added, &changes.added, &new_tree),
+        (ChangeKind::Modified, &changes.modified, &new_tree),
+        (ChangeKind::Deleted, &changes.deleted, &old_tree),
+    ] {
+        for file_path in files {
+            check_cancelled(cancel)?;
+            pages.push(PreviewPage {
+                kind,
+                file_path: file_path.clone(),
+                title: page_title(&repo, tree, file_path).unwrap_or_default(),
+            });
+        }
+    }
+
+    Ok(UpdatePreview {
+        mirror,
+        head: head.to_string(),
+        target: target.to_string(),
+        pages,
+    })
+}
+
+fn page_title(repo: &Repository, tree: &git2::Tree, file_path: &str) -> Option<String> {
+    let blob = repo
+        .find_blob(tree.get_path(Path::new(file_path)).ok()?.id())
+        .ok()?;
+    let html_content = String::from_utf8_lossy(blob.content());
+    crate::parser::parse_html_content(file_path.to_string(), &html_content)
+        .ok()
+        .flatten()
+        .map(|page| page.title)
+}
 
-    callbacks.transfer_progress(|stats| {
-        if stats.received_objects() == stats.total_objects() {
-            print!(
-                "\rAnalysising: {}/{}",
-                stats.indexed_objects(),
-                stats.total_objects()
-            );
+/// 在 HEAD 无效的仓库中重新获取远程提交，并让当前分支指向它
+fn repair_repository(
+    repo: &Repository,
+    config: &MirrorConfig,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<String, git2::Error> {
+    let mirror = fetch_repo(repo, config, progress, cancel)?;
+    check_cancelled(cancel)?;
+
+    let oid = target_commit(repo, config)?;
+
+    // HEAD 可能指向一个尚未创建的分支（unborn），沿用它的名字
+    let branch = repo
+        .find_reference("HEAD")?
+        .symbolic_target()
+        .unwrap_or("refs/heads/master")
+        .to_string();
+    repo.reference(&branch, oid, true, "repair")?;
+    repo.set_head(&branch)?;
+    repo.checkout_head(Some(&mut checkout_builder(repo, progress)?))?;
+
+    println!("仓库已修复，当前提交 {}", oid);
+    Ok(mirror)
+}
+
+fn reclone(
+    path: &Path,
+    config: &MirrorConfig,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<UpdateReport, git2::Error> {
+    println!("删除本地仓库并重新克隆...");
+    std::fs::remove_dir_all(path).ok();
+    let (repo, mirror) = clone_with_progress(config, path, progress, cancel)?;
+    apply_pin(&repo, config, progress)?;
+    Ok(UpdateReport {
+        update: RepoUpdate::Recloned,
+        mirror,
+    })
+}
+
+/// 比较两个提交的树，列出新增、修改和删除的页面文件
+pub fn diff_pages(repo: &Repository, old: Oid, new: Oid) -> Result<PageChanges, git2::Error> {
+    let old_tree = repo.find_commit(old)?.tree()?;
+    let new_tree = repo.find_commit(new)?.tree()?;
+
+    let mut diff_options = DiffOptions::new();
+    diff_options.pathspec(PAGES_DIR);
+    let diff = repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), Some(&mut diff_options))?;
+
+    let mut changes = PageChanges::default();
+    for delta in diff.deltas() {
+        let old_path = delta.old_file().path().and_then(|p| p.to_str());
+        let new_path = delta.new_file().path().and_then(|p| p.to_str());
+
+        match delta.status() {
+            Delta::Added | Delta::Copied => push_page(&mut changes.added, new_path),
+            Delta::Modified | Delta::Typechange => push_page(&mut changes.modified, new_path),
+            Delta::Deleted => push_page(&mut changes.deleted, old_path),
+            Delta::Renamed => {
+                push_page(&mut changes.deleted, old_path);
+                push_page(&mut changes.added, new_path);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(changes)
+}
+
+fn push_page(list: &mut Vec<String>, path: Option<&str>) {
+    if let Some(path) = path.filter(|p| is_page_file(p)) {
+        list.push(path.to_string());
+    }
+}
+
+/// 判断仓库中的相对路径是否为 `pages/**/content.html`
+pub fn is_page_file(relative_path: &str) -> bool {
+    let path = Path::new(relative_path);
+    path.starts_with(PAGES_DIR) && path.file_name().is_some_and(|name| name == PAGE_FILE_NAME)
+}
+
+/// 修改过某个页面的一次提交
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PageRevision {
+    pub commit: String,
+    /// 提交时间（Unix 时间戳）
+    pub time: i64,
+    /// 提交信息的第一行
+    pub summary: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffLineKind {
+    /// `@@ -a,b +c,d @@` 形式的块标题
+    Hunk,
+    Context,
+    Added,
+    Removed,
+}
+
+/// 页面两个版本之间文本差异的一行
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    pub text: String,
+}
+
+/// 从 HEAD 开始按时间倒序列出修改过 `file_path` 的提交，最多 `limit` 个
+pub fn page_history(
+    path: &Path,
+    file_path: &str,
+    limit: usize,
+) -> Result<Vec<PageRevision>, git2::Error> {
+    let repo = Repository::open(path)?;
+    let file = Path::new(file_path);
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_head()?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+
+    let mut revisions = Vec::new();
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        let blob = page_blob_id(&commit, file);
+
+        // 与所有父提交都不同才算修改了页面，合并进来的修改只记在原提交上
+        let changed = if commit.parent_count() == 0 {
+            blob.is_some()
         } else {
-            print!(
-                "\rReceiving: {}/{} ({:.2} MB)",
-                stats.received_objects(),
-                stats.total_objects(),
-                stats.received_bytes() as f64 / 1024.0 / 1024.0
-            );
-        }
-        std::io::stdout().flush().unwrap();
-        true
-    });
+            commit
+                .parents()
+                .all(|parent| page_blob_id(&parent, file) != blob)
+        };
+        if changed {
+            revisions.push(PageRevision {
+                commit: commit.id().to_string(),
+                time: commit.time().seconds(),
+                summary: commit.summary().unwrap_or_default().to_string(),
+            });
+            if revisions.len() >= limit {
+                break;
+            }
+        }
+    }
+
+    Ok(revisions)
+}
+
+fn page_blob_id(commit: &git2::Commit, file: &Path) -> Option<Oid> {
+    commit
+        .tree()
+        .ok()?
+        .get_path(file)
+        .ok()
+        .map(|entry| entry.id())
+}
+
+/// 比较页面在 `old` 和 `new` 两个版本的正文，按 `parser::extract_content_lines` 的方式清理后逐行比较
+///
+/// 某个版本中页面不存在时视为空
+pub fn page_diff(
+    path: &Path,
+    file_path: &str,
+    old: &str,
+    new: &str,
+) -> Result<Vec<DiffLine>, git2::Error> {
+    let repo = Repository::open(path)?;
+    let old_text = page_text_at(&repo, old, file_path)?;
+    let new_text = page_text_at(&repo, new, file_path)?;
 
+    let mut diff_options = DiffOptions::new();
+    diff_options.context_lines(2);
+    let patch = git2::Patch::from_buffers(
+        old_text.as_bytes(),
+        None,
+        new_text.as_bytes(),
+        None,
+        Some(&mut diff_options),
+    )?;
+
+    let mut lines = Vec::new();
+    for hunk_index in 0..patch.num_hunks() {
+        let (hunk, line_count) = patch.hunk(hunk_index)?;
+        lines.push(DiffLine {
+            kind: DiffLineKind::Hunk,
+            text: String::from_utf8_lossy(hunk.header())
+                .trim_end()
+                .to_string(),
+        });
+        for line_index in 0..line_count {
+            let line = patch.line_in_hunk(hunk_index, line_index)?;
+            let kind = match line.origin() {
+                '+' => DiffLineKind::Added,
+                '-' => DiffLineKind::Removed,
+                ' ' => DiffLineKind::Context,
+                _ => continue,
+            };
+            lines.push(DiffLine {
+                kind,
+                text: String::from_utf8_lossy(line.content())
+                    .trim_end()
+                    .to_string(),
+            });
+        }
+    }
+
+    Ok(lines)
+}
+
+/// 页面在 `revision` 时的正文，每个文本节点一行
+fn page_text_at(repo: &Repository, revision: &str, file_path: &str) -> Result<String, git2::Error> {
+    let commit = repo.revparse_single(revision)?.peel_to_commit()?;
+    let Some(blob_id) = page_blob_id(&commit, Path::new(file_path)) else {
+        return Ok(String::new());
+    };
+
+    let blob = repo.find_blob(blob_id)?;
+    let html_content = String::from_utf8_lossy(blob.content());
+    let mut text = crate::parser::extract_content_lines(&html_content).join("\n");
+    text.push('\n');
+    Ok(text)
+}
+
+/// 把 `oid` 设为当前分支的提交并强制检出，用于同步失败或取消后回滚
+pub fn reset_head(path: &Path, oid: Oid) -> Result<(), git2::Error> {
+    let repo = Repository::open(path)?;
+    hard_reset(&repo, oid, &NoProgress)?;
+    println!("已回滚到提交 {}", oid);
+    Ok(())
+}
+
+fn hard_reset(repo: &Repository, oid: Oid, progress: &dyn ProgressSink) -> Result<(), git2::Error> {
+    let commit = repo.find_object(oid, Some(git2::ObjectType::Commit))?;
+    repo.reset(
+        &commit,
+        git2::ResetType::Hard,
+        Some(&mut checkout_builder(repo, progress)?),
+    )
+}
+
+/// 取消时返回的错误
+pub fn cancelled_error() -> git2::Error {
+    git2::Error::new(
+        git2::ErrorCode::User,
+        git2::ErrorClass::Callback,
+        "operation cancelled",
+    )
+}
+
+fn check_cancelled(cancel: &CancelToken) -> Result<(), git2::Error> {
+    if cancel.is_cancelled() {
+        Err(cancelled_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// 克隆第一个镜像大约需要的磁盘空间；bundle 按文件大小估计
+pub fn estimated_clone_size(config: &MirrorConfig) -> u64 {
+    let bundle_size = config
+        .mirrors
+        .first()
+        .filter(|mirror| is_bundle(&mirror.url))
+        .and_then(|mirror| std::fs::metadata(bundle_path(&mirror.url)).ok())
+        .map(|metadata| metadata.len());
+    match bundle_size {
+        // 导入的包和检出的页面
+        Some(size) => size * 3,
+        None if config.shallow => SHALLOW_CLONE_SIZE,
+        None => FULL_CLONE_SIZE,
+    }
+}
+
+/// 按顺序尝试 `config` 中的镜像进行克隆，返回仓库和成功的镜像地址
+///
+/// 每次失败（包括取消）都会删除不完整的仓库目录
+pub fn clone_with_progress(
+    config: &MirrorConfig,
+    path: &Path,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<(Repository, String), git2::Error> {
+    let mut failures = Vec::new();
+
+    for mirror in &config.mirrors {
+        println!("正在从 {} 克隆...", mirror.url);
+        let result = if is_bundle(&mirror.url) {
+            clone_from_bundle(&mirror.url, config, path, progress, cancel)
+        } else {
+            clone_from_mirror(mirror, config, path, progress, cancel)
+        };
+        match result {
+            Ok(repo) => {
+                println!("\n克隆完成");
+                return Ok((repo, mirror.url.clone()));
+            }
+            Err(e) => {
+                println!("\n克隆失败，删除不完整的仓库: {}", e);
+                std::fs::remove_dir_all(path).ok();
+                if cancel.is_cancelled() {
+                    return Err(cancelled_error());
+                }
+                // 磁盘已满时换一个镜像也不会成功
+                if crate::maintenance::is_disk_full(&e) {
+                    return Err(e);
+                }
+                failures.push(format!("{}: {}", mirror.url, e.message()));
+            }
+        }
+    }
+
+    Err(all_mirrors_failed(&failures))
+}
+
+fn clone_from_mirror(
+    mirror: &Mirror,
+    config: &MirrorConfig,
+    path: &Path,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<Repository, git2::Error> {
     let mut fetch_options = FetchOptions::new();
-    fetch_options.remote_callbacks(callbacks);
+    fetch_options.remote_callbacks(transfer_callbacks(Phase::Clone, mirror, progress, cancel));
+    fetch_options.proxy_options(proxy_options(config));
+    if config.shallow {
+        // libgit2 的本地传输不支持浅获取，本地镜像只做稀疏检出
+        if is_local_url(&mirror.url) {
+            progress.message("Local mirrors cannot be cloned shallowly; fetching the full history");
+        } else {
+            fetch_options.depth(SHALLOW_DEPTH);
+        }
+    }
 
-    let mut checkout_last_printed = 0;
     let mut checkout_options = CheckoutBuilder::new();
     checkout_options
-        .progress(|_path, completed_steps, total_steps| {
-            if total_steps > 0
-                && (completed_steps - checkout_last_printed >= 1000
-                    || completed_steps == total_steps)
-            {
-                print!("\r检出：{}/{}", completed_steps, total_steps);
-                checkout_last_printed = completed_steps;
-                std::io::stdout().flush().unwrap();
-            }
-        })
+        .progress(checkout_progress(progress))
         .force(); // 强制检出以覆盖文件
+    if config.shallow {
+        checkout_options.path(SPARSE_PATHSPEC);
+    }
 
     let mut builder = RepoBuilder::new();
     builder.fetch_options(fetch_options);
     builder.with_checkout(checkout_options);
 
-    let repo = builder.clone(url, path)?;
-    println!("\n克隆完成");
-
+    let repo = builder.clone(&mirror.url, path)?;
+    if config.shallow {
+        repo.config()?.set_bool(SPARSE_CONFIG_KEY, true)?;
+    }
     Ok(repo)
 }
 
-fn fetch_repo(repo: &Repository) -> Result<(), git2::Error> {
+fn is_local_url(url: &str) -> bool {
+    url.starts_with("file://") || Path::new(url).exists()
+}
+
+/// 仓库是否以稀疏检出模式克隆
+pub fn is_sparse(repo: &Repository) -> bool {
+    repo.config()
+        .and_then(|config| config.get_bool(SPARSE_CONFIG_KEY))
+        .unwrap_or(false)
+}
+
+/// 仓库是否保留工作目录；`remove_worktree` 之后为 false
+pub fn has_worktree(repo: &Repository) -> bool {
+    repo.config()
+        .and_then(|config| config.get_bool(WORKTREE_CONFIG_KEY))
+        .unwrap_or(true)
+}
+
+/// 删除工作目录中除 `.git` 以外的全部文件，返回释放的字节数
+///
+/// 页面都可以从对象库读取；之后的检出只移动 HEAD，直到 `restore_worktree`
+pub fn remove_worktree(path: &Path) -> Result<u64, git2::Error> {
+    let repo = Repository::open(path)?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| git2::Error::from_str("the mirror has no working tree"))?
+        .to_path_buf();
+    // 先写标记：删除中途失败时，下一次检出也不会把文件写回来
+    repo.config()?.set_bool(WORKTREE_CONFIG_KEY, false)?;
+
+    let mut freed = 0;
+    for entry in std::fs::read_dir(&workdir).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let entry_path = entry.path();
+        freed += crate::maintenance::dir_size(&entry_path);
+        if entry.file_type().map_err(io_error)?.is_dir() {
+            std::fs::remove_dir_all(&entry_path).map_err(io_error)?;
+        } else {
+            std::fs::remove_file(&entry_path).map_err(io_error)?;
+        }
+    }
+    println!("已删除工作目录，释放 {} 字节", freed);
+    Ok(freed)
+}
+
+/// 重新检出 HEAD 的工作目录
+pub fn restore_worktree(path: &Path, progress: &dyn ProgressSink) -> Result<(), git2::Error> {
+    let repo = Repository::open(path)?;
+    repo.config()?.set_bool(WORKTREE_CONFIG_KEY, true)?;
+    repo.checkout_head(Some(&mut checkout_builder(&repo, progress)?))?;
+    println!("已恢复工作目录");
+    Ok(())
+}
+
+/// 按顺序尝试 `config` 中的镜像获取更新，返回成功的镜像地址
+///
+/// 成功的镜像会被设为 `origin` 的地址
+fn fetch_repo(
+    repo: &Repository,
+    config: &MirrorConfig,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<String, git2::Error> {
+    let mut failures = Vec::new();
+
+    for mirror in &config.mirrors {
+        println!("正在从 {} 获取更新...", mirror.url);
+        let result = if is_bundle(&mirror.url) {
+            fetch_bundle(repo, &mirror.url, progress, cancel)
+        } else {
+            fetch_from_mirror(repo, mirror, config, progress, cancel)
+        };
+
+        // 取消时已接收的对象留在对象库中，不影响 HEAD
+        match result {
+            Ok(()) => return Ok(mirror.url.clone()),
+            Err(_) if cancel.is_cancelled() => return Err(cancelled_error()),
+            Err(e) => {
+                println!("\n获取失败: {}", e);
+                failures.push(format!("{}: {}", mirror.url, e.message()));
+            }
+        }
+    }
+
+    Err(all_mirrors_failed(&failures))
+}
+
+fn fetch_from_mirror(
+    repo: &Repository,
+    mirror: &Mirror,
+    config: &MirrorConfig,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<(), git2::Error> {
+    repo.remote_set_url("origin", &mirror.url)?;
     let mut remote = repo.find_remote("origin")?;
 
-    // 为 fetch 添加 sideband_progress 回调
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.remote_callbacks(transfer_callbacks(Phase::Fetch, mirror, progress, cancel));
+    fetch_options.proxy_options(proxy_options(config));
+
+    // libgit2 的本地传输不报告浅克隆的边界：获取后会删掉 `.git/shallow`，并清空
+    // 仓库句柄中已加载的边界，而边界之前的提交并不在对象库中。
+    // 因此用另一个句柄获取，再把文件写回
+    if repo.is_shallow() && is_local_url(&mirror.url) {
+        let shallow_path = repo.path().join("shallow");
+        let shallow = std::fs::read(&shallow_path).map_err(io_error)?;
+        let result = Repository::open(repo.path())?
+            .find_remote("origin")?
+            .fetch::<&str>(&[], Some(&mut fetch_options), None);
+        std::fs::write(&shallow_path, shallow).map_err(io_error)?;
+        return result;
+    }
+
+    remote.fetch::<&str>(&[], Some(&mut fetch_options), None)
+}
+
+/// 本地文件（而不是目录）形式的镜像视为 git bundle，用于没有网络的机器
+pub fn is_bundle(url: &str) -> bool {
+    bundle_path(url).is_file()
+}
+
+fn bundle_path(url: &str) -> &Path {
+    Path::new(url.strip_prefix("file://").unwrap_or(url))
+}
+
+/// bundle 的文件头：接收方必须已有的提交，以及 bundle 包含的引用
+#[derive(Debug, Default)]
+struct BundleHeader {
+    prerequisites: Vec<Oid>,
+    refs: Vec<(String, Oid)>,
+}
+
+impl BundleHeader {
+    /// 要更新到的提交：优先 `HEAD`，否则为第一个引用
+    fn tip(&self) -> Result<Oid, git2::Error> {
+        self.refs
+            .iter()
+            .find(|(name, _)| name == "HEAD")
+            .or_else(|| self.refs.first())
+            .map(|(_, oid)| *oid)
+            .ok_or_else(|| git2::Error::from_str("bundle contains no refs"))
+    }
+}
+
+fn io_error(e: std::io::Error) -> git2::Error {
+    git2::Error::from_str(&e.to_string())
+}
+
+/// 读取 bundle 的文件头，返回停在包数据开头的 reader
+fn read_bundle_header(path: &Path) -> Result<(BundleHeader, BufReader<File>), git2::Error> {
+    let file = File::open(path).map_err(io_error)?;
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(io_error)?;
+    if line.trim_end() != BUNDLE_SIGNATURE {
+        return Err(git2::Error::from_str(&format!(
+            "{} is not a v2 git bundle",
+            path.display()
+        )));
+    }
+
+    let mut header = BundleHeader::default();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(io_error)? == 0 {
+            return Err(git2::Error::from_str("unexpected end of bundle header"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(prerequisite) = line.strip_prefix('-') {
+            // `-<oid> <提交信息>`
+            let oid = prerequisite.split(' ').next().unwrap_or_default();
+            header.prerequisites.push(Oid::from_str(oid)?);
+        } else {
+            // `<oid> <引用名>`
+            let (oid, name) = line
+                .split_once(' ')
+                .ok_or_else(|| git2::Error::from_str("invalid ref in bundle header"))?;
+            header.refs.push((name.to_string(), Oid::from_str(oid)?));
+        }
+    }
+
+    Ok((header, reader))
+}
+
+/// 把 bundle 的包数据写入对象库，返回 bundle 的提交
+///
+/// 镜像缺少 bundle 的前置提交时返回错误，此时对象库不会被修改
+fn unbundle(
+    repo: &Repository,
+    url: &str,
+    phase: Phase,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<Oid, git2::Error> {
+    let (header, mut reader) = read_bundle_header(bundle_path(url))?;
+    for oid in &header.prerequisites {
+        if repo.find_commit(*oid).is_err() {
+            return Err(git2::Error::new(
+                git2::ErrorCode::NotFound,
+                git2::ErrorClass::Odb,
+                format!(
+                    "bundle requires commit {} which is not in the local mirror",
+                    oid
+                ),
+            ));
+        }
+    }
+    let tip = header.tip()?;
+
+    let odb = repo.odb()?;
+    let mut writer = odb.packwriter()?;
+    writer.progress(move |stats| {
+        progress.report(Progress {
+            phase,
+            current: stats.indexed_objects(),
+            total: stats.total_objects(),
+            received_bytes: stats.received_bytes(),
+        });
+        true
+    });
+
+    // 取消时丢弃 writer，不提交已写入的部分
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        check_cancelled(cancel)?;
+        let read = reader.read(&mut buffer).map_err(io_error)?;
+        if read == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..read]).map_err(io_error)?;
+    }
+    writer.commit()?;
+
+    repo.find_commit(tip)?;
+    Ok(tip)
+}
+
+/// 从完整的 bundle 创建镜像
+fn clone_from_bundle(
+    url: &str,
+    config: &MirrorConfig,
+    path: &Path,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<Repository, git2::Error> {
+    let repo = Repository::init(path)?;
+    if config.shallow {
+        repo.config()?.set_bool(SPARSE_CONFIG_KEY, true)?;
+    }
+
+    let tip = unbundle(&repo, url, Phase::Clone, progress, cancel)?;
+    repo.reference(BUNDLE_BRANCH, tip, true, "clone from bundle")?;
+    repo.set_head(BUNDLE_BRANCH)?;
+    repo.checkout_head(Some(&mut checkout_builder(&repo, progress)?))?;
+    Ok(repo)
+}
+
+/// 导入增量 bundle，并像 fetch 一样让 FETCH_HEAD 指向它的提交
+fn fetch_bundle(
+    repo: &Repository,
+    url: &str,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<(), git2::Error> {
+    let tip = unbundle(repo, url, Phase::Fetch, progress, cancel)?;
+    repo.reference("FETCH_HEAD", tip, true, "fetch from bundle")?;
+    Ok(())
+}
+
+/// 导出的 bundle
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BundleSummary {
+    pub path: String,
+    pub tip: String,
+    /// 接收方必须已有的提交；为空时是完整的 bundle，可用于首次克隆
+    pub since: Option<String>,
+    pub object_count: usize,
+}
+
+/// 把镜像 HEAD 的历史导出为 bundle；给定 `since`（提交号、标签或日期）时只包含它之后的提交
+///
+/// 失败或取消时删除不完整的文件
+pub fn export_bundle(
+    path: &Path,
+    since: Option<&str>,
+    output: &Path,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<BundleSummary, git2::Error> {
+    let repo = Repository::open(path)?;
+    let head = repo.refname_to_id("HEAD")?;
+    let since = since
+        .map(|revision| resolve_revision_from(&repo, revision, head))
+        .transpose()?;
+    if since == Some(head) {
+        return Err(git2::Error::from_str(
+            "nothing to export: the mirror has no commits after the given revision",
+        ));
+    }
+
+    let result = write_bundle(&repo, head, since, output, progress, cancel);
+    if result.is_err() {
+        std::fs::remove_file(output).ok();
+    }
+    let object_count = result?;
+    println!("已导出 {} 个对象到 {}", object_count, output.display());
+
+    Ok(BundleSummary {
+        path: output.display().to_string(),
+        tip: head.to_string(),
+        since: since.map(|oid| oid.to_string()),
+        object_count,
+    })
+}
+
+fn write_bundle(
+    repo: &Repository,
+    head: Oid,
+    since: Option<Oid>,
+    output: &Path,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<usize, git2::Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(head)?;
+    if let Some(since) = since {
+        revwalk.hide(since)?;
+    }
+
+    let mut builder = repo.packbuilder()?;
+    builder.set_progress_callback(|_stage, current, total| {
+        progress.report(Progress::new(Phase::Pack, current as usize, total as usize));
+        // 返回 false 会让 git2 中止打包
+        !cancel.is_cancelled()
+    })?;
+    builder.insert_walk(&mut revwalk)?;
+    check_cancelled(cancel)?;
+
+    let mut header = format!("{}\n", BUNDLE_SIGNATURE);
+    if let Some(since) = since {
+        let commit = repo.find_commit(since)?;
+        header.push_str(&format!(
+            "-{} {}\n",
+            since,
+            commit.summary().unwrap_or_default()
+        ));
+    }
+    header.push_str(&format!("{} {}\n{} HEAD\n\n", head, BUNDLE_BRANCH, head));
+
+    let mut file = File::create(output).map_err(io_error)?;
+    file.write_all(header.as_bytes()).map_err(io_error)?;
+    let mut write_error = None;
+    let result = builder.foreach(|chunk| match file.write_all(chunk) {
+        Ok(()) => true,
+        Err(e) => {
+            write_error = Some(e);
+            false
+        }
+    });
+    if let Some(e) = write_error {
+        return Err(io_error(e));
+    }
+    result?;
+    file.sync_all().map_err(io_error)?;
+
+    Ok(builder.object_count())
+}
+
+/// 把所有引用可达的对象重新打包为一个包，并删除旧的包和松散对象，相当于 `git gc --prune=now`
+///
+/// 返回新包中的对象数；写入新包之前出错或取消时不删除任何文件
+pub fn gc_repository(
+    path: &Path,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<usize, git2::Error> {
+    let (objects_dir, pack_name, object_count) = {
+        let repo = Repository::open(path)?;
+        let objects_dir = repo.path().join("objects");
+        let (pack_name, object_count) = write_repack(&repo, &objects_dir, progress, cancel)?;
+        (objects_dir, pack_name, object_count)
+    };
+
+    // 新包已经包含所有可达对象，其余的包和松散对象都可以删除
+    let keep = format!("pack-{}", pack_name);
+    let pack_dir = objects_dir.join("pack");
+    for entry in std::fs::read_dir(&pack_dir).map_err(io_error)? {
+        let entry_path = entry.map_err(io_error)?.path();
+        let Some(stem) = entry_path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        // multi-pack-index 会引用被删除的包，git 需要时会重新生成
+        let is_stale_pack = stem.starts_with("pack-")
+            && stem != keep
+            && !pack_dir.join(format!("{}.keep", stem)).exists();
+        if is_stale_pack || stem == "multi-pack-index" {
+            std::fs::remove_file(&entry_path).map_err(io_error)?;
+        }
+    }
+    for entry in std::fs::read_dir(&objects_dir).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        let is_loose_dir = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.len() == 2 && name.chars().all(|c| c.is_ascii_hexdigit()));
+        if is_loose_dir {
+            std::fs::remove_dir_all(entry.path()).map_err(io_error)?;
+        }
+    }
+
+    println!("已重新打包 {} 个对象", object_count);
+    Ok(object_count)
+}
+
+/// 写入包含 HEAD、所有引用和 FETCH_HEAD 可达对象的新包，返回包名和对象数
+fn write_repack(
+    repo: &Repository,
+    objects_dir: &Path,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<(String, usize), git2::Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_head()?;
+    revwalk.push_glob("*")?;
+    // 预览或导入 bundle 之后，FETCH_HEAD 指向的提交可能还没有被其他引用包含
+    if let Ok(fetch_head) = get_fetch_head(repo) {
+        revwalk.push(fetch_head)?;
+    }
+
+    let mut builder = repo.packbuilder()?;
+    builder.set_progress_callback(|_stage, current, total| {
+        progress.report(Progress::new(Phase::Pack, current as usize, total as usize));
+        !cancel.is_cancelled()
+    })?;
+    builder.insert_walk(&mut revwalk)?;
+    // 附注标签对象不在提交历史中，需要单独加入
+    for reference in repo.references()? {
+        let Some(oid) = reference?.target() else {
+            continue;
+        };
+        if repo.find_object(oid, None)?.kind() == Some(git2::ObjectType::Tag) {
+            builder.insert_object(oid, None)?;
+        }
+    }
+    check_cancelled(cancel)?;
+
+    builder.write(&objects_dir.join("pack"), 0)?;
+    check_cancelled(cancel)?;
+    let pack_name = builder
+        .name()
+        .ok_or_else(|| git2::Error::from_str("failed to name the new pack"))?
+        .to_string();
+    Ok((pack_name, builder.object_count()))
+}
+
+fn all_mirrors_failed(failures: &[String]) -> git2::Error {
+    if failures.is_empty() {
+        git2::Error::from_str("no mirror configured")
+    } else {
+        git2::Error::from_str(&format!("all mirrors failed: {}", failures.join("; ")))
+    }
+}
+
+/// 配置了代理时使用它，否则沿用 git 配置和环境变量中的代理
+fn proxy_options(config: &MirrorConfig) -> ProxyOptions<'_> {
+    let mut proxy_options = ProxyOptions::new();
+    match &config.proxy {
+        Some(url) => proxy_options.url(url),
+        None => proxy_options.auto(),
+    };
+    proxy_options
+}
+
+/// 把 clone/fetch 的传输进度转发给 `progress`，`cancel` 被触发时中止传输
+fn transfer_callbacks<'a>(
+    phase: Phase,
+    mirror: &'a Mirror,
+    progress: &'a dyn ProgressSink,
+    cancel: &'a CancelToken,
+) -> RemoteCallbacks<'a> {
     let mut callbacks = RemoteCallbacks::new();
-    callbacks.sideband_progress(|data| {
-        print!("\r远程: {}", String::from_utf8_lossy(data));
-        std::io::stdout().flush().unwrap();
+    callbacks.credentials(credentials(mirror));
+
+    // 远程的消息（例如服务器端压缩对象的进度）以 `\r` 或换行分隔
+    callbacks.sideband_progress(move |data| {
+        let text = String::from_utf8_lossy(data);
+        for line in text
+            .split(['\r', '\n'])
+            .filter(|line| !line.trim().is_empty())
+        {
+            progress.message(line.trim());
+        }
         true
     });
 
-    callbacks.transfer_progress(|stats| {
-        if stats.received_objects() == stats.total_objects() {
-            print!(
-                "\r解析对象: {}/{}",
-                stats.indexed_objects(),
-                stats.total_objects()
-            );
+    callbacks.transfer_progress(move |stats| {
+        // 对象接收完成后，进度改为报告已解析（indexed）的对象数
+        let current = if stats.received_objects() == stats.total_objects() {
+            stats.indexed_objects()
         } else {
-            print!(
-                "\r接收对象: {}/{} ({:.2} MB)",
-                stats.received_objects(),
-                stats.total_objects(),
-                stats.received_bytes() as f64 / 1024.0 / 1024.0
-            );
-        }
-        std::io::stdout().flush().unwrap();
-        true
+            stats.received_objects()
+        };
+        progress.report(Progress {
+            phase,
+            current,
+            total: stats.total_objects(),
+            received_bytes: stats.received_bytes(),
+        });
+        // 返回 false 会让 git2 中止本次传输
+        !cancel.is_cancelled()
     });
 
-    let mut fetch_options = FetchOptions::new();
-    fetch_options.remote_callbacks(callbacks);
+    callbacks
+}
 
-    // 使用带有回调的 fetch_options
-    remote.fetch::<&str>(&[], Some(&mut fetch_options), None)?;
-    println!(); // fetch 进度打印完成后换行
+/// libgit2 在认证失败后会反复请求凭据，超过次数后放弃
+const MAX_CREDENTIAL_ATTEMPTS: usize = 3;
 
-    Ok(())
+/// 认证回调：优先使用镜像配置的用户名和密码，其次是 git 的 credential helper 和 SSH agent
+fn credentials(
+    mirror: &Mirror,
+) -> impl FnMut(&str, Option<&str>, CredentialType) -> Result<Cred, git2::Error> + '_ {
+    let mut attempts = 0;
+    move |url, username_from_url, allowed| {
+        attempts += 1;
+        if attempts > MAX_CREDENTIAL_ATTEMPTS {
+            return Err(git2::Error::from_str(&format!(
+                "authentication failed for {}",
+                url
+            )));
+        }
+
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            if let (Some(username), Some(password)) = (&mirror.username, &mirror.password) {
+                return Cred::userpass_plaintext(username, password);
+            }
+            if let Ok(git_config) = git2::Config::open_default() {
+                if let Ok(cred) = Cred::credential_helper(&git_config, url, username_from_url) {
+                    return Ok(cred);
+                }
+            }
+        }
+        if allowed.contains(CredentialType::SSH_KEY) {
+            if let Some(username) = username_from_url {
+                return Cred::ssh_key_from_agent(username);
+            }
+        }
+        Cred::default()
+    }
 }
 
-fn get_fetch_head(
+/// 强制检出的选项；稀疏仓库只检出页面文件
+fn checkout_builder<'a>(
     repo: &Repository,
-) -> Result<((git2::MergeAnalysis, git2::MergePreference), git2::Oid), git2::Error> {
+    progress: &'a dyn ProgressSink,
+) -> Result<CheckoutBuilder<'a>, git2::Error> {
+    let mut builder = CheckoutBuilder::new();
+    builder.progress(checkout_progress(progress)).force();
+    if is_sparse(repo) {
+        builder.path(SPARSE_PATHSPEC);
+    }
+    if !has_worktree(repo) {
+        builder.dry_run();
+    }
+    Ok(builder)
+}
+
+/// 检出进度的回调，每 1000 步报告一次
+fn checkout_progress(progress: &dyn ProgressSink) -> impl FnMut(Option<&Path>, usize, usize) + '_ {
+    let mut last_reported = 0;
+    move |_path, completed_steps, total_steps| {
+        if total_steps > 0
+            && (completed_steps - last_reported >= 1000 || completed_steps == total_steps)
+        {
+            progress.report(Progress::new(Phase::Checkout, completed_steps, total_steps));
+            last_reported = completed_steps;
+        }
+    }
+}
+
+fn get_fetch_head(repo: &Repository) -> Result<git2::Oid, git2::Error> {
     let fetch_head = repo.find_reference("FETCH_HEAD")?;
-    let oid = fetch_head.target().ok_or_else(|| {
+    fetch_head.target().ok_or_else(|| {
         git2::Error::new(
             git2::ErrorCode::NotFound,
             git2::ErrorClass::Reference,
             "FETCH_HEAD 没有目标 OID",
         )
-    })?;
+    })
+}
+
+/// fetch 之后应当检出的提交：配置了 `pin` 时为固定的版本，否则为 FETCH_HEAD
+fn target_commit(repo: &Repository, config: &MirrorConfig) -> Result<Oid, git2::Error> {
+    let fetch_head = get_fetch_head(repo)?;
+    match &config.pin {
+        Some(revision) => {
+            let oid = resolve_revision_from(repo, revision, fetch_head)?;
+            println!("镜像固定在 {} ({})", revision, oid);
+            Ok(oid)
+        }
+        None => Ok(fetch_head),
+    }
+}
+
+/// 克隆后把仓库重置到固定的版本
+fn apply_pin(
+    repo: &Repository,
+    config: &MirrorConfig,
+    progress: &dyn ProgressSink,
+) -> Result<(), git2::Error> {
+    let Some(revision) = &config.pin else {
+        return Ok(());
+    };
+    let head = repo.refname_to_id("HEAD")?;
+    let oid = resolve_revision_from(repo, revision, head)?;
+    if oid != head {
+        println!("镜像固定在 {} ({})", revision, oid);
+        hard_reset(repo, oid, progress)?;
+    }
+    Ok(())
+}
+
+/// 把提交号、标签、分支或日期（`YYYY-MM-DD`）解析为提交
+///
+/// 日期表示当天结束（UTC）时 HEAD 历史上最新的提交
+pub fn resolve_revision(path: &Path, revision: &str) -> Result<Oid, git2::Error> {
+    let repo = Repository::open(path)?;
+    let head = repo.refname_to_id("HEAD")?;
+    resolve_revision_from(&repo, revision, head)
+}
+
+fn resolve_revision_from(repo: &Repository, revision: &str, tip: Oid) -> Result<Oid, git2::Error> {
+    let Some(before) = parse_date(revision) else {
+        return Ok(repo.revparse_single(revision)?.peel_to_commit()?.id());
+    };
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(tip)?;
+    revwalk.set_sorting(git2::Sort::TIME)?;
+    for oid in revwalk {
+        let oid = oid?;
+        if repo.find_commit(oid)?.time().seconds() <= before {
+            return Ok(oid);
+        }
+    }
+
+    Err(git2::Error::new(
+        git2::ErrorCode::NotFound,
+        git2::ErrorClass::Reference,
+        format!("no commit on or before {}", revision),
+    ))
+}
 
-    let remote_commit = repo.find_annotated_commit(oid)?;
-    Ok((repo.merge_analysis(&[&remote_commit])?, oid))
+/// 当前 HEAD 指向的提交
+pub fn head_commit(path: &Path) -> Result<Oid, git2::Error> {
+    Repository::open(path)?.refname_to_id("HEAD")
+}
+
+/// 最近一次 fetch 得到的远程提交，以及它比 `commit` 多出的提交数；从未 fetch 过时返回 `None`
+pub fn remote_ahead(path: &Path, commit: Oid) -> Result<Option<(Oid, usize)>, git2::Error> {
+    let repo = Repository::open(path)?;
+    let Ok(remote) = get_fetch_head(&repo) else {
+        return Ok(None);
+    };
+    let (ahead, _behind) = repo.graph_ahead_behind(remote, commit)?;
+    Ok(Some((remote, ahead)))
+}
+
+/// 把 Unix 时间戳格式化为 UTC 的 `YYYY-MM-DD`
+pub fn format_date(timestamp: i64) -> String {
+    // Howard Hinnant 的 civil_from_days，`parse_date` 的逆运算
+    let z = timestamp.div_euclid(86400) + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// 把 `YYYY-MM-DD` 解析为当天最后一秒（UTC）的 Unix 时间戳
+fn parse_date(date: &str) -> Option<i64> {
+    let mut parts = date.splitn(3, '-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    let year: i64 = year.parse().ok()?;
+    let month: i64 = month.parse().ok()?;
+    let day: i64 = day.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // 公历日期到 1970-01-01 的天数（Howard Hinnant 的 days_from_civil）
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    Some(days * 86400 + 86399)
 }
 
 fn fast_forward(
     repo: &Repository,
     reference: &mut git2::Reference,
     oid: git2::Oid,
+    progress: &dyn ProgressSink,
 ) -> Result<(), git2::Error> {
     // 获取该引用的名称，用于后续操作
     let ref_name = reference
@@ -186,8 +1387,698 @@ fn fast_forward(
 
     // 更新 HEAD 指向，并检出工作目录以匹配
     repo.set_head(&ref_name)?;
-    repo.checkout_head(Some(CheckoutBuilder::new().force()))?;
+    repo.checkout_head(Some(&mut checkout_builder(repo, progress)?))?;
 
     println!("更新完成。");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn write_file(repo_path: &Path, relative: &str, content: &str) {
+        let path = repo_path.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    // 将工作目录的全部内容提交，返回新提交的 OID
+    fn commit_all(repo: &Repository, message: &str) -> Oid {
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.update_all(["*"].iter(), None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let parents: Vec<git2::Commit> = repo
+            .head()
+            .ok()
+            .and_then(|head| head.peel_to_commit().ok())
+            .into_iter()
+            .collect();
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_reset_head_rolls_back_working_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo_path = temp_dir.path();
+        let repo = Repository::init(repo_path).unwrap();
+
+        write_file(repo_path, "pages/1/1/content.html", "old");
+        let old = commit_all(&repo, "initial");
+        write_file(repo_path, "pages/1/1/content.html", "new");
+        commit_all(&repo, "update");
+
+        reset_head(repo_path, old).unwrap();
+
+        assert_eq!(repo.head().unwrap().target(), Some(old));
+        let content = fs::read_to_string(repo_path.join("pages/1/1/content.html")).unwrap();
+        assert_eq!(content, "old");
+    }
+
+    fn mirror_config(urls: &[&str]) -> MirrorConfig {
+        MirrorConfig {
+            mirrors: urls.iter().map(|url| Mirror::new(url)).collect(),
+            proxy: None,
+            shallow: false,
+            pin: None,
+        }
+    }
+
+    // 创建一个带有一次提交的上游仓库，并克隆到 `local`
+    fn upstream_and_clone(temp_dir: &TempDir) -> (Repository, Repository) {
+        let upstream_path = temp_dir.path().join("upstream");
+        let upstream = Repository::init(&upstream_path).unwrap();
+        write_file(&upstream_path, "pages/1/1/content.html", "one");
+        write_file(&upstream_path, "pages/2/2/content.html", "two");
+        commit_all(&upstream, "initial");
+
+        let local = Repository::clone(
+            upstream_path.to_str().unwrap(),
+            temp_dir.path().join("local"),
+        )
+        .unwrap();
+        (upstream, local)
+    }
+
+    #[test]
+    fn test_update_resets_diverged_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let (upstream, local) = upstream_and_clone(&temp_dir);
+        let upstream_path = upstream.workdir().unwrap().to_path_buf();
+        let local_path = local.workdir().unwrap().to_path_buf();
+        let old_head = local.head().unwrap().target().unwrap();
+
+        // 上游改写历史：丢弃原提交，在空历史上重新提交（相当于 force-push）
+        fs::remove_file(upstream_path.join("pages/2/2/content.html")).unwrap();
+        write_file(&upstream_path, "pages/1/1/content.html", "one, rewritten");
+        upstream
+            .head()
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .delete()
+            .unwrap();
+        let new_head = commit_all(&upstream, "rewritten");
+
+        let config = mirror_config(&[upstream_path.to_str().unwrap()]);
+        let report =
+            update_local_repository(&local_path, &config, &NoProgress, &CancelToken::new())
+                .unwrap();
+
+        match report.update {
+            RepoUpdate::Reset {
+                old_head: reported_old,
+                new_head: reported_new,
+                changes,
+            } => {
+                assert_eq!(reported_old, old_head);
+                assert_eq!(reported_new, new_head);
+                assert_eq!(changes.modified, vec!["pages/1/1/content.html"]);
+                assert_eq!(changes.deleted, vec!["pages/2/2/content.html"]);
+            }
+            other => panic!("expected Reset, got {:?}", other),
+        }
+        assert_eq!(local.head().unwrap().target(), Some(new_head));
+        let content = fs::read_to_string(local_path.join("pages/1/1/content.html")).unwrap();
+        assert_eq!(content, "one, rewritten");
+    }
+
+    #[test]
+    fn test_gc_and_worktree_removal_keep_updates_working() {
+        let temp_dir = TempDir::new().unwrap();
+        let (upstream, local) = upstream_and_clone(&temp_dir);
+        let upstream_path = upstream.workdir().unwrap().to_path_buf();
+        let local_path = local.workdir().unwrap().to_path_buf();
+        let objects_dir = local.path().join("objects");
+        drop(local);
+        let config = mirror_config(&[upstream_path.to_str().unwrap()]);
+
+        // fetch 会留下松散对象或新的包
+        write_file(&upstream_path, "pages/3/3/content.html", "three");
+        commit_all(&upstream, "add three");
+        update_local_repository(&local_path, &config, &NoProgress, &CancelToken::new()).unwrap();
+
+        let object_count = gc_repository(&local_path, &NoProgress, &CancelToken::new()).unwrap();
+        assert!(object_count > 0);
+        let names: Vec<String> = fs::read_dir(&objects_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert!(names.iter().all(|name| name.len() != 2), "{:?}", names);
+        let packs = fs::read_dir(objects_dir.join("pack"))
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().extension() == Some("pack".as_ref()))
+            .count();
+        assert_eq!(packs, 1);
+
+        remove_worktree(&local_path).unwrap();
+        assert!(!local_path.join("pages").exists());
+        assert!(local_path.join(".git").exists());
+
+        // 没有工作目录时更新只移动 HEAD
+        write_file(&upstream_path, "pages/1/1/content.html", "one, edited");
+        let new_head = commit_all(&upstream, "edit one");
+        let report =
+            update_local_repository(&local_path, &config, &NoProgress, &CancelToken::new())
+                .unwrap();
+        assert!(matches!(report.update, RepoUpdate::FastForwarded { .. }));
+        assert_eq!(head_commit(&local_path).unwrap(), new_head);
+        assert!(!local_path.join("pages").exists());
+
+        restore_worktree(&local_path, &NoProgress).unwrap();
+        let content = fs::read_to_string(local_path.join("pages/1/1/content.html")).unwrap();
+        assert_eq!(content, "one, edited");
+    }
+
+    #[test]
+    fn test_update_repairs_repository_without_head() {
+        let temp_dir = TempDir::new().unwrap();
+        let (upstream, _) = upstream_and_clone(&temp_dir);
+        let upstream_path = upstream.workdir().unwrap().to_path_buf();
+
+        // 模拟中断的克隆：只有 origin 远程，没有任何提交
+        let broken_path = temp_dir.path().join("broken");
+        let broken = Repository::init(&broken_path).unwrap();
+        broken
+            .remote("origin", upstream_path.to_str().unwrap())
+            .unwrap();
+
+        let config = mirror_config(&[upstream_path.to_str().unwrap()]);
+        let report =
+            update_local_repository(&broken_path, &config, &NoProgress, &CancelToken::new())
+                .unwrap();
+
+        assert_eq!(report.update, RepoUpdate::Repaired);
+        assert!(report.update.needs_full_reindex());
+        assert_eq!(
+            broken.head().unwrap().target(),
+            upstream.head().unwrap().target()
+        );
+        assert!(broken_path.join("pages/2/2/content.html").is_file());
+    }
+
+    #[test]
+    fn test_update_falls_back_to_next_mirror() {
+        let temp_dir = TempDir::new().unwrap();
+        let (upstream, _) = upstream_and_clone(&temp_dir);
+
+        // 本地 file:// 裸仓库镜像，例如 NAS 上的副本
+        let bare_path = temp_dir.path().join("mirror.git");
+        RepoBuilder::new()
+            .bare(true)
+            .clone(upstream.path().to_str().unwrap(), &bare_path)
+            .unwrap();
+        let missing_url = format!("file://{}", temp_dir.path().join("missing.git").display());
+        let bare_url = format!("file://{}", bare_path.display());
+        let config = mirror_config(&[&missing_url, &bare_url]);
+
+        let local_path = temp_dir.path().join("from_mirror");
+        let report =
+            update_local_repository(&local_path, &config, &NoProgress, &CancelToken::new())
+                .unwrap();
+        assert_eq!(report.update, RepoUpdate::Cloned);
+        assert_eq!(report.mirror, bare_url);
+        assert!(local_path.join("pages/1/1/content.html").is_file());
+
+        let report =
+            update_local_repository(&local_path, &config, &NoProgress, &CancelToken::new())
+                .unwrap();
+        assert_eq!(report.update, RepoUpdate::UpToDate);
+        assert_eq!(report.mirror, bare_url);
+    }
+
+    #[test]
+    fn test_sparse_clone_checks_out_only_pages() {
+        let temp_dir = TempDir::new().unwrap();
+        let (upstream, _) = upstream_and_clone(&temp_dir);
+        let upstream_path = upstream.workdir().unwrap().to_path_buf();
+        write_file(&upstream_path, "README.md", "readme");
+        write_file(&upstream_path, "pages/1/1/source.md", "source");
+        commit_all(&upstream, "add non-page files");
+
+        let mut config = mirror_config(&[upstream_path.to_str().unwrap()]);
+        config.shallow = true;
+        let local_path = temp_dir.path().join("sparse");
+        let report =
+            update_local_repository(&local_path, &config, &NoProgress, &CancelToken::new())
+                .unwrap();
+        assert_eq!(report.update, RepoUpdate::Cloned);
+        assert!(local_path.join("pages/1/1/content.html").is_file());
+        assert!(!local_path.join("README.md").exists());
+        assert!(!local_path.join("pages/1/1/source.md").exists());
+
+        // 之后的快进仍然只检出页面文件
+        write_file(&upstream_path, "pages/3/3/content.html", "three");
+        write_file(&upstream_path, "pages/3/3/source.md", "source");
+        commit_all(&upstream, "add page three");
+        let report =
+            update_local_repository(&local_path, &config, &NoProgress, &CancelToken::new())
+                .unwrap();
+
+        match report.update {
+            RepoUpdate::FastForwarded { changes, .. } => {
+                assert_eq!(changes.added, vec!["pages/3/3/content.html"]);
+            }
+            other => panic!("expected FastForwarded, got {:?}", other),
+        }
+        assert!(local_path.join("pages/3/3/content.html").is_file());
+        assert!(!local_path.join("pages/3/3/source.md").exists());
+    }
+
+    // 记录 `message` 收到的说明
+    #[derive(Default)]
+    struct Messages(std::sync::Mutex<Vec<String>>);
+
+    impl ProgressSink for Messages {
+        fn report(&self, _progress: Progress) {}
+
+        fn message(&self, message: &str) {
+            self.0.lock().unwrap().push(message.to_string());
+        }
+    }
+
+    #[test]
+    fn test_shallow_clone_from_local_mirror_fetches_full_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let (upstream, _) = upstream_and_clone(&temp_dir);
+        let upstream_path = upstream.workdir().unwrap().to_path_buf();
+        write_file(&upstream_path, "pages/1/1/content.html", "one, edited");
+        commit_all(&upstream, "edit one");
+
+        let mut config = mirror_config(&[upstream_path.to_str().unwrap()]);
+        config.shallow = true;
+        let local_path = temp_dir.path().join("shallow");
+        let messages = Messages::default();
+        let report =
+            update_local_repository(&local_path, &config, &messages, &CancelToken::new()).unwrap();
+
+        assert_eq!(report.update, RepoUpdate::Cloned);
+        assert_eq!(messages.0.lock().unwrap().len(), 1);
+        let local = Repository::open(&local_path).unwrap();
+        assert!(!local.is_shallow());
+        assert_eq!(
+            local
+                .head()
+                .unwrap()
+                .peel_to_commit()
+                .unwrap()
+                .parent_count(),
+            1
+        );
+    }
+
+    // 只有 `upstream` 的 HEAD 提交及其树的仓库，相当于深度为 1 的浅克隆
+    fn shallow_clone(upstream: &Repository, path: &Path) -> Repository {
+        let repo = Repository::init(path).unwrap();
+        let head = upstream.head().unwrap();
+        let commit = head.peel_to_commit().unwrap();
+        {
+            let source = upstream.odb().unwrap();
+            let target = repo.odb().unwrap();
+            let mut oids = vec![commit.id(), commit.tree_id()];
+            commit
+                .tree()
+                .unwrap()
+                .walk(git2::TreeWalkMode::PreOrder, |_, entry| {
+                    oids.push(entry.id());
+                    git2::TreeWalkResult::Ok
+                })
+                .unwrap();
+            for oid in oids {
+                let object = source.read(oid).unwrap();
+                target.write(object.kind(), object.data()).unwrap();
+            }
+        }
+        fs::write(repo.path().join("shallow"), format!("{}\n", commit.id())).unwrap();
+        repo.remote("origin", upstream.workdir().unwrap().to_str().unwrap())
+            .unwrap();
+        repo.reference(head.name().unwrap(), commit.id(), true, "shallow clone")
+            .unwrap();
+        repo.set_head(head.name().unwrap()).unwrap();
+        repo.checkout_head(Some(CheckoutBuilder::new().force()))
+            .unwrap();
+        repo
+    }
+
+    #[test]
+    fn test_shallow_repository_updates() {
+        let temp_dir = TempDir::new().unwrap();
+        let (upstream, _) = upstream_and_clone(&temp_dir);
+        let upstream_path = upstream.workdir().unwrap().to_path_buf();
+        write_file(&upstream_path, "pages/1/1/content.html", "one, edited");
+        commit_all(&upstream, "edit one");
+
+        let local_path = temp_dir.path().join("shallow");
+        let local = shallow_clone(&upstream, &local_path);
+        assert!(local.is_shallow());
+        let mut config = mirror_config(&[upstream_path.to_str().unwrap()]);
+        config.shallow = true;
+
+        // 之后的获取快进，浅克隆的边界保持不变
+        write_file(&upstream_path, "pages/3/3/content.html", "three");
+        let fast_forward = commit_all(&upstream, "add page three");
+        let report =
+            update_local_repository(&local_path, &config, &NoProgress, &CancelToken::new())
+                .unwrap();
+        match report.update {
+            RepoUpdate::FastForwarded { changes, .. } => {
+                assert_eq!(changes.added, vec!["pages/3/3/content.html"]);
+            }
+            other => panic!("expected FastForwarded, got {:?}", other),
+        }
+        assert_eq!(head_commit(&local_path).unwrap(), fast_forward);
+        assert!(local_path.join("pages/3/3/content.html").is_file());
+        assert!(local.is_shallow());
+        let mut revwalk = local.revwalk().unwrap();
+        revwalk.push_head().unwrap();
+        assert_eq!(revwalk.count(), 2);
+
+        // 上游改写历史后重置到新的提交
+        fs::remove_file(upstream_path.join("pages/2/2/content.html")).unwrap();
+        upstream
+            .head()
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .delete()
+            .unwrap();
+        let rewritten = commit_all(&upstream, "rewritten");
+        let report =
+            update_local_repository(&local_path, &config, &NoProgress, &CancelToken::new())
+                .unwrap();
+        match report.update {
+            RepoUpdate::Reset { changes, .. } => {
+                assert_eq!(changes.deleted, vec!["pages/2/2/content.html"]);
+            }
+            other => panic!("expected Reset, got {:?}", other),
+        }
+        assert_eq!(head_commit(&local_path).unwrap(), rewritten);
+        assert!(local.is_shallow());
+    }
+
+    #[test]
+    fn test_shallow_repository_without_head_is_repaired() {
+        let temp_dir = TempDir::new().unwrap();
+        let (upstream, _) = upstream_and_clone(&temp_dir);
+        let upstream_path = upstream.workdir().unwrap().to_path_buf();
+        write_file(&upstream_path, "pages/1/1/content.html", "one, edited");
+        let upstream_head = commit_all(&upstream, "edit one");
+
+        // 中断的浅克隆：已写入边界，但 HEAD 还没有提交
+        let broken_path = temp_dir.path().join("broken");
+        let broken = shallow_clone(&upstream, &broken_path);
+        let branch = broken.head().unwrap().name().unwrap().to_string();
+        broken.find_reference(&branch).unwrap().delete().unwrap();
+
+        let mut config = mirror_config(&[upstream_path.to_str().unwrap()]);
+        config.shallow = true;
+        let report =
+            update_local_repository(&broken_path, &config, &NoProgress, &CancelToken::new())
+                .unwrap();
+        assert_eq!(report.update, RepoUpdate::Repaired);
+        assert_eq!(head_commit(&broken_path).unwrap(), upstream_head);
+        assert!(broken.is_shallow());
+        assert!(broken_path.join("pages/2/2/content.html").is_file());
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("1970-01-01"), Some(86399));
+        assert_eq!(parse_date("2021-06-01"), Some(1622591999));
+        assert_eq!(parse_date("2021-6-1"), None);
+        assert_eq!(parse_date("2021-13-01"), None);
+        assert_eq!(parse_date("HEAD~1"), None);
+
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(1622591999), "2021-06-01");
+        assert_eq!(format_date(parse_date("2000-02-29").unwrap()), "2000-02-29");
+    }
+
+    #[test]
+    fn test_update_follows_pinned_revision() {
+        let temp_dir = TempDir::new().unwrap();
+        let (upstream, local) = upstream_and_clone(&temp_dir);
+        let upstream_path = upstream.workdir().unwrap().to_path_buf();
+        let local_path = local.workdir().unwrap().to_path_buf();
+        let first = local.head().unwrap().target().unwrap();
+
+        write_file(&upstream_path, "pages/3/3/content.html", "three");
+        commit_all(&upstream, "add page three");
+        let mut config = mirror_config(&[upstream_path.to_str().unwrap()]);
+        update_local_repository(&local_path, &config, &NoProgress, &CancelToken::new()).unwrap();
+
+        // 固定到更早的提交：重置回去并报告页面被删除
+        config.pin = Some(first.to_string());
+        let report =
+            update_local_repository(&local_path, &config, &NoProgress, &CancelToken::new())
+                .unwrap();
+        match report.update {
+            RepoUpdate::Reset { changes, .. } => {
+                assert_eq!(changes.deleted, vec!["pages/3/3/content.html"]);
+            }
+            other => panic!("expected Reset, got {:?}", other),
+        }
+        assert!(!local_path.join("pages/3/3/content.html").exists());
+
+        let report =
+            update_local_repository(&local_path, &config, &NoProgress, &CancelToken::new())
+                .unwrap();
+        assert_eq!(report.update, RepoUpdate::UpToDate);
+
+        // 远程比固定的版本多一个提交
+        let (_, ahead) = remote_ahead(&local_path, first).unwrap().unwrap();
+        assert_eq!(ahead, 1);
+
+        // 日期早于所有提交时找不到版本
+        assert!(resolve_revision(&local_path, "1999-01-01").is_err());
+        assert_eq!(resolve_revision(&local_path, "2999-01-01").unwrap(), first);
+    }
+
+    fn page_html(content: &str) -> String {
+        format!(
+            r#"<div id="revision"><p>{}</p><p>Related concepts</p></div>"#,
+            content
+        )
+    }
+
+    #[test]
+    fn test_page_history_and_diff() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo_path = temp_dir.path();
+        let repo = Repository::init(repo_path).unwrap();
+        let page = "pages/1/1/content.html";
+
+        write_file(repo_path, page, &page_html("A topos is a category."));
+        let first = commit_all(&repo, "create topos");
+        write_file(repo_path, "pages/2/2/content.html", "other");
+        commit_all(&repo, "create other page");
+        write_file(repo_path, page, &page_html("A topos is a nice category."));
+        let second = commit_all(&repo, "edit topos\n\ndetails");
+
+        let history = page_history(repo_path, page, 10).unwrap();
+        let commits: Vec<&str> = history.iter().map(|r| r.commit.as_str()).collect();
+        assert_eq!(commits, vec![second.to_string(), first.to_string()]);
+        assert_eq!(history[0].summary, "edit topos");
+        assert_eq!(page_history(repo_path, page, 1).unwrap().len(), 1);
+
+        let diff = page_diff(repo_path, page, &first.to_string(), &second.to_string()).unwrap();
+        let changed: Vec<(DiffLineKind, &str)> = diff
+            .iter()
+            .filter(|line| line.kind != DiffLineKind::Hunk)
+            .map(|line| (line.kind, line.text.as_str()))
+            .collect();
+        assert_eq!(
+            changed,
+            vec![
+                (DiffLineKind::Removed, "A topos is a category."),
+                (DiffLineKind::Added, "A topos is a nice category."),
+                (DiffLineKind::Context, "Related concepts"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_preview_update_does_not_move_head() {
+        let temp_dir = TempDir::new().unwrap();
+        let (upstream, local) = upstream_and_clone(&temp_dir);
+        let upstream_path = upstream.workdir().unwrap().to_path_buf();
+        let local_path = local.workdir().unwrap().to_path_buf();
+        let old_head = local.head().unwrap().target().unwrap();
+
+        write_file(
+            &upstream_path,
+            "pages/3/3/content.html",
+            r#"<h1 id="pageName">sheaf</h1><a id="edit" href="/nlab/edit/sheaf">Edit</a>"#,
+        );
+        fs::remove_file(upstream_path.join("pages/2/2/content.html")).unwrap();
+        let new_head = commit_all(&upstream, "add sheaf, remove two");
+
+        let config = mirror_config(&[upstream_path.to_str().unwrap()]);
+        let preview =
+            preview_update(&local_path, &config, &NoProgress, &CancelToken::new()).unwrap();
+
+        assert_eq!(preview.head, old_head.to_string());
+        assert_eq!(preview.target, new_head.to_string());
+        let pages: Vec<(ChangeKind, &str, &str)> = preview
+            .pages
+            .iter()
+            .map(|page| (page.kind, page.file_path.as_str(), page.title.as_str()))
+            .collect();
+        assert_eq!(
+            pages,
+            vec![
+                (ChangeKind::Added, "pages/3/3/content.html", "sheaf"),
+                // 测试页面没有编辑链接，无法解析出标题
+                (ChangeKind::Deleted, "pages/2/2/content.html", ""),
+            ]
+        );
+        assert_eq!(local.head().unwrap().target(), Some(old_head));
+        assert!(!local_path.join("pages/3/3/content.html").exists());
+    }
+
+    #[test]
+    fn test_bundle_clone_and_incremental_update() {
+        let temp_dir = TempDir::new().unwrap();
+        let upstream_path = temp_dir.path().join("upstream");
+        let upstream = Repository::init(&upstream_path).unwrap();
+        write_file(&upstream_path, "pages/1/1/content.html", "one");
+        let first = commit_all(&upstream, "initial");
+
+        let full = temp_dir.path().join("full.bundle");
+        let summary = export_bundle(
+            &upstream_path,
+            None,
+            &full,
+            &NoProgress,
+            &CancelToken::new(),
+        )
+        .unwrap();
+        assert_eq!(summary.tip, first.to_string());
+        assert!(summary.since.is_none());
+
+        // 增量 bundle 不能用于首次克隆
+        write_file(&upstream_path, "pages/2/2/content.html", "two");
+        let second = commit_all(&upstream, "add page two");
+        let incremental = temp_dir.path().join("incremental.bundle");
+        let summary = export_bundle(
+            &upstream_path,
+            Some(&first.to_string()),
+            &incremental,
+            &NoProgress,
+            &CancelToken::new(),
+        )
+        .unwrap();
+        assert_eq!(summary.since, Some(first.to_string()));
+
+        let local_path = temp_dir.path().join("local");
+        let config = mirror_config(&[incremental.to_str().unwrap()]);
+        let err = update_local_repository(&local_path, &config, &NoProgress, &CancelToken::new())
+            .unwrap_err();
+        assert!(err.message().contains(&first.to_string()));
+        assert!(!local_path.exists());
+
+        let config = mirror_config(&[full.to_str().unwrap()]);
+        let report =
+            update_local_repository(&local_path, &config, &NoProgress, &CancelToken::new())
+                .unwrap();
+        assert_eq!(report.update, RepoUpdate::Cloned);
+        assert_eq!(head_commit(&local_path).unwrap(), first);
+        assert!(local_path.join("pages/1/1/content.html").is_file());
+
+        let config = mirror_config(&[incremental.to_str().unwrap()]);
+        let report =
+            update_local_repository(&local_path, &config, &NoProgress, &CancelToken::new())
+                .unwrap();
+        match report.update {
+            RepoUpdate::FastForwarded {
+                new_head, changes, ..
+            } => {
+                assert_eq!(new_head, second);
+                assert_eq!(changes.added, vec!["pages/2/2/content.html"]);
+            }
+            other => panic!("expected FastForwarded, got {:?}", other),
+        }
+        assert_eq!(report.mirror, incremental.to_str().unwrap());
+        assert!(export_bundle(
+            &local_path,
+            Some("HEAD"),
+            &temp_dir.path().join("empty.bundle"),
+            &NoProgress,
+            &CancelToken::new()
+        )
+        .is_err());
+        assert!(!temp_dir.path().join("empty.bundle").exists());
+    }
+
+    #[test]
+    fn test_clone_reports_all_failed_mirrors() {
+        let temp_dir = TempDir::new().unwrap();
+        let first = format!("file://{}", temp_dir.path().join("a.git").display());
+        let second = format!("file://{}", temp_dir.path().join("b.git").display());
+        let local_path = temp_dir.path().join("local");
+
+        let error = clone_with_progress(
+            &mirror_config(&[&first, &second]),
+            &local_path,
+            &NoProgress,
+            &CancelToken::new(),
+        )
+        .err()
+        .unwrap();
+
+        assert!(error.message().starts_with("all mirrors failed"));
+        assert!(error.message().contains(&first) && error.message().contains(&second));
+        assert!(!local_path.exists());
+    }
+
+    #[test]
+    fn test_is_page_file() {
+        assert!(is_page_file("pages/4/7/4/1/1474/content.html"));
+        assert!(!is_page_file("pages/4/7/4/1/1474/source.md"));
+        assert!(!is_page_file("pagesx/1/content.html"));
+        assert!(!is_page_file("README.html"));
+    }
+
+    #[test]
+    fn test_diff_pages_reports_added_modified_deleted() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo_path = temp_dir.path();
+        let repo = Repository::init(repo_path).unwrap();
+
+        write_file(repo_path, "pages/1/1/content.html", "<h1>one</h1>");
+        write_file(repo_path, "pages/2/2/content.html", "<h1>two</h1>");
+        write_file(repo_path, "README.md", "readme");
+        let old = commit_all(&repo, "initial");
+
+        write_file(repo_path, "pages/1/1/content.html", "<h1>one, edited</h1>");
+        fs::remove_file(repo_path.join("pages/2/2/content.html")).unwrap();
+        write_file(repo_path, "pages/3/3/content.html", "<h1>three</h1>");
+        write_file(repo_path, "README.md", "readme, edited");
+        let new = commit_all(&repo, "update");
+
+        let changes = diff_pages(&repo, old, new).unwrap();
+        assert_eq!(changes.added, vec!["pages/3/3/content.html"]);
+        assert_eq!(changes.modified, vec!["pages/1/1/content.html"]);
+        assert_eq!(changes.deleted, vec!["pages/2/2/content.html"]);
+        assert_eq!(changes.len(), 3);
+
+        let unchanged = diff_pages(&repo, new, new).unwrap();
+        assert!(unchanged.is_empty());
+    }
+}
@@ -1,31 +1,119 @@
 
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
-use crate::parser::index_local_files;
+use crate::cancel::CancelToken;
+use crate::data_dir::DataDir;
+use crate::git_ops::{PageChanges, RepoUpdate};
+use crate::parser::index_commit_files;
+use crate::progress::{Progress, ProgressSink, Throttled};
 use crate::{git_ops::update_local_repository, models::SearchIndex, search::SearchEngine};
-use tauri::{Emitter, State};
+use tauri::{Emitter, Manager, State};
 
 pub const REPO_URL: &str = "https://github.com/ncatlab/nlab-content-html.git";
 pub const GIT_REPO_PATH: &str = "nlab_mirror";
 pub const DB_PATH: &str = "nlab_page_data.db";
 pub const INDEX_PATH: &str = "nlab_page_index";
+pub const CONFIG_PATH: &str = "nlab_config.json";
+pub const SNAPSHOTS_PATH: &str = "nlab_snapshots";
+/// 压缩包来源解压到的目录
+pub const SOURCE_PATH: &str = "nlab_source";
 
 mod browser;
+mod cancel;
+mod config;
+mod data_dir;
 mod git_ops;
+mod links;
+mod maintenance;
 mod models;
+mod onboarding;
 mod parser;
+mod progress;
+mod schedule;
 mod search;
+mod snapshot;
+mod source;
 mod storage;
+mod tex;
 
 pub struct AppStateInner {
     search_engine: Option<search::TantivySearch>,
     storage: Option<storage::Storage>,
+    // tantivy 同一时间只允许一个 IndexWriter，因此同步任务不能并发
+    syncing: AtomicBool,
+    // 当前同步或构建任务的取消标记，由 `cancel_operation` 触发
+    cancel: CancelToken,
+    // 已打开的历史快照，按提交号索引
+    snapshots: HashMap<String, snapshot::Snapshot>,
+    // 首次运行还没有选择数据目录和来源，`complete_setup` 之后才开始初始化
+    awaiting_setup: bool,
+    // 数据目录，完成首次运行的选择之前是启动时的工作目录
+    data_dir: DataDir,
 }
 
 type AppState = Arc<RwLock<AppStateInner>>;
 
+/// 同一阶段内两次 `progress` 事件的最小间隔
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 把进度以 `progress` 事件转发给前端
+struct ProgressEvents(tauri::AppHandle);
+
+impl ProgressSink for ProgressEvents {
+    fn report(&self, progress: Progress) {
+        let _ = self.0.emit("progress", progress);
+    }
+
+    fn message(&self, message: &str) {
+        let _ = self.0.emit("progress-message", message);
+    }
+}
+
+/// 按 `PROGRESS_INTERVAL` 节流后发送 `progress` 事件，并统计解析失败的文件
+struct EventProgress {
+    events: Throttled<ProgressEvents>,
+    // 解析失败的文件数，写入同步记录
+    skipped: AtomicUsize,
+}
+
+impl EventProgress {
+    fn new(app_handle: &tauri::AppHandle) -> Self {
+        Self {
+            events: Throttled::new(ProgressEvents(app_handle.clone()), PROGRESS_INTERVAL),
+            skipped: AtomicUsize::new(0),
+        }
+    }
+
+    fn skipped_count(&self) -> usize {
+        self.skipped.load(Ordering::Relaxed)
+    }
+}
+
+impl ProgressSink for EventProgress {
+    fn report(&self, progress: Progress) {
+        self.events.report(progress);
+    }
+
+    fn skipped(&self, _name: &str) {
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn message(&self, message: &str) {
+        self.events.message(message);
+    }
+}
+
+/// 搜索页面，`include` 和 `exclude` 按分类或领域筛选，见 `SearchFilters`
 #[tauri::command]
-fn get_search_results(state: State<AppState>, query: String) -> Result<Vec<SearchIndex>, String> {
+fn get_search_results(
+    state: State<AppState>,
+    query: String,
+    include: Option<Vec<search::FacetFilter>>,
+    exclude: Option<Vec<search::FacetFilter>>,
+) -> Result<Vec<SearchIndex>, String> {
     let state = state
         .read()
         .map_err(|e| format!("failed to lock state: {}", e))?;
@@ -39,20 +127,100 @@ fn get_search_results(state: State<AppState>, query: String) -> Result<Vec<Searc
         .as_ref()
         .ok_or_else(|| "storage is not initialized".to_string())?;
 
+    search_pages(
+        search_engine,
+        storage,
+        &query,
+        facet_filters(include, exclude),
+    )
+}
+
+/// 与 `get_search_results` 相同的查询和筛选条件下，各分类和领域的页面数
+#[tauri::command]
+fn get_facet_counts(
+    state: State<AppState>,
+    query: String,
+    include: Option<Vec<search::FacetFilter>>,
+    exclude: Option<Vec<search::FacetFilter>>,
+) -> Result<Vec<search::FacetCount>, String> {
+    let state = state
+        .read()
+        .map_err(|e| format!("failed to lock state: {}", e))?;
+
+    let search_engine = state
+        .search_engine
+        .as_ref()
+        .ok_or_else(|| "search engine is not initialized".to_string())?;
+
+    search_engine
+        .facet_counts(&query, &facet_filters(include, exclude))
+        .map_err(|e| format!("failed to count facets: {}", e))
+}
+
+fn facet_filters(
+    include: Option<Vec<search::FacetFilter>>,
+    exclude: Option<Vec<search::FacetFilter>>,
+) -> search::SearchFilters {
+    search::SearchFilters {
+        include: include.unwrap_or_default(),
+        exclude: exclude.unwrap_or_default(),
+        ..search::SearchFilters::default()
+    }
+}
+
+/// 在某个历史快照中搜索，快照需先通过 `build_snapshot` 打开
+#[tauri::command]
+fn get_snapshot_search_results(
+    state: State<AppState>,
+    commit: String,
+    query: String,
+    include: Option<Vec<search::FacetFilter>>,
+    exclude: Option<Vec<search::FacetFilter>>,
+) -> Result<Vec<SearchIndex>, String> {
+    let state = state
+        .read()
+        .map_err(|e| format!("failed to lock state: {}", e))?;
+
+    let snapshot = state
+        .snapshots
+        .get(&commit)
+        .ok_or_else(|| format!("snapshot {} is not loaded", commit))?;
+
+    search_pages(
+        &snapshot.search_engine,
+        &snapshot.storage,
+        &query,
+        facet_filters(include, exclude),
+    )
+}
+
+fn search_pages(
+    search_engine: &search::TantivySearch,
+    storage: &storage::Storage,
+    query: &str,
+    filters: search::SearchFilters,
+) -> Result<Vec<SearchIndex>, String> {
     let results = search_engine
-        .search(&query, 10)
+        .search_with_filters(query, 10, filters)
         .map_err(|e| format!("failed to search: {}", e))?;
 
     let search_results = results
         .into_iter()
         .filter_map(|res| {
             storage
-                .get_page(&res.id)
+                .get_page(res.id)
                 .ok()
                 .flatten()
                 .map(|page| SearchIndex {
-                    title: res.title,
-                    url: page.url,
+                    id: page.id,
+                    // 一节的文档不保存页面标题，因此标题总是取自存储
+                    title: page.title,
+                    url: match &res.anchor {
+                        Some(anchor) => format!("{}#{}", page.url, anchor),
+                        None => page.url,
+                    },
+                    file_path: page.file_path,
+                    section: res.section,
                 })
         })
         .collect();
@@ -60,40 +228,356 @@ fn get_search_results(state: State<AppState>, query: String) -> Result<Vec<Searc
     Ok(search_results)
 }
 
-#[cfg(feature = "ignore")]
+/// 在后台线程同步仓库并增量更新索引，进度通过 `sync-status` 事件发送
+///
+/// 同步期间搜索继续使用旧的索引，只有在提交时才切换到新数据
 #[tauri::command]
-fn sync_local_repo(state: State<AppState>) -> Result<(), String> {
-    use std::path::Path;
-    let path = Path::new(GIT_REPO_PATH);
-    
-    let mut state = state
-        .write()
-        .map_err(|e| format!("failed to lock state: {}", e))?;
+fn sync_local_repo(app_handle: tauri::AppHandle, state: State<AppState>) -> Result<(), String> {
+    let state = state.inner().clone();
+    try_start_sync(&state)?;
+
+    std::thread::spawn(move || {
+        sync_in_background(&app_handle, &state, None);
+    });
+
+    Ok(())
+}
+
+/// 从 bundle 文件更新镜像（没有网络的机器），之后与普通同步一样增量更新索引
+#[tauri::command]
+fn import_bundle(
+    app_handle: tauri::AppHandle,
+    state: State<AppState>,
+    bundle_path: String,
+) -> Result<(), String> {
+    if !git_ops::is_bundle(&bundle_path) {
+        return Err(format!("bundle not found: {}", bundle_path));
+    }
+    if !current_source(&current_data_dir(&state)?).is_git() {
+        return Err(
+            "bundles can only be imported when the git mirror is the page source".to_string(),
+        );
+    }
+    let state = state.inner().clone();
+    try_start_sync(&state)?;
+
+    std::thread::spawn(move || {
+        sync_in_background(&app_handle, &state, Some(&bundle_path));
+    });
+
+    Ok(())
+}
+
+/// 在后台把镜像导出为 bundle；给定 `since` 时只包含它之后的提交
+///
+/// 完成后发送 `bundle-exported` 事件，失败时发送 `bundle-export-error`
+#[tauri::command]
+fn export_bundle(
+    app_handle: tauri::AppHandle,
+    state: State<AppState>,
+    output: String,
+    since: Option<String>,
+) -> Result<(), String> {
+    let state = state.inner().clone();
+    let data_dir = current_data_dir(&state)?;
+    try_start_sync(&state)?;
+
+    std::thread::spawn(move || {
+        let _ = app_handle.emit("sync-status", format!("Exporting bundle to {}...", output));
+        let cancel = match state.read() {
+            Ok(state) => state.cancel.clone(),
+            Err(_) => return,
+        };
+
+        let progress = EventProgress::new(&app_handle);
+        let result = git_ops::export_bundle(
+            &data_dir.mirror(),
+            since.as_deref().filter(|since| !since.is_empty()),
+            std::path::Path::new(&output),
+            &progress,
+            &cancel,
+        );
+
+        match result {
+            Ok(summary) => {
+                let _ = app_handle.emit("bundle-exported", summary);
+            }
+            Err(_) if cancel.is_cancelled() => {
+                let _ = app_handle.emit("sync-cancelled", true);
+            }
+            Err(e) => {
+                eprintln!("failed to export bundle: {}", e);
+                let _ = app_handle.emit("bundle-export-error", format!("{}", e));
+            }
+        }
+
+        finish_sync(&state);
+    });
+
+    Ok(())
+}
+
+/// 在后台把页面之间的链接导出为 DOT 或 GraphML 文件
+///
+/// 完成后发送 `link-graph-exported` 事件，失败时发送 `link-graph-export-error`
+#[tauri::command]
+fn export_link_graph(
+    app_handle: tauri::AppHandle,
+    state: State<AppState>,
+    output: String,
+    format: links::GraphFormat,
+) -> Result<(), String> {
+    let state = state.inner().clone();
+    try_start_sync(&state)?;
+
+    std::thread::spawn(move || {
+        let _ = app_handle.emit(
+            "sync-status",
+            format!("Exporting link graph to {}...", output),
+        );
+        let cancel = match state.read() {
+            Ok(state) => state.cancel.clone(),
+            Err(_) => return,
+        };
+
+        let result = with_components(&state, |storage, _| {
+            Ok(links::export_graph(
+                storage,
+                format,
+                std::path::Path::new(&output),
+                &cancel,
+            )?)
+        });
+
+        match result {
+            Ok(summary) => {
+                let _ = app_handle.emit("link-graph-exported", summary);
+            }
+            Err(_) if cancel.is_cancelled() => {
+                let _ = app_handle.emit("sync-cancelled", true);
+            }
+            Err(e) => {
+                eprintln!("failed to export link graph: {}", e);
+                let _ = app_handle.emit("link-graph-export-error", format!("{}", e));
+            }
+        }
+
+        finish_sync(&state);
+    });
+
+    Ok(())
+}
+
+/// 在后台 fetch 并预览同步将带来的页面变化，不移动镜像 HEAD，也不修改索引
+///
+/// 结果通过 `sync-preview` 事件发送，失败时发送 `sync-preview-error`；确认后调用 `sync_local_repo` 应用
+#[tauri::command]
+fn preview_sync(app_handle: tauri::AppHandle, state: State<AppState>) -> Result<(), String> {
+    let state = state.inner().clone();
+    let data_dir = current_data_dir(&state)?;
+    try_start_sync(&state)?;
+
+    std::thread::spawn(move || {
+        let _ = app_handle.emit("sync-status", "Fetching updates for preview...");
+        let cancel = match state.read() {
+            Ok(state) => state.cancel.clone(),
+            Err(_) => return,
+        };
+
+        let progress = EventProgress::new(&app_handle);
+        let result = config::Config::load(&data_dir.config())
+            .map_err(|e| e.to_string())
+            .and_then(|config| {
+                git_ops::preview_update(&data_dir.mirror(), &config.mirror, &progress, &cancel)
+                    .map_err(|e| e.to_string())
+            });
+
+        match result {
+            Ok(preview) => {
+                let _ = app_handle.emit("sync-preview", preview);
+            }
+            Err(_) if cancel.is_cancelled() => {
+                let _ = app_handle.emit("sync-cancelled", true);
+            }
+            Err(e) => {
+                eprintln!("failed to preview sync: {}", e);
+                let _ = app_handle.emit("sync-preview-error", e);
+            }
+        }
+
+        finish_sync(&state);
+    });
+
+    Ok(())
+}
+
+/// 打开或在后台构建 `revision`（提交号、标签或 `YYYY-MM-DD`）时的快照
+///
+/// 构建完成后发送 `snapshot-complete` 事件，失败或取消时发送 `snapshot-error`
+#[tauri::command]
+fn build_snapshot(
+    app_handle: tauri::AppHandle,
+    state: State<AppState>,
+    revision: String,
+) -> Result<(), String> {
+    let state = state.inner().clone();
+    let data_dir = current_data_dir(&state)?;
+    let commit = git_ops::resolve_revision(&data_dir.mirror(), &revision)
+        .map_err(|e| format!("failed to resolve revision: {}", e))?;
+
+    {
+        let state = state
+            .read()
+            .map_err(|e| format!("failed to lock state: {}", e))?;
+        if let Some(snapshot) = state.snapshots.get(&commit.to_string()) {
+            let _ = app_handle.emit("snapshot-complete", snapshot.info.clone());
+            return Ok(());
+        }
+    }
+    try_start_sync(&state)?;
+
+    std::thread::spawn(move || {
+        let _ = app_handle.emit(
+            "sync-status",
+            format!("Building snapshot of {}...", revision),
+        );
+        let cancel = match state.read() {
+            Ok(state) => state.cancel.clone(),
+            Err(_) => return,
+        };
+
+        let progress = EventProgress::new(&app_handle);
+        let root = data_dir.snapshots();
+        let result = std::fs::create_dir_all(&root)
+            .map_err(snapshot::SnapshotError::from)
+            .and_then(|()| {
+                snapshot::Snapshot::build(
+                    &data_dir.mirror(),
+                    &root,
+                    &revision,
+                    commit,
+                    &progress,
+                    &cancel,
+                )
+            });
+
+        match result {
+            Ok(snapshot) => {
+                let info = snapshot.info.clone();
+                if let Ok(mut state) = state.write() {
+                    state.snapshots.insert(info.commit.clone(), snapshot);
+                }
+                let _ = app_handle.emit("snapshot-complete", info);
+            }
+            Err(e) => {
+                eprintln!("failed to build snapshot of {}: {}", revision, e);
+                let _ = app_handle.emit("snapshot-error", describe_error(&app_handle, &e));
+            }
+        }
+
+        finish_sync(&state);
+    });
+
+    Ok(())
+}
+
+/// 页面修改历史最多列出的提交数
+const PAGE_HISTORY_LIMIT: usize = 100;
+
+/// 列出修改过页面的提交，最新的在前
+#[tauri::command]
+fn get_page_history(
+    state: State<AppState>,
+    file_path: String,
+) -> Result<Vec<git_ops::PageRevision>, String> {
+    if !git_ops::is_page_file(&file_path) {
+        return Err(format!("not a page file: {}", file_path));
+    }
+    git_ops::page_history(
+        &current_data_dir(&state)?.mirror(),
+        &file_path,
+        PAGE_HISTORY_LIMIT,
+    )
+    .map_err(|e| format!("failed to read page history: {}", e))
+}
 
-    update_local_repository(path)
-        .map_err(|e| format!("Synchronizing local repo failed: {}", e))?;
+/// 页面中的链接，以及链接到该页面的页面
+#[tauri::command]
+fn get_page_links(state: State<AppState>, page_id: u64) -> Result<links::PageLinks, String> {
+    let state = state
+        .read()
+        .map_err(|e| format!("failed to lock state: {}", e))?;
+    let storage = state
+        .storage
+        .as_ref()
+        .ok_or_else(|| "storage is not initialized".to_string())?;
 
-    let pages = index_local_files(path)
-        .map_err(|e| format!("Parsing htmls failed: {}", e))?;
+    links::page_links(storage, page_id).map_err(|e| format!("failed to read page links: {}", e))
+}
 
+/// 标题为 `title` 的全部页面；nLab 中不同页面的标题可能相同
+#[tauri::command]
+fn get_pages_by_title(state: State<AppState>, title: String) -> Result<Vec<SearchIndex>, String> {
+    let state = state
+        .read()
+        .map_err(|e| format!("failed to lock state: {}", e))?;
     let storage = state
         .storage
         .as_ref()
         .ok_or_else(|| "storage is not initialized".to_string())?;
-    
-    let search_engine = state
-        .search_engine
-        .as_mut()
-        .ok_or_else(|| "search engine is not initialized".to_string())?;
 
-    storage
-        .save_pages_batch(&pages)
-        .map_err(|e| format!("Saving pages to storage failed: {}", e))?;
+    let ids = storage
+        .get_page_ids_by_title(&title)
+        .map_err(|e| format!("failed to look up title: {}", e))?;
+    let mut pages = Vec::new();
+    for id in ids {
+        let page = storage
+            .get_page(id)
+            .map_err(|e| format!("failed to read page: {}", e))?;
+        if let Some(page) = page {
+            pages.push(SearchIndex {
+                id: page.id,
+                title: page.title,
+                url: page.url,
+                file_path: page.file_path,
+                section: None,
+            });
+        }
+    }
+    Ok(pages)
+}
 
-    search_engine
-        .update_pages_batch(&pages)
-        .map_err(|e| format!("Building search index failed: {}", e))?;
-    
+/// 比较页面在两个版本之间的正文
+#[tauri::command]
+fn get_page_diff(
+    state: State<AppState>,
+    file_path: String,
+    old: String,
+    new: String,
+) -> Result<Vec<git_ops::DiffLine>, String> {
+    if !git_ops::is_page_file(&file_path) {
+        return Err(format!("not a page file: {}", file_path));
+    }
+    let mirror = current_data_dir(&state)?.mirror();
+    git_ops::page_diff(&mirror, &file_path, &old, &new)
+        .map_err(|e| format!("failed to diff page: {}", e))
+}
+
+/// 列出磁盘上已构建的快照
+#[tauri::command]
+fn list_snapshots(state: State<AppState>) -> Vec<snapshot::SnapshotInfo> {
+    current_data_dir(&state)
+        .map(|data_dir| snapshot::list_snapshots(&data_dir.snapshots()))
+        .unwrap_or_default()
+}
+
+/// 取消正在进行的同步或首次构建，已提交的数据保持不变
+#[tauri::command]
+fn cancel_operation(state: State<AppState>) -> Result<(), String> {
+    let state = state
+        .read()
+        .map_err(|e| format!("failed to lock state: {}", e))?;
+    state.cancel.cancel();
     Ok(())
 }
 
@@ -116,6 +600,404 @@ fn open_url(url: String) -> Result<(), String> {
     }
 }
 
+/// 索引对应的提交、构建时间，以及远程比它多出的提交数
+#[tauri::command]
+fn get_status(state: State<AppState>) -> Result<models::IndexStatus, String> {
+    let state = state
+        .read()
+        .map_err(|e| format!("failed to lock state: {}", e))?;
+    let (Some(search_engine), Some(storage)) = (&state.search_engine, &state.storage) else {
+        return Err("search engine is not initialized".to_string());
+    };
+
+    let path = state.data_dir.mirror();
+    let last_sync = storage
+        .get_last_sync()
+        .map_err(|e| format!("failed to read sync record: {}", e))?;
+    let head = current_source(&state.data_dir).revision();
+    let remote = last_sync
+        .as_ref()
+        .and_then(|record| git2::Oid::from_str(&record.commit).ok())
+        .and_then(|commit| git_ops::remote_ahead(&path, commit).ok().flatten());
+
+    let last_attempt = storage
+        .get_last_attempt()
+        .map_err(|e| format!("failed to read sync attempt: {}", e))?;
+    let next_sync = schedule::next_sync_at(
+        last_attempt.as_ref(),
+        load_schedule(&state.data_dir).interval_hours,
+    );
+
+    let mut summary = match (&last_sync, remote) {
+        (Some(record), Some((_, ahead))) => format!(
+            "Index built from {} on {}; remote is {} commits ahead",
+            describe_revision(&record.commit),
+            git_ops::format_date(record.timestamp),
+            ahead
+        ),
+        (Some(record), None) => format!(
+            "Index built from {} on {}",
+            describe_revision(&record.commit),
+            git_ops::format_date(record.timestamp)
+        ),
+        (None, _) => "Index has not been synced yet".to_string(),
+    };
+    if let Some(error) = last_attempt
+        .as_ref()
+        .and_then(|attempt| attempt.error.as_ref())
+    {
+        summary.push_str(&format!("; last sync failed: {}", error));
+    }
+    if let Some(at) = next_sync {
+        summary.push_str(&format!(
+            "; next automatic sync on {}",
+            git_ops::format_date(at.max(unix_now()))
+        ));
+    }
+
+    Ok(models::IndexStatus {
+        inconsistency: check_consistency(storage, search_engine, head.as_deref()),
+        indexed_commit: last_sync.as_ref().map(|record| record.commit.clone()),
+        indexed_at: last_sync.as_ref().map(|record| record.timestamp),
+        page_count: storage.page_count(),
+        duration_ms: last_sync.as_ref().map(|record| record.duration_ms),
+        head_commit: head,
+        remote_commit: remote.map(|(oid, _)| oid.to_string()),
+        remote_ahead: remote.map(|(_, ahead)| ahead),
+        last_error: last_attempt.and_then(|attempt| attempt.error),
+        next_sync,
+        summary,
+    })
+}
+
+/// 同步记录默认列出的运行次数
+const SYNC_HISTORY_LIMIT: usize = 50;
+
+/// 最近的同步、构建和修复记录，最新的在前
+#[tauri::command]
+fn get_sync_history(
+    state: State<AppState>,
+    limit: Option<usize>,
+) -> Result<Vec<storage::SyncRun>, String> {
+    let state = state
+        .read()
+        .map_err(|e| format!("failed to lock state: {}", e))?;
+    let storage = state
+        .storage
+        .as_ref()
+        .ok_or_else(|| "storage is not initialized".to_string())?;
+
+    storage
+        .sync_history(limit.unwrap_or(SYNC_HISTORY_LIMIT))
+        .map_err(|e| format!("failed to read sync history: {}", e))
+}
+
+/// 定时同步和开机启动的设置
+#[tauri::command]
+fn get_schedule(state: State<AppState>) -> Result<config::ScheduleConfig, String> {
+    config::Config::load(&current_data_dir(&state)?.config())
+        .map(|config| config.schedule)
+        .map_err(|e| format!("failed to load config: {}", e))
+}
+
+/// 保存定时同步设置并更新开机启动；定时线程在下一次检查时使用新的间隔
+#[tauri::command]
+fn set_schedule(state: State<AppState>, settings: config::ScheduleConfig) -> Result<(), String> {
+    let data_dir = current_data_dir(&state)?;
+    let path = data_dir.config();
+    let mut config =
+        config::Config::load(&path).map_err(|e| format!("failed to load config: {}", e))?;
+
+    schedule::set_launch_at_login(settings.launch_at_login, data_dir.root())
+        .map_err(|e| format!("{}", e))?;
+    config.schedule = settings;
+    config
+        .save(&path)
+        .map_err(|e| format!("failed to save config: {}", e))
+}
+
+/// 首次运行是否需要选择数据目录和来源
+#[tauri::command]
+fn get_onboarding(
+    app_handle: tauri::AppHandle,
+    state: State<AppState>,
+) -> Result<onboarding::OnboardingStatus, String> {
+    let state = state
+        .read()
+        .map_err(|e| format!("failed to lock state: {}", e))?;
+    let default_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("failed to resolve app data directory: {}", e))?;
+
+    Ok(onboarding::OnboardingStatus {
+        required: state.awaiting_setup,
+        default_data_dir: default_data_dir.to_string_lossy().into_owned(),
+    })
+}
+
+/// 估计所选来源在 `data_dir` 中需要的空间
+#[tauri::command]
+fn estimate_setup(
+    data_dir: String,
+    choice: onboarding::SetupChoice,
+) -> Result<onboarding::SpaceEstimate, String> {
+    let data_dir = std::path::PathBuf::from(data_dir);
+    let choice = onboarding::resolve(choice).map_err(|e| format!("{}", e))?;
+    let mut config = config::Config::load(&data_dir.join(CONFIG_PATH))
+        .map_err(|e| format!("failed to load config: {}", e))?;
+    onboarding::apply_to_config(&choice, &mut config);
+
+    Ok(onboarding::estimate(&data_dir, &choice, &config.mirror))
+}
+
+/// 保存首次运行的选择并开始初始化；之后启动时不再询问
+#[tauri::command]
+fn complete_setup(
+    app_handle: tauri::AppHandle,
+    state: State<AppState>,
+    data_dir: String,
+    choice: onboarding::SetupChoice,
+) -> Result<(), String> {
+    if !state
+        .read()
+        .map_err(|e| format!("failed to lock state: {}", e))?
+        .awaiting_setup
+    {
+        return Err("setup has already been completed".to_string());
+    }
+    if data_dir.trim().is_empty() {
+        return Err("choose a data directory".to_string());
+    }
+    let choice = onboarding::resolve(choice).map_err(|e| format!("{}", e))?;
+
+    let data_dir = std::path::Path::new(data_dir.trim());
+    let open_data_dir = || -> std::io::Result<std::path::PathBuf> {
+        std::fs::create_dir_all(data_dir)?;
+        std::fs::canonicalize(data_dir)
+    };
+    let data_dir = open_data_dir().map_err(|e| {
+        format!(
+            "failed to open data directory {}: {}",
+            data_dir.display(),
+            e
+        )
+    })?;
+    let data_dir = DataDir::new(data_dir);
+    save_setup(&app_handle, &data_dir, &choice)?;
+
+    {
+        let mut state = state
+            .write()
+            .map_err(|e| format!("failed to lock state: {}", e))?;
+        state.awaiting_setup = false;
+        state.data_dir = data_dir;
+    }
+    start_app(app_handle, state.inner().clone(), Some(choice));
+    Ok(())
+}
+
+/// 把选择写入数据目录的配置并保存首次运行记录
+fn save_setup(
+    app_handle: &tauri::AppHandle,
+    data_dir: &DataDir,
+    choice: &onboarding::SetupChoice,
+) -> Result<(), String> {
+    let path = data_dir.config();
+    let mut config =
+        config::Config::load(&path).map_err(|e| format!("failed to load config: {}", e))?;
+    onboarding::apply_to_config(choice, &mut config);
+
+    let estimate = onboarding::estimate(data_dir.root(), choice, &config.mirror);
+    maintenance::ensure_free_space(data_dir.root(), estimate.required)
+        .map_err(|e| format!("{}", e))?;
+    config
+        .save(&path)
+        .map_err(|e| format!("failed to save config: {}", e))?;
+
+    let record = onboarding::SetupRecord {
+        data_dir: data_dir.root().to_path_buf(),
+        choice: Some(choice.clone()),
+        completed_at: unix_now(),
+    };
+    let setup_path = setup_path(app_handle)
+        .ok_or_else(|| "failed to resolve app config directory".to_string())?;
+    record
+        .save(&setup_path)
+        .map_err(|e| format!("failed to save setup record: {}", e))
+}
+
+/// 镜像、数据库、索引和快照占用的磁盘空间
+#[tauri::command]
+fn get_disk_usage(state: State<AppState>) -> Result<maintenance::DiskUsage, String> {
+    Ok(disk_usage(&current_data_dir(&state)?))
+}
+
+/// 在后台整理磁盘空间：重新打包镜像并删除不可达对象、合并索引段、压缩数据库
+///
+/// `remove_worktree` 为 true 时删除镜像的工作目录，为 false 时恢复已删除的工作目录；
+/// 完成后发送 `maintenance-complete` 事件，失败时发送 `maintenance-error`
+#[tauri::command]
+fn run_maintenance(
+    app_handle: tauri::AppHandle,
+    state: State<AppState>,
+    remove_worktree: bool,
+) -> Result<(), String> {
+    let state = state.inner().clone();
+    let data_dir = current_data_dir(&state)?;
+    try_start_sync(&state)?;
+
+    std::thread::spawn(move || {
+        let cancel = match state.read() {
+            Ok(state) => state.cancel.clone(),
+            Err(_) => return,
+        };
+
+        let mut run = storage::SyncRun::new(storage::RunKind::Maintenance, unix_now());
+        let progress = EventProgress::new(&app_handle);
+        let result = run_maintenance_steps(
+            &app_handle,
+            &state,
+            &data_dir,
+            remove_worktree,
+            &progress,
+            &cancel,
+        );
+        match result {
+            Ok(report) => {
+                run.outcome = Some(format!(
+                    "{} → {} bytes",
+                    report.before.total(),
+                    report.after.total()
+                ));
+                let _ = app_handle.emit("maintenance-complete", report);
+            }
+            Err(_) if cancel.is_cancelled() => {
+                let _ = app_handle.emit("sync-cancelled", true);
+                run.error = Some("cancelled".to_string());
+            }
+            Err(e) => {
+                eprintln!("maintenance failed: {}", e);
+                run.error = Some(format!("{}", e));
+                let _ =
+                    app_handle.emit("maintenance-error", describe_error(&app_handle, e.as_ref()));
+            }
+        }
+
+        run.finished_at = unix_now();
+        record_run(&state, &run);
+        finish_sync(&state);
+    });
+
+    Ok(())
+}
+
+fn run_maintenance_steps(
+    app_handle: &tauri::AppHandle,
+    state: &AppState,
+    data_dir: &DataDir,
+    remove_worktree: bool,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<maintenance::MaintenanceReport, Box<dyn std::error::Error>> {
+    let mirror = data_dir.mirror();
+    let path = mirror.as_path();
+    let before = disk_usage(data_dir);
+
+    let packed_objects = if current_source(data_dir).is_git() && path.exists() {
+        let _ = app_handle.emit("sync-status", "Repacking mirror...");
+        let object_count = git_ops::gc_repository(path, progress, cancel)?;
+        if remove_worktree && before.worktree {
+            let _ = app_handle.emit("sync-status", "Removing working tree...");
+            git_ops::remove_worktree(path)?;
+        } else if !remove_worktree && !before.worktree {
+            let _ = app_handle.emit("sync-status", "Restoring working tree...");
+            git_ops::restore_worktree(path, progress)?;
+        }
+        Some(object_count)
+    } else {
+        None
+    };
+    if cancel.is_cancelled() {
+        return Err("maintenance cancelled".into());
+    }
+
+    let _ = app_handle.emit("sync-status", "Merging index segments...");
+    let merged_segments = with_components(state, |_, search_engine| Ok(search_engine.optimize()?))?;
+
+    let _ = app_handle.emit("sync-status", "Compacting database...");
+    compact_storage(state, data_dir)?;
+
+    Ok(maintenance::MaintenanceReport {
+        before,
+        after: disk_usage(data_dir),
+        packed_objects,
+        merged_segments,
+    })
+}
+
+fn disk_usage(data_dir: &DataDir) -> maintenance::DiskUsage {
+    let mirror = data_dir.mirror();
+    maintenance::DiskUsage {
+        mirror: maintenance::dir_size(&mirror),
+        database: maintenance::dir_size(&data_dir.database()),
+        index: maintenance::dir_size(&data_dir.search_index()),
+        snapshots: maintenance::dir_size(&data_dir.snapshots()),
+        worktree: git2::Repository::open(&mirror)
+            .map(|repo| git_ops::has_worktree(&repo))
+            .unwrap_or(false),
+    }
+}
+
+/// 在读锁下把数据库写入新目录，只在替换时短暂持有写锁，搜索不会被长时间阻塞
+fn compact_storage(state: &AppState, data_dir: &DataDir) -> Result<(), Box<dyn std::error::Error>> {
+    let path = data_dir.storage();
+    let compacted = path.with_extension("compact");
+    maintenance::ensure_free_space(&path, maintenance::dir_size(&path))?;
+    with_components(state, |storage, _| Ok(storage.write_compacted(&compacted)?))?;
+
+    let mut state = state
+        .write()
+        .map_err(|e| format!("failed to lock state: {}", e))?;
+    let storage = state.storage.take().ok_or("storage is not initialized")?;
+    match storage.replace(&path, &compacted) {
+        Ok(storage) => {
+            state.storage = Some(storage);
+            Ok(())
+        }
+        Err(e) => {
+            // 替换失败时重新打开原来的数据库
+            state.storage = storage::Storage::new(path.to_str().unwrap()).ok();
+            Err(e.into())
+        }
+    }
+}
+
+/// 给用户看的错误说明；磁盘空间不足时同时发送 `disk-full` 事件
+fn describe_error(
+    app_handle: &tauri::AppHandle,
+    error: &(dyn std::error::Error + 'static),
+) -> String {
+    if !maintenance::is_disk_full(error) {
+        return format!("{}", error);
+    }
+    let message = format!(
+        "{}. Free up some disk space and try again; incomplete data is not used.",
+        error
+    );
+    let _ = app_handle.emit("disk-full", message.clone());
+    message
+}
+
+/// 提交号显示为 `commit` 加前 8 位，其他来源的版本（路径）原样显示
+fn describe_revision(revision: &str) -> String {
+    if revision.len() == 40 && git2::Oid::from_str(revision).is_ok() {
+        format!("commit {}", &revision[..8])
+    } else {
+        revision.to_string()
+    }
+}
+
 #[tauri::command]
 fn is_ready(state: State<AppState>) -> Result<bool, String> {
     let state = state
@@ -126,9 +1008,18 @@ fn is_ready(state: State<AppState>) -> Result<bool, String> {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    let workdir = schedule::workdir_arg()
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_default();
+
     let app_state = Arc::new(RwLock::new(AppStateInner {
         search_engine: None,
         storage: None,
+        syncing: AtomicBool::new(false),
+        cancel: CancelToken::new(),
+        snapshots: HashMap::new(),
+        awaiting_setup: false,
+        data_dir: DataDir::new(workdir),
     }));
 
     let state_clone = app_state.clone();
@@ -138,74 +1029,924 @@ pub fn run() {
         .manage(app_state)
         .invoke_handler(tauri::generate_handler![
             get_search_results,
+            get_facet_counts,
             open_url,
             is_ready,
+            sync_local_repo,
+            preview_sync,
+            import_bundle,
+            export_bundle,
+            cancel_operation,
+            build_snapshot,
+            list_snapshots,
+            get_snapshot_search_results,
+            get_page_history,
+            get_page_diff,
+            get_page_links,
+            get_pages_by_title,
+            export_link_graph,
+            get_status,
+            get_schedule,
+            set_schedule,
+            get_sync_history,
+            get_disk_usage,
+            run_maintenance,
+            get_onboarding,
+            estimate_setup,
+            complete_setup,
         ])
         .setup(move |app| {
             let app_handle = app.handle().clone();
 
-            std::thread::spawn(move || {
-                eprintln!("initializing ...");
-                let _ = app_handle.emit("init-status", "Initializing...");
-
-                match initialize_components(&app_handle) {
-                    Ok((search_engine, storage)) => {
-                        let mut state = state_clone.write().unwrap();
-                        state.search_engine = Some(search_engine);
-                        state.storage = Some(storage);
-                        eprintln!("initialized successfully");
-                        let _ = app_handle.emit("init-complete", true);
-                    }
-                    Err(e) => {
-                        eprintln!("failed to initialize app state: {}", e);
-                        let _ = app_handle.emit("init-error", format!("{}", e));
-                    }
+            // 首次运行时等待前端的选择，不自动开始克隆
+            let workdir = state_clone.read().unwrap().data_dir.clone();
+            match prepare_data_dir(&app_handle, &workdir) {
+                Some(record) => {
+                    state_clone.write().unwrap().data_dir = DataDir::new(&record.data_dir);
+                    start_app(app_handle, state_clone, record.choice)
                 }
-            });
+                None => {
+                    eprintln!("waiting for first-run setup");
+                    state_clone.write().unwrap().awaiting_setup = true;
+                }
+            }
             Ok(())
         })
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
 
+/// 读取首次运行的记录，其中有数据目录；还没有完成首次运行时返回 `None`
+///
+/// 升级前在启动目录 `workdir` 已有数据时，直接把它记为数据目录
+fn prepare_data_dir(
+    app_handle: &tauri::AppHandle,
+    workdir: &DataDir,
+) -> Option<onboarding::SetupRecord> {
+    let path = setup_path(app_handle)?;
+    match onboarding::SetupRecord::load(&path) {
+        Ok(Some(record)) if record.data_dir.is_dir() => Some(record),
+        Ok(Some(record)) => {
+            eprintln!(
+                "data directory {} does not exist",
+                record.data_dir.display()
+            );
+            None
+        }
+        Ok(None) if workdir.has_local_data() => {
+            let record = onboarding::SetupRecord {
+                data_dir: std::path::absolute(workdir.root()).ok()?,
+                choice: None,
+                completed_at: unix_now(),
+            };
+            if let Err(e) = record.save(&path) {
+                eprintln!("failed to save setup record: {}", e);
+            }
+            Some(record)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            eprintln!("failed to read setup record: {}", e);
+            None
+        }
+    }
+}
+
+fn setup_path(app_handle: &tauri::AppHandle) -> Option<std::path::PathBuf> {
+    match app_handle.path().app_config_dir() {
+        Ok(dir) => Some(dir.join(onboarding::SETUP_FILE)),
+        Err(e) => {
+            eprintln!("failed to resolve app config directory: {}", e);
+            None
+        }
+    }
+}
+
+/// 在后台打开或构建数据，完成后开始定时同步；`choice` 为首次运行选择的来源
+fn start_app(
+    app_handle: tauri::AppHandle,
+    state: AppState,
+    choice: Option<onboarding::SetupChoice>,
+) {
+    std::thread::spawn(move || {
+        eprintln!("initializing ...");
+        let _ = app_handle.emit("init-status", "Initializing...");
+
+        // 已有本地数据时先直接打开，网络同步放到后台，不阻塞搜索
+        let (data_dir, cancel) = {
+            let state = state.read().unwrap();
+            (state.data_dir.clone(), state.cancel.clone())
+        };
+        let has_local_data = data_dir.has_local_data();
+        let components = if has_local_data {
+            load_existing_components(&app_handle, &data_dir)
+        } else {
+            initialize_components(&app_handle, &data_dir, choice.as_ref(), &cancel)
+        };
+
+        match components {
+            Ok((search_engine, storage)) => {
+                {
+                    let mut state = state.write().unwrap();
+                    state.search_engine = Some(search_engine);
+                    state.storage = Some(storage);
+                }
+                eprintln!("initialized successfully");
+                let _ = app_handle.emit("init-complete", true);
+
+                let interval_hours = load_schedule(&data_dir).interval_hours;
+                match startup_sync(has_local_data, interval_hours) {
+                    StartupSync::Skip => record_attempt(&state, None),
+                    StartupSync::Background => {
+                        if try_start_sync(&state).is_ok() {
+                            sync_in_background(&app_handle, &state, None);
+                        }
+                    }
+                    StartupSync::Scheduled => {}
+                }
+                run_scheduler(&app_handle, &state, &data_dir);
+            }
+            Err(e) if cancel.is_cancelled() => {
+                eprintln!("initialization cancelled: {}", e);
+                let _ = app_handle.emit("init-error", "Initialization cancelled");
+            }
+            Err(e) => {
+                eprintln!("failed to initialize app state: {}", e);
+                let _ = app_handle.emit("init-error", describe_error(&app_handle, e.as_ref()));
+            }
+        }
+    });
+}
+
+/// 打开数据之后如何同步
+#[derive(Debug, PartialEq)]
+enum StartupSync {
+    /// 数据是刚构建的，只记录这次尝试
+    Skip,
+    /// 在后台同步一次，搜索不等待
+    Background,
+    /// 设置了定时同步，由定时线程决定
+    Scheduled,
+}
+
+/// 启动时的同步方式；已有本地数据时总是先打开，同步在打开之后才开始
+fn startup_sync(has_local_data: bool, interval_hours: u64) -> StartupSync {
+    match (has_local_data, interval_hours) {
+        (false, _) => StartupSync::Skip,
+        (true, 0) => StartupSync::Background,
+        (true, _) => StartupSync::Scheduled,
+    }
+}
+
+/// 直接打开已有的数据库和索引，不访问网络
+fn load_existing_components(
+    app_handle: &tauri::AppHandle,
+    data_dir: &DataDir,
+) -> Result<(search::TantivySearch, storage::Storage), Box<dyn std::error::Error>> {
+    let _ = app_handle.emit("init-status", "Loading existing data...");
+
+    let (search_engine, storage) = open_local_data(data_dir, |status| {
+        let _ = app_handle.emit("init-status", status);
+    })?;
+    check_local_data(app_handle, data_dir, &storage, &search_engine);
+
+    Ok((search_engine, storage))
+}
+
+/// 打开数据目录中的数据库和索引，只读写这两个目录；`status` 接收状态说明
+fn open_local_data(
+    data_dir: &DataDir,
+    status: impl Fn(&str),
+) -> Result<(search::TantivySearch, storage::Storage), Box<dyn std::error::Error>> {
+    // 旧版本以标题为页面 ID：存储在打开时迁移，索引用迁移后的页面重建
+    let storage = storage::Storage::new(data_dir.storage().to_str().unwrap())?;
+    let mut search_engine = search::TantivySearch::new(data_dir.index().to_str().unwrap())?;
+    if !search_engine.has_current_schema() {
+        status("Rebuilding search index...");
+        search_engine = search_engine.rebuild(&data_dir.index(), &storage.pages()?)?;
+    }
+
+    Ok((search_engine, storage))
+}
+
+/// 首次运行：克隆（或更新）仓库并全量构建数据库和索引；选择了索引包时直接导入
 fn initialize_components(
     app_handle: &tauri::AppHandle,
+    data_dir: &DataDir,
+    choice: Option<&onboarding::SetupChoice>,
+    cancel: &CancelToken,
 ) -> Result<(search::TantivySearch, storage::Storage), Box<dyn std::error::Error>> {
-    use std::path::Path;
-    let path = Path::new(GIT_REPO_PATH);
-    let storage_path = Path::new(DB_PATH).join("storage");
-    let index_path = Path::new(INDEX_PATH).join("index");
+    let mirror_path = data_dir.mirror();
+    let path = mirror_path.as_path();
+
+    let started = Instant::now();
+    let mut run = storage::SyncRun::new(storage::RunKind::Initial, unix_now());
+    let progress = EventProgress::new(app_handle);
+    let config = config::Config::load(&data_dir.config())?;
+    let source = page_source(&config, data_dir)?;
+    run.old_commit = source.revision();
 
-    let _ = app_handle.emit("init-status", "Synchronizing repository...");
-    let _repo = update_local_repository(path)?;
+    if let Some(onboarding::SetupChoice::IndexBundle { path: bundle }) = choice {
+        let _ = app_handle.emit("init-status", "Importing index bundle...");
+        onboarding::import_index_bundle(
+            std::path::Path::new(bundle),
+            data_dir.root(),
+            &progress,
+            cancel,
+        )?;
+        let (search_engine, storage) = load_existing_components(app_handle, data_dir)?;
+
+        run.finished_at = unix_now();
+        run.mirror = Some(bundle.clone());
+        run.outcome = Some("index_bundle".to_string());
+        run.new_commit = search_engine.indexed_commit().ok().flatten();
+        run.pages_added = storage.page_count();
+        if let Err(e) = storage.append_sync_run(&run) {
+            eprintln!("failed to record sync run: {}", e);
+        }
+        return Ok((search_engine, storage));
+    }
+
+    let mirror = if source.is_git() {
+        // 已有的克隆或 bundle 文件只用于首次克隆
+        let mut mirror_config = config.mirror.clone();
+        if let Some(initial) = choice.and_then(onboarding::initial_mirror) {
+            mirror_config.mirrors.insert(0, initial);
+        }
+        if !path.exists() {
+            maintenance::ensure_free_space(path, git_ops::estimated_clone_size(&mirror_config))?;
+        }
+        let _ = app_handle.emit("init-status", "Synchronizing repository...");
+        let mirror = match update_local_repository(path, &mirror_config, &progress, cancel) {
+            Ok(report) => {
+                run.outcome = Some(report.update.name().to_string());
+                Some(report.mirror)
+            }
+            // 已有本地仓库时，离线也可以用现有的检出构建索引
+            Err(e) if path.exists() && !cancel.is_cancelled() => {
+                eprintln!("failed to update repository, using local copy: {}", e);
+                let _ = app_handle.emit("index-stale", format!("{}", e));
+                None
+            }
+            Err(e) => return Err(e.into()),
+        };
 
-    if !path.exists() {
-        Err("local repo should exist after update".into())
+        if !path.exists() {
+            return Err("local repo should exist after update".into());
+        }
+        mirror
     } else {
-        let pages = index_local_files(path)?;
-        let needs_full_rebuild = !storage_path.exists() || !index_path.exists();
-        if needs_full_rebuild {
-            let _ = app_handle.emit("init-status", "Parsing pages...");
+        Some(source.describe())
+    };
+    let (search_engine, storage) =
+        build_local_data(app_handle, data_dir, &source, &progress, cancel).inspect_err(|_| {
+            // 不完整的数据库和索引不能留下，否则下次启动会被当作已有数据
+            remove_local_data(data_dir);
+        })?;
+    if let Some(mirror) = &mirror {
+        storage.set_metadata(storage::META_MIRROR, mirror.as_bytes())?;
+    }
+    record_sync(&storage, &search_engine, &source, started);
+
+    run.finished_at = unix_now();
+    run.mirror = mirror;
+    run.new_commit = source.revision();
+    run.pages_added = storage.page_count();
+    run.parse_failures = progress.skipped_count();
+    if let Err(e) = storage.append_sync_run(&run) {
+        eprintln!("failed to record sync run: {}", e);
+    }
+    Ok((search_engine, storage))
+}
+
+fn build_local_data(
+    app_handle: &tauri::AppHandle,
+    data_dir: &DataDir,
+    source: &source::Source,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<(search::TantivySearch, storage::Storage), Box<dyn std::error::Error>> {
+    let _ = app_handle.emit("init-status", "Parsing pages...");
+    let pages = source.read_pages(progress, cancel)?;
+    maintenance::ensure_free_space(
+        &data_dir.database(),
+        maintenance::estimated_data_size(&pages),
+    )?;
+    // 之前中断的构建可能留下只有存储或只有索引的数据
+    remove_local_data(data_dir);
 
-            let _ = app_handle.emit("init-status", "Initializing storage...");
-            let storage = storage::Storage::new(storage_path.to_str().unwrap())?;
-            storage.save_pages_batch(&pages)?;
+    // 先写入 `.partial` 目录，全部完成后再重命名；中途退出时不会被当作已有数据
+    let storage_partial = partial_path(&data_dir.storage());
+    let index_partial = partial_path(&data_dir.index());
+    {
+        let _ = app_handle.emit("init-status", "Initializing storage...");
+        let storage = storage::Storage::new(storage_partial.to_str().unwrap())?;
+        storage.save_pages_batch(&pages)?;
+        storage.flush()?;
 
-            let _ = app_handle.emit("init-status", "Building search index...");
-            let mut search_engine = search::TantivySearch::new(index_path.to_str().unwrap())?;
-            search_engine.build_index(&pages)?;
+        let _ = app_handle.emit("init-status", "Building search index...");
+        let mut search_engine = search::TantivySearch::new(&index_partial)?;
+        search_engine.build_index_with_progress(&pages, progress, cancel)?;
+    }
+    std::fs::rename(&storage_partial, data_dir.storage())?;
+    std::fs::rename(&index_partial, data_dir.index())?;
 
-            Ok((search_engine, storage))
-        } else {
-            let _ = app_handle.emit("init-status", "Loading existing data...");
+    let storage = storage::Storage::new(data_dir.storage().to_str().unwrap())?;
+    let search_engine = search::TantivySearch::new(data_dir.index().to_str().unwrap())?;
+    Ok((search_engine, storage))
+}
+
+fn partial_path(path: &std::path::Path) -> std::path::PathBuf {
+    path.with_extension("partial")
+}
+
+fn remove_local_data(data_dir: &DataDir) {
+    let (storage, index) = (data_dir.storage(), data_dir.index());
+    for dir in [partial_path(&storage), partial_path(&index), storage, index] {
+        if dir.exists() {
+            eprintln!("removing incomplete data: {}", dir.display());
+            std::fs::remove_dir_all(&dir).ok();
+        }
+    }
+}
+
+/// 标记同步或快照构建开始；已有任务在运行或尚未初始化时返回错误
+fn try_start_sync(state: &AppState) -> Result<(), String> {
+    let state = state
+        .read()
+        .map_err(|e| format!("failed to lock state: {}", e))?;
+
+    if state.search_engine.is_none() || state.storage.is_none() {
+        return Err("search engine is not initialized".to_string());
+    }
+    if state.syncing.swap(true, Ordering::SeqCst) {
+        return Err("a sync or snapshot build is already running".to_string());
+    }
+    state.cancel.reset();
+    Ok(())
+}
+
+/// 同步仓库并更新索引，需先调用 `try_start_sync`；给定 `bundle` 时只从该文件更新
+///
+/// 失败时只发出 `index-stale` 警告，继续使用现有数据
+fn sync_in_background(app_handle: &tauri::AppHandle, state: &AppState, bundle: Option<&str>) {
+    let status = match bundle {
+        Some(bundle) => format!("Importing {}...", bundle),
+        None => "Synchronizing repository...".to_string(),
+    };
+    let _ = app_handle.emit("sync-status", status);
+
+    let (data_dir, cancel) = match state.read() {
+        Ok(state) => (state.data_dir.clone(), state.cancel.clone()),
+        Err(_) => return,
+    };
+
+    let kind = match bundle {
+        Some(_) => storage::RunKind::Bundle,
+        None => storage::RunKind::Sync,
+    };
+    let mut run = storage::SyncRun::new(kind, unix_now());
+    run.old_commit = current_source(&data_dir).revision();
+    let progress = EventProgress::new(app_handle);
+
+    let result = sync_repository(
+        app_handle, state, &data_dir, bundle, &mut run, &progress, &cancel,
+    );
+    let error = match result {
+        Ok(()) => {
+            eprintln!("background sync finished");
+            let _ = app_handle.emit("sync-complete", true);
+            None
+        }
+        Err(e) if cancel.is_cancelled() => {
+            eprintln!("background sync cancelled: {}", e);
+            let _ = app_handle.emit("sync-cancelled", true);
+            run.error = Some("cancelled".to_string());
+            None
+        }
+        Err(e) => {
+            eprintln!("background sync failed, index may be stale: {}", e);
+            let _ = app_handle.emit("index-stale", describe_error(app_handle, e.as_ref()));
+            Some(format!("{}", e))
+        }
+    };
 
-            let storage = storage::Storage::new(storage_path.to_str().unwrap())?;
+    run.finished_at = unix_now();
+    run.new_commit = current_source(&data_dir).revision();
+    run.parse_failures = progress.skipped_count();
+    if error.is_some() {
+        run.error = error.clone();
+    }
+    record_run(state, &run);
+    record_attempt(state, error);
+    finish_sync(state);
+}
+
+/// 把一次运行追加到同步记录；失败只打印日志
+fn record_run(state: &AppState, run: &storage::SyncRun) {
+    let result = with_components(state, |storage, _| {
+        storage.append_sync_run(run)?;
+        Ok(())
+    });
+
+    if let Err(e) = result {
+        eprintln!("failed to record sync run: {}", e);
+    }
+}
+
+/// 保存本次同步尝试的结果，定时同步据此计算下一次时间；失败只打印日志
+fn record_attempt(state: &AppState, error: Option<String>) {
+    let result = with_components(state, |storage, _| {
+        storage.set_last_attempt(&storage::SyncAttempt {
+            timestamp: unix_now(),
+            error,
+        })?;
+        Ok(())
+    });
+
+    if let Err(e) = result {
+        eprintln!("failed to record sync attempt: {}", e);
+    }
+}
+
+/// 按配置的间隔在后台同步，每 `CHECK_INTERVAL` 检查一次，修改后的设置在下一次检查时生效
+fn run_scheduler(app_handle: &tauri::AppHandle, state: &AppState, data_dir: &DataDir) {
+    loop {
+        std::thread::sleep(schedule::CHECK_INTERVAL);
+
+        let last = with_components(state, |storage, _| Ok(storage.get_last_attempt()?))
+            .ok()
+            .flatten();
+        let due = schedule::next_sync_at(last.as_ref(), load_schedule(data_dir).interval_hours)
+            .is_some_and(|at| unix_now() >= at);
+        if due && try_start_sync(state).is_ok() {
+            eprintln!("starting scheduled sync");
+            sync_in_background(app_handle, state, None);
+        }
+    }
+}
+
+/// 读取定时同步设置；配置无效时不自动同步
+fn load_schedule(data_dir: &DataDir) -> config::ScheduleConfig {
+    config::Config::load(&data_dir.config())
+        .map(|config| config.schedule)
+        .unwrap_or_else(|e| {
+            eprintln!("failed to read schedule: {}", e);
+            config::ScheduleConfig::default()
+        })
+}
+
+/// 清除 `try_start_sync` 设置的标记
+fn finish_sync(state: &AppState) {
+    if let Ok(state) = state.read() {
+        state.syncing.store(false, Ordering::SeqCst);
+    }
+}
+
+/// 同步并把镜像、更新方式和页面变化写入 `run`
+fn sync_repository(
+    app_handle: &tauri::AppHandle,
+    state: &AppState,
+    data_dir: &DataDir,
+    bundle: Option<&str>,
+    run: &mut storage::SyncRun,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mirror_path = data_dir.mirror();
+    let path = mirror_path.as_path();
+    let started = Instant::now();
+    let mut config = config::Config::load(&data_dir.config())?;
+    if let Some(bundle) = bundle {
+        config.mirror.mirrors = vec![config::Mirror::new(bundle)];
+    }
+    let source = page_source(&config, data_dir)?;
+
+    // 目录和压缩包没有版本信息，每次同步都重新读取全部页面
+    if !source.is_git() {
+        run.mirror = Some(source.describe());
+        run.outcome = Some("reread".to_string());
+        run.pages_added = reindex_all(app_handle, state, data_dir, &source, progress, cancel)?;
+        return with_components(state, |storage, search_engine| {
+            storage.set_metadata(storage::META_MIRROR, source.describe().as_bytes())?;
+            record_sync(storage, search_engine, &source, started);
+            Ok(())
+        });
+    }
+
+    maintenance::ensure_free_space(path, maintenance::MIN_FREE_SPACE)?;
+    let report = update_local_repository(path, &config.mirror, progress, cancel)?;
+    run.mirror = Some(report.mirror.clone());
+    run.outcome = Some(report.update.name().to_string());
+    let reread_pending = with_components(state, |storage, _| Ok(storage.reread_pending()?))?;
+    match report.update {
+        update if update.needs_full_reindex() || reread_pending => {
+            // 仓库是重新克隆或修复的，无法得知变化，只能全量更新；
+            // 数据迁移后也要全量更新，找回旧版本中因标题相同而被覆盖的页面
+            run.pages_added = reindex_all(app_handle, state, data_dir, &source, progress, cancel)?;
+        }
+        RepoUpdate::FastForwarded {
+            old_head,
+            new_head,
+            changes,
+        }
+        | RepoUpdate::Reset {
+            old_head,
+            new_head,
+            changes,
+        } if !changes.is_empty() => {
+            run.pages_added = changes.added.len();
+            run.pages_modified = changes.modified.len();
+            run.pages_deleted = changes.deleted.len();
+            let _ = app_handle.emit(
+                "sync-status",
+                format!("Applying {} page changes...", changes.len()),
+            );
+            let result = with_components(state, |storage, search_engine| {
+                apply_page_changes(
+                    path,
+                    new_head,
+                    &changes,
+                    storage,
+                    search_engine,
+                    progress,
+                    cancel,
+                )
+            });
+
+            // 索引没有更新成功时把仓库退回旧的 HEAD，下次同步会重新计算这些变化
+            if let Err(e) = result {
+                git_ops::reset_head(path, old_head)?;
+                return Err(e);
+            }
+        }
+        _ => {}
+    }
+
+    with_components(state, |storage, search_engine| {
+        storage.set_metadata(storage::META_MIRROR, report.mirror.as_bytes())?;
+        record_sync(storage, search_engine, &source, started);
+        Ok(())
+    })
+}
+
+/// 重新读取来源中的全部页面，写入存储和搜索索引，返回页面数
+fn reindex_all(
+    app_handle: &tauri::AppHandle,
+    state: &AppState,
+    data_dir: &DataDir,
+    source: &source::Source,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let _ = app_handle.emit("sync-status", "Parsing pages...");
+    let pages = source.read_pages(progress, cancel)?;
+    maintenance::ensure_free_space(
+        &data_dir.database(),
+        maintenance::estimated_data_size(&pages),
+    )?;
+
+    let _ = app_handle.emit("sync-status", "Updating search index...");
+    with_components(state, |storage, search_engine| {
+        replace_pages(storage, search_engine, &pages, progress, cancel)?;
+        storage.clear_reread_pending()?;
+        Ok(pages.len())
+    })
+}
+
+/// 用 `pages` 替换存储和索引中的全部页面，来源中已不存在的页面被删除
+fn replace_pages(
+    storage: &storage::Storage,
+    search_engine: &search::TantivySearch,
+    pages: &[models::NLabPage],
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let new_ids: std::collections::HashSet<u64> = pages.iter().map(|p| p.id).collect();
+    let stale_ids: Vec<u64> = storage
+        .page_ids()?
+        .into_iter()
+        .filter(|id| !new_ids.contains(id))
+        .collect();
+
+    // 与增量同步相同，先提交索引再修改存储
+    search_engine.apply_changes(pages, &stale_ids, progress, cancel)?;
+    storage.delete_pages_batch(&stale_ids)?;
+    storage.save_pages_batch(pages)?;
+
+    Ok(())
+}
+
+/// 配置中的页面来源，未设置时为数据目录中的 git 镜像；相对路径相对于数据目录
+fn page_source(
+    config: &config::Config,
+    data_dir: &DataDir,
+) -> Result<source::Source, source::SourceError> {
+    let configured = config
+        .source
+        .as_deref()
+        .map(|source| data_dir.root().join(source).to_string_lossy().into_owned());
+    source::Source::from_config(
+        configured.as_deref(),
+        &data_dir.mirror(),
+        &data_dir.source(),
+    )
+}
+
+/// 读取当前配置的页面来源；配置无效时按 git 镜像处理，只用于状态显示和一致性检查
+fn current_source(data_dir: &DataDir) -> source::Source {
+    config::Config::load(&data_dir.config())
+        .map_err(|e| e.to_string())
+        .and_then(|config| page_source(&config, data_dir).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            eprintln!("failed to read page source, assuming git mirror: {}", e);
+            source::Source::Git(data_dir.mirror())
+        })
+}
+
+/// 记录数据对应的版本（镜像 HEAD 或来源路径）、页面数和耗时；失败只打印日志，数据本身已经提交
+fn record_sync(
+    storage: &storage::Storage,
+    search_engine: &search::TantivySearch,
+    source: &source::Source,
+    started: Instant,
+) {
+    let result = source
+        .revision()
+        .ok_or_else(|| Box::<dyn std::error::Error>::from("failed to resolve mirror HEAD"))
+        .and_then(|commit| {
+            search_engine.set_indexed_commit(&commit)?;
+            storage.set_last_sync(&storage::SyncRecord {
+                commit,
+                timestamp: unix_now(),
+                page_count: storage.page_count(),
+                duration_ms: started.elapsed().as_millis() as u64,
+            })?;
+            Ok(())
+        });
+
+    if let Err(e) = result {
+        eprintln!("failed to record sync: {}", e);
+    }
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as i64)
+}
+
+/// 比较存储、搜索索引和镜像 HEAD 所在的提交，不一致时返回说明
+fn check_consistency(
+    storage: &storage::Storage,
+    search_engine: &search::TantivySearch,
+    head: Option<&str>,
+) -> Option<String> {
+    let stored = storage
+        .get_last_sync()
+        .ok()
+        .flatten()
+        .map(|record| record.commit);
+    let indexed = search_engine.indexed_commit().ok().flatten();
+
+    match (stored, indexed) {
+        (None, None) => {
+            Some("the index does not record which commit it was built from".to_string())
+        }
+        (stored, indexed) if stored != indexed => Some(format!(
+            "the page store is at commit {} but the search index is at commit {}",
+            stored.as_deref().unwrap_or("unknown"),
+            indexed.as_deref().unwrap_or("unknown")
+        )),
+        (Some(commit), _) if Some(commit.as_str()) != head => Some(format!(
+            "the index is at commit {} but the mirror is at commit {}",
+            commit,
+            head.unwrap_or("unknown")
+        )),
+        _ => None,
+    }
+}
+
+/// 启动时检查已有数据；存储和索引一致但镜像 HEAD 已前进（例如同步中途退出）时，
+/// 把镜像退回索引所在的提交，让下一次增量同步重新计算这段变化
+fn check_local_data(
+    app_handle: &tauri::AppHandle,
+    data_dir: &DataDir,
+    storage: &storage::Storage,
+    search_engine: &search::TantivySearch,
+) {
+    let mirror = data_dir.mirror();
+    let path = mirror.as_path();
+    let source = current_source(data_dir);
+    // 导入的索引包可以不带镜像，第一次同步时再克隆
+    if source.is_git() && !path.exists() {
+        return;
+    }
+    let head = source.revision();
+    let Some(problem) = check_consistency(storage, search_engine, head.as_deref()) else {
+        return;
+    };
+    eprintln!("local data is inconsistent: {}", problem);
+    if !source.is_git() {
+        let _ = app_handle.emit("index-stale", problem);
+        return;
+    }
+
+    let indexed = search_engine.indexed_commit().ok().flatten();
+    let stored = storage
+        .get_last_sync()
+        .ok()
+        .flatten()
+        .map(|record| record.commit);
+    if let Some(commit) = indexed.filter(|commit| Some(commit) == stored.as_ref()) {
+        let mut run = storage::SyncRun::new(storage::RunKind::Repair, unix_now());
+        run.old_commit = head;
+        run.outcome = Some("reset_to_index".to_string());
+        match git2::Oid::from_str(&commit).and_then(|oid| git_ops::reset_head(path, oid)) {
+            Ok(()) => {
+                run.finished_at = unix_now();
+                run.new_commit = Some(commit);
+                if let Err(e) = storage.append_sync_run(&run) {
+                    eprintln!("failed to record sync run: {}", e);
+                }
+                return;
+            }
+            Err(e) => eprintln!("failed to reset mirror to indexed commit: {}", e),
+        }
+    }
+    let _ = app_handle.emit("index-stale", problem);
+}
+
+/// 当前的数据目录
+fn current_data_dir(state: &AppState) -> Result<DataDir, String> {
+    Ok(state
+        .read()
+        .map_err(|e| format!("failed to lock state: {}", e))?
+        .data_dir
+        .clone())
+}
+
+/// 持有读锁，对已初始化的存储和搜索引擎执行 `f`
+///
+/// 写入索引不需要写锁：tantivy 的提交是原子的，正在进行的搜索继续使用旧的 searcher
+fn with_components<T>(
+    state: &AppState,
+    f: impl FnOnce(
+        &storage::Storage,
+        &search::TantivySearch,
+    ) -> Result<T, Box<dyn std::error::Error>>,
+) -> Result<T, Box<dyn std::error::Error>> {
+    let state = state
+        .read()
+        .map_err(|e| format!("failed to lock state: {}", e))?;
+
+    let storage = state
+        .storage
+        .as_ref()
+        .ok_or("storage is not initialized")?;
+    let search_engine = state
+        .search_engine
+        .as_ref()
+        .ok_or("search engine is not initialized")?;
+
+    f(storage, search_engine)
+}
+
+/// 只重新解析 `commit` 中变化的页面，并同步到存储和搜索索引
+fn apply_page_changes(
+    repo_path: &std::path::Path,
+    commit: git2::Oid,
+    changes: &PageChanges,
+    storage: &storage::Storage,
+    search_engine: &search::TantivySearch,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let changed_files: Vec<String> = changes.changed_files().cloned().collect();
+    let pages = index_commit_files(repo_path, commit, &changed_files, progress, cancel)?;
+
+    // 被删除的页面，以及修改后 ID 发生变化的页面，需要移除旧 ID
+    let new_ids: std::collections::HashSet<u64> = pages.iter().map(|p| p.id).collect();
+    let mut stale_ids = Vec::new();
+    for file_path in changes.deleted.iter().chain(changes.modified.iter()) {
+        if let Some(old_id) = storage.get_page_id_by_path(file_path)? {
+            if !new_ids.contains(&old_id) {
+                stale_ids.push(old_id);
+            }
+        }
+    }
+
+    // 先提交索引：取消时索引会回滚，此时存储还没有被修改
+    search_engine.apply_changes(&pages, &stale_ids, progress, cancel)?;
+    storage.delete_pages_batch(&stale_ids)?;
+    storage.save_pages_batch(&pages)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::NoProgress;
+    use tempfile::TempDir;
 
-            let _ = app_handle.emit("init-status", "Checking for index updates...");
-            let mut search_engine = search::TantivySearch::new(index_path.to_str().unwrap())?;
-            search_engine.update_pages_batch(&pages)?;
+    #[test]
+    fn test_open_local_data_stays_offline() {
+        let temp_dir = TempDir::new().unwrap();
+        let data_dir = DataDir::new(temp_dir.path());
+        {
+            storage::Storage::new(data_dir.storage().to_str().unwrap()).unwrap();
+            search::TantivySearch::new(data_dir.index().to_str().unwrap()).unwrap();
+        }
+        assert!(data_dir.has_local_data());
+
+        let (search_engine, storage) = open_local_data(&data_dir, |_| {}).unwrap();
+        assert!(search_engine.has_current_schema());
+        assert_eq!(storage.page_count(), 0);
+        // 没有克隆镜像，也没有记录任何同步
+        assert!(!data_dir.mirror().exists());
+        assert!(storage.sync_history(10).unwrap().is_empty());
+        assert!(storage.get_last_attempt().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_try_start_sync_allows_one_run() {
+        let temp_dir = TempDir::new().unwrap();
+        let data_dir = DataDir::new(temp_dir.path());
+        let state: AppState = Arc::new(RwLock::new(AppStateInner {
+            search_engine: None,
+            storage: None,
+            syncing: AtomicBool::new(false),
+            cancel: CancelToken::new(),
+            snapshots: HashMap::new(),
+            awaiting_setup: false,
+            data_dir: data_dir.clone(),
+        }));
+        assert!(try_start_sync(&state).is_err());
 
-            Ok((search_engine, storage))
+        let (search_engine, storage) = open_local_data(&data_dir, |_| {}).unwrap();
+        {
+            let mut state = state.write().unwrap();
+            state.search_engine = Some(search_engine);
+            state.storage = Some(storage);
         }
+        state.read().unwrap().cancel.cancel();
+
+        assert!(try_start_sync(&state).is_ok());
+        assert!(!state.read().unwrap().cancel.is_cancelled());
+        assert!(try_start_sync(&state).is_err());
+
+        finish_sync(&state);
+        assert!(try_start_sync(&state).is_ok());
+    }
+
+    fn page(id: u64, title: &str, content: &str) -> models::NLabPage {
+        models::NLabPage::new(
+            id,
+            format!("pages/{}/content.html", id),
+            title.to_string(),
+            format!("https://ncatlab.org/nlab/show/{}", title),
+            content.to_string(),
+            Vec::new(),
+            Vec::new(),
+        )
+    }
+
+    #[test]
+    fn test_replace_pages_removes_vanished_pages() {
+        let temp_dir = TempDir::new().unwrap();
+        let (search_engine, storage) =
+            open_local_data(&DataDir::new(temp_dir.path()), |_| {}).unwrap();
+        let cancel = CancelToken::new();
+        let search = |query: &str| {
+            search_engine
+                .search_with_filters(query, 10, search::SearchFilters::default())
+                .unwrap()
+                .into_iter()
+                .map(|result| result.id)
+                .collect::<Vec<_>>()
+        };
+
+        let first = [page(1, "sheaf", "alpha"), page(2, "topos", "beta")];
+        replace_pages(&storage, &search_engine, &first, &NoProgress, &cancel).unwrap();
+        assert_eq!(storage.page_ids().unwrap(), vec![1, 2]);
+        assert_eq!(search("beta"), vec![2]);
+
+        // 第二次读取时 topos 已从来源中消失
+        let second = [page(1, "sheaf", "gamma")];
+        replace_pages(&storage, &search_engine, &second, &NoProgress, &cancel).unwrap();
+        assert_eq!(storage.page_ids().unwrap(), vec![1]);
+        assert!(storage.get_page(2).unwrap().is_none());
+        assert_eq!(storage.get_page_id_by_slug("topos").unwrap(), None);
+        assert!(search("beta").is_empty());
+        assert_eq!(search("gamma"), vec![1]);
+    }
+
+    #[test]
+    fn test_startup_sync_waits_for_local_data() {
+        assert_eq!(startup_sync(false, 0), StartupSync::Skip);
+        assert_eq!(startup_sync(false, 24), StartupSync::Skip);
+        assert_eq!(startup_sync(true, 0), StartupSync::Background);
+        assert_eq!(startup_sync(true, 24), StartupSync::Scheduled);
     }
 }
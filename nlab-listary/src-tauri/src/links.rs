@@ -0,0 +1,311 @@
+use crate::cancel::CancelToken;
+use crate::models::NLabPage;
+use crate::storage::{Storage, StorageError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum LinkError {
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Export cancelled")]
+    Cancelled,
+}
+
+const BASE_URL: &str = "https://ncatlab.org/nlab/show/";
+
+/// 链接另一端的页面
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LinkedPage {
+    /// 链接到还不存在的页面时为 `None`
+    pub id: Option<u64>,
+    pub title: String,
+    pub url: String,
+}
+
+impl LinkedPage {
+    fn from_page(page: &NLabPage) -> Self {
+        Self {
+            id: Some(page.id),
+            title: page.title.clone(),
+            url: page.url.clone(),
+        }
+    }
+}
+
+/// `get_page_links` 命令的结果
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PageLinks {
+    /// 页面中链接到的页面，按出现顺序
+    pub outgoing: Vec<LinkedPage>,
+    /// 链接到该页面的页面（"what links here"），按页面编号排序
+    pub incoming: Vec<LinkedPage>,
+}
+
+/// 导出链接图的文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphFormat {
+    Dot,
+    Graphml,
+}
+
+/// `export_graph` 的结果
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GraphSummary {
+    pub path: String,
+    pub nodes: usize,
+    pub edges: usize,
+}
+
+pub fn page_links(storage: &Storage, page_id: u64) -> Result<PageLinks, LinkError> {
+    let mut outgoing = Vec::new();
+    for (slug, target) in storage.links_from(page_id)? {
+        let page = match target {
+            Some(id) => storage.get_page(id)?,
+            None => None,
+        };
+        outgoing.push(match page {
+            Some(page) => LinkedPage::from_page(&page),
+            None => LinkedPage {
+                id: None,
+                title: slug.replace('+', " "),
+                url: format!("{}{}", BASE_URL, slug),
+            },
+        });
+    }
+
+    let mut incoming = Vec::new();
+    for id in storage.links_to(page_id)? {
+        if let Some(page) = storage.get_page(id)? {
+            incoming.push(LinkedPage::from_page(&page));
+        }
+    }
+
+    Ok(PageLinks { outgoing, incoming })
+}
+
+/// 把全部页面和它们之间的链接写入 `output`，链接到不存在的页面的边被忽略
+///
+/// 出错或取消时删除写了一半的文件
+pub fn export_graph(
+    storage: &Storage,
+    format: GraphFormat,
+    output: &Path,
+    cancel: &CancelToken,
+) -> Result<GraphSummary, LinkError> {
+    println!("正在导出链接图到 {}...", output.display());
+    let result = write_graph(storage, format, output, cancel);
+    if result.is_err() {
+        std::fs::remove_file(output).ok();
+    }
+    result
+}
+
+fn write_graph(
+    storage: &Storage,
+    format: GraphFormat,
+    output: &Path,
+    cancel: &CancelToken,
+) -> Result<GraphSummary, LinkError> {
+    let mut writer = BufWriter::new(File::create(output)?);
+    match format {
+        GraphFormat::Dot => writeln!(writer, "digraph nlab {{")?,
+        GraphFormat::Graphml => {
+            writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+            writeln!(
+                writer,
+                r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+            )?;
+            writeln!(
+                writer,
+                r#"  <key id="title" for="node" attr.name="title" attr.type="string"/>"#
+            )?;
+            writeln!(
+                writer,
+                r#"  <key id="url" for="node" attr.name="url" attr.type="string"/>"#
+            )?;
+            writeln!(writer, r#"  <graph id="nlab" edgedefault="directed">"#)?;
+        }
+    }
+
+    let (mut nodes, mut edges) = (0, 0);
+    for page in storage.iter_pages() {
+        if cancel.is_cancelled() {
+            return Err(LinkError::Cancelled);
+        }
+        let page = page?;
+        match format {
+            GraphFormat::Dot => writeln!(
+                writer,
+                "  {} [label=\"{}\", URL=\"{}\"];",
+                page.id,
+                dot_escape(&page.title),
+                dot_escape(&page.url)
+            )?,
+            GraphFormat::Graphml => writeln!(
+                writer,
+                r#"    <node id="n{}"><data key="title">{}</data><data key="url">{}</data></node>"#,
+                page.id,
+                xml_escape(&page.title),
+                xml_escape(&page.url)
+            )?,
+        }
+        nodes += 1;
+
+        // 不同写法的页面名可能指向同一页面
+        let mut targets = HashSet::new();
+        for slug in &page.links {
+            let Some(target) = storage.get_page_id_by_slug(slug)? else {
+                continue;
+            };
+            if target == page.id || !targets.insert(target) {
+                continue;
+            }
+            match format {
+                GraphFormat::Dot => writeln!(writer, "  {} -> {};", page.id, target)?,
+                GraphFormat::Graphml => writeln!(
+                    writer,
+                    r#"    <edge source="n{}" target="n{}"/>"#,
+                    page.id, target
+                )?,
+            }
+            edges += 1;
+        }
+    }
+
+    match format {
+        GraphFormat::Dot => writeln!(writer, "}}")?,
+        GraphFormat::Graphml => {
+            writeln!(writer, "  </graph>")?;
+            writeln!(writer, "</graphml>")?;
+        }
+    }
+    writer.flush()?;
+
+    println!("链接图导出完成: {} 个页面, {} 条链接", nodes, edges);
+    Ok(GraphSummary {
+        path: output.display().to_string(),
+        nodes,
+        edges,
+    })
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', " ")
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn page(id: u64, slug: &str, title: &str, links: &[&str]) -> NLabPage {
+        NLabPage::new(
+            id,
+            format!("pages/{}/{}/content.html", id, id),
+            title.to_string(),
+            format!("{}{}", BASE_URL, slug),
+            "content".to_string(),
+            links.iter().map(|link| link.to_string()).collect(),
+            Vec::new(),
+        )
+    }
+
+    fn create_test_storage(temp_dir: &TempDir) -> Storage {
+        let storage = Storage::new(temp_dir.path().join("db").to_str().unwrap()).unwrap();
+        storage
+            .save_pages_batch(&[
+                page(
+                    1,
+                    "topos",
+                    "topos",
+                    &["sheaf", "category", "Grothendieck+topos"],
+                ),
+                page(2, "sheaf", "sheaf", &["topos", "presheaf"]),
+                page(3, "category", "category <of \"things\">", &[]),
+            ])
+            .unwrap();
+        storage
+    }
+
+    #[test]
+    fn test_page_links() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = create_test_storage(&temp_dir);
+
+        let links = page_links(&storage, 1).unwrap();
+        let outgoing: Vec<_> = links
+            .outgoing
+            .iter()
+            .map(|link| (link.id, link.title.as_str()))
+            .collect();
+        assert_eq!(
+            outgoing,
+            vec![
+                (Some(2), "sheaf"),
+                (Some(3), "category <of \"things\">"),
+                (None, "Grothendieck topos"),
+            ]
+        );
+        assert_eq!(
+            links.incoming,
+            vec![LinkedPage::from_page(&page(2, "sheaf", "sheaf", &[]))]
+        );
+
+        let links = page_links(&storage, 3).unwrap();
+        assert!(links.outgoing.is_empty());
+        assert_eq!(links.incoming.len(), 1);
+        assert!(page_links(&storage, 4).is_err());
+    }
+
+    #[test]
+    fn test_export_graph() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = create_test_storage(&temp_dir);
+
+        let dot = temp_dir.path().join("links.dot");
+        let summary = export_graph(&storage, GraphFormat::Dot, &dot, &CancelToken::new()).unwrap();
+        assert_eq!((summary.nodes, summary.edges), (3, 3));
+        let text = std::fs::read_to_string(&dot).unwrap();
+        assert!(text.starts_with("digraph nlab {"));
+        assert!(text.contains("  1 -> 2;\n  1 -> 3;\n"));
+        assert!(text.contains(r#"label="category <of \"things\">""#));
+
+        let graphml = temp_dir.path().join("links.graphml");
+        export_graph(
+            &storage,
+            GraphFormat::Graphml,
+            &graphml,
+            &CancelToken::new(),
+        )
+        .unwrap();
+        let text = std::fs::read_to_string(&graphml).unwrap();
+        assert!(text.contains(r#"<edge source="n2" target="n1"/>"#));
+        assert!(text.contains("category &lt;of &quot;things&quot;&gt;"));
+        assert!(text.trim_end().ends_with("</graphml>"));
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let cancelled = temp_dir.path().join("cancelled.dot");
+        assert!(export_graph(&storage, GraphFormat::Dot, &cancelled, &cancel).is_err());
+        assert!(!cancelled.exists());
+    }
+}
@@ -0,0 +1,186 @@
+use crate::models::NLabPage;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use walkdir::WalkDir;
+
+const MB: u64 = 1024 * 1024;
+/// 同步前至少需要的可用空间，用于 fetch 和增量更新索引
+pub const MIN_FREE_SPACE: u64 = 256 * MB;
+/// 数据库（sled 的写放大）和搜索索引相对于页面文本大小的估计倍数
+const DATA_SIZE_FACTOR: u64 = 4;
+
+/// 开始写入之前发现磁盘空间不足
+#[derive(Error, Debug)]
+#[error(
+    "Not enough disk space in {}: about {} MB needed, only {} MB available",
+    .dir.display(),
+    .required / MB,
+    .available / MB
+)]
+pub struct InsufficientSpace {
+    pub dir: PathBuf,
+    pub required: u64,
+    pub available: u64,
+}
+
+/// 各数据目录占用的磁盘空间（字节）
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DiskUsage {
+    pub mirror: u64,
+    pub database: u64,
+    pub index: u64,
+    pub snapshots: u64,
+    /// 镜像是否保留工作目录
+    pub worktree: bool,
+}
+
+impl DiskUsage {
+    pub fn total(&self) -> u64 {
+        self.mirror + self.database + self.index + self.snapshots
+    }
+}
+
+/// 一次维护的结果
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MaintenanceReport {
+    pub before: DiskUsage,
+    pub after: DiskUsage,
+    /// 镜像重新打包后的对象数；页面来源不是 git 时为 `None`
+    pub packed_objects: Option<usize>,
+    /// 合并前的索引段数
+    pub merged_segments: usize,
+}
+
+/// 目录下所有文件的大小之和，不跟随符号链接；目录不存在时为 0
+pub fn dir_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// `dir` 所在磁盘的可用空间；`dir` 不存在时查询最近的上级目录，查询失败时返回 `None`
+pub fn available_space(dir: &Path) -> Option<u64> {
+    let existing = dir
+        .ancestors()
+        .map(|ancestor| {
+            if ancestor.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                ancestor
+            }
+        })
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(Path::new("."));
+
+    match fs4::available_space(existing) {
+        Ok(available) => Some(available),
+        Err(e) => {
+            eprintln!(
+                "failed to query free space in {}: {}",
+                existing.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// 确认 `dir` 所在的磁盘至少有 `required` 字节可用
+///
+/// 无法查询可用空间时不阻止操作
+pub fn ensure_free_space(dir: &Path, required: u64) -> Result<(), InsufficientSpace> {
+    match available_space(dir) {
+        Some(available) if available < required => Err(InsufficientSpace {
+            dir: dir.to_path_buf(),
+            required,
+            available,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// 估计把 `pages` 写入数据库和搜索索引需要的空间
+pub fn estimated_data_size(pages: &[NLabPage]) -> u64 {
+    let text: usize = pages
+        .iter()
+        .map(|page| {
+            std::mem::size_of_val(&page.id)
+                + page.title.len()
+                + page.file_path.len()
+                + page.url.len()
+                + page.content.len()
+        })
+        .sum();
+    text as u64 * DATA_SIZE_FACTOR
+}
+
+/// 错误（或它的某个来源）是否由磁盘空间不足引起
+///
+/// git2、tantivy 和解析器的错误有时只保留了文字描述，因此也检查错误信息
+pub fn is_disk_full(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        if error.is::<InsufficientSpace>() {
+            return true;
+        }
+        if let Some(io_error) = error.downcast_ref::<std::io::Error>() {
+            if io_error.kind() == std::io::ErrorKind::StorageFull {
+                return true;
+            }
+        }
+        let message = error.to_string();
+        if message.contains("No space left on device")
+            || message.contains("not enough space on the disk")
+        {
+            return true;
+        }
+        current = error.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_dir_size() {
+        let temp_dir = TempDir::new().unwrap();
+        assert_eq!(dir_size(&temp_dir.path().join("missing")), 0);
+
+        std::fs::create_dir_all(temp_dir.path().join("a/b")).unwrap();
+        std::fs::write(temp_dir.path().join("a/one.txt"), [0u8; 100]).unwrap();
+        std::fs::write(temp_dir.path().join("a/b/two.txt"), [0u8; 23]).unwrap();
+        assert_eq!(dir_size(temp_dir.path()), 123);
+        assert_eq!(dir_size(&temp_dir.path().join("a/one.txt")), 100);
+    }
+
+    #[test]
+    fn test_ensure_free_space() {
+        let temp_dir = TempDir::new().unwrap();
+        let missing = temp_dir.path().join("not/created/yet");
+        assert!(ensure_free_space(&missing, 0).is_ok());
+
+        let error = ensure_free_space(&missing, u64::MAX).unwrap_err();
+        assert_eq!(error.dir, missing);
+        assert!(is_disk_full(&error));
+    }
+
+    #[test]
+    fn test_is_disk_full() {
+        let full = std::io::Error::from(std::io::ErrorKind::StorageFull);
+        assert!(is_disk_full(&full));
+
+        let git_error = git2::Error::from_str("failed to write pack: No space left on device");
+        assert!(is_disk_full(&git_error));
+
+        let other = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        assert!(!is_disk_full(&other));
+    }
+}
@@ -2,14 +2,43 @@ use bincode::{Decode, Encode};
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq)]
 pub struct SearchIndex {
+    /// nLab 的页面编号，用于查询链接
+    pub id: u64,
     pub title: String,
+    /// 匹配的是页面中的一节时带有 `#anchor`
     pub url: String,
+    /// 页面文件相对于仓库根目录的路径，用于查看修改历史
+    pub file_path: String,
+    /// 匹配的一节的标题
+    pub section: Option<String>,
+}
+
+/// `get_status` 命令的结果
+#[derive(serde::Serialize, Clone, PartialEq)]
+pub struct IndexStatus {
+    pub indexed_commit: Option<String>,
+    /// 最近一次成功同步的时间（Unix 时间戳）
+    pub indexed_at: Option<i64>,
+    pub page_count: usize,
+    pub duration_ms: Option<u64>,
+    pub head_commit: Option<String>,
+    /// 最近一次 fetch 得到的远程提交
+    pub remote_commit: Option<String>,
+    pub remote_ahead: Option<usize>,
+    /// 最近一次同步失败时的错误信息
+    pub last_error: Option<String>,
+    /// 下一次自动同步的时间（Unix 时间戳），未设置定时同步时为 `None`
+    pub next_sync: Option<i64>,
+    /// 存储、索引和镜像 HEAD 不一致时的说明
+    pub inconsistency: Option<String>,
+    pub summary: String,
 }
 
 // 定义一个结构体来存储提取到的数据
 #[derive(Debug, Encode, Decode, Clone)]
 pub struct NLabPage {
-    pub id: String,
+    /// nLab 的页面编号，见 `page_id_from_path`
+    pub id: u64,
     /// 页面标题
     pub title: String,
     /// 文件相对于仓库根目录的路径
@@ -19,21 +48,135 @@ pub struct NLabPage {
 
     /// 页面的文本内容（已清理格式）
     pub content: String,
+
+    /// 正文中链接到的其他页面名（已用 `normalize_slug` 规范化），按出现顺序去重
+    pub links: Vec<String>,
+
+    /// 页面中公式的 TeX 源码，按出现顺序去重；不包含在 `content` 中
+    pub math: Vec<String>,
+
+    /// 正文按 `h2`/`h3`/`h4` 标题分成的节，不包括第一个标题之前的部分
+    pub sections: Vec<Section>,
+
+    /// 页面末尾 `category:` 行中的分类，例如 `people`、`reference`
+    pub categories: Vec<String>,
+
+    /// 侧栏 "Context" 中的领域，例如 `Category theory`
+    pub contexts: Vec<String>,
+}
+
+/// 正文中从一个标题开始、到下一个标题为止的一节
+#[derive(Debug, Encode, Decode, Clone, PartialEq)]
+pub struct Section {
+    pub heading: String,
+    /// 标题的 `id`，用于链接到页面中的这一节
+    pub anchor: Option<String>,
+    /// 这一节（包括标题）在 `NLabPage::content` 中的字节范围
+    pub start: usize,
+    pub end: usize,
 }
 
 impl NLabPage {
-    /// 从文件路径创建 ID
-    pub fn new(file_path: String, title: String, url: String, content: String) -> Self {
+    pub fn new(
+        id: u64,
+        file_path: String,
+        title: String,
+        url: String,
+        content: String,
+        links: Vec<String>,
+        math: Vec<String>,
+    ) -> Self {
         Self {
-            id: title.clone(),
+            id,
             title,
             file_path,
             url,
             content,
+            links,
+            math,
+            sections: Vec::new(),
+            categories: Vec::new(),
+            contexts: Vec::new(),
         }
     }
-    
-    pub fn id_from_url(url: &str) -> Option<String> {
-        url.split("/show/").nth(1).map(|s| s.to_string())
+
+    pub fn with_sections(mut self, sections: Vec<Section>) -> Self {
+        self.sections = sections;
+        self
+    }
+
+    pub fn with_facets(mut self, categories: Vec<String>, contexts: Vec<String>) -> Self {
+        self.categories = categories;
+        self.contexts = contexts;
+        self
+    }
+
+    pub fn section_text(&self, section: &Section) -> &str {
+        self.content
+            .get(section.start..section.end)
+            .unwrap_or_default()
+            .trim()
+    }
+
+    /// URL 中 `/show/` 之后的页面名，例如 `category+theory`
+    pub fn slug(&self) -> Option<&str> {
+        Self::slug_from_url(&self.url)
+    }
+
+    pub fn slug_from_url(url: &str) -> Option<&str> {
+        url.split("/show/").nth(1)
+    }
+}
+
+/// 页面文件所在目录的名字就是 nLab 的页面编号，例如 `pages/4/7/4/1/1474/content.html` 为 1474
+pub fn page_id_from_path(file_path: &str) -> Option<u64> {
+    std::path::Path::new(file_path)
+        .parent()?
+        .file_name()?
+        .to_str()?
+        .parse()
+        .ok()
+}
+
+/// 规范化 URL 中的页面名：去掉 `#` 锚点和 `?` 查询，解码百分号转义，空格写作 `+`
+///
+/// 编辑链接和正文链接对同一页面的写法不一定相同，比较或查找页面名前都要先规范化
+pub fn normalize_slug(slug: &str) -> String {
+    let slug = slug.split(['#', '?']).next().unwrap_or_default();
+    let slug = slug.trim_end_matches('/');
+    let bytes = slug.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| slug.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).replace(' ', "+")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_slug() {
+        assert_eq!(normalize_slug("category+theory"), "category+theory");
+        assert_eq!(normalize_slug("category%20theory#idea"), "category+theory");
+        assert_eq!(normalize_slug("topos?source=x"), "topos");
+        assert_eq!(normalize_slug("Poincar%C3%A9+duality"), "Poincaré+duality");
+        assert_eq!(normalize_slug("100%25"), "100%");
+        assert_eq!(normalize_slug("50%"), "50%");
     }
 }
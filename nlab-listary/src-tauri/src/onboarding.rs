@@ -0,0 +1,342 @@
+use crate::cancel::CancelToken;
+use crate::config::{Config, Mirror, MirrorConfig};
+use crate::progress::ProgressSink;
+use crate::source::{self, SourceError};
+use crate::{git_ops, maintenance};
+use crate::{DB_PATH, GIT_REPO_PATH, INDEX_PATH};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// 首次运行的结果，保存在应用配置目录中（不在数据目录里，启动时才知道数据目录在哪）
+pub const SETUP_FILE: &str = "setup.json";
+/// 导入索引包时的临时解压目录
+const IMPORT_PATH: &str = "nlab_import";
+
+const GB: u64 = 1024 * 1024 * 1024;
+/// 还没有页面时对数据库和搜索索引大小的估计
+const DATA_SIZE_ESTIMATE: u64 = 2 * GB;
+/// 页面压缩包解压后大约是压缩包的几倍
+const ARCHIVE_EXPANSION: u64 = 5;
+/// 索引包解压后大约是压缩包的几倍
+const INDEX_BUNDLE_EXPANSION: u64 = 3;
+
+#[derive(Error, Debug)]
+pub enum SetupError {
+    #[error("Failed to access setup file: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Invalid setup file: {0}")]
+    ParseError(#[from] serde_json::Error),
+
+    #[error("Failed to extract index bundle: {0}")]
+    SourceError(#[from] SourceError),
+
+    #[error("{0}")]
+    Invalid(String),
+}
+
+/// 首次运行时选择的数据来源
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SetupChoice {
+    /// 从镜像克隆；`url` 为空时使用配置中的镜像，也可以是 bundle 文件
+    Clone { url: String, shallow: bool },
+    /// 从本机已有的 nlab-content-html 克隆复制镜像
+    ExistingClone { path: String },
+    /// 导出的页面目录或 `.tar.gz`/`.zip` 压缩包，作为页面来源
+    Archive { path: String },
+    /// 别的机器上构建好的数据库和索引（可以带上镜像）打包成的压缩包
+    IndexBundle { path: String },
+}
+
+/// 首次运行选择的数据目录和数据来源
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SetupRecord {
+    pub data_dir: PathBuf,
+    /// 升级前已经有数据、没有经过首次运行流程时为 `None`
+    pub choice: Option<SetupChoice>,
+    pub completed_at: i64,
+}
+
+impl SetupRecord {
+    /// 读取记录；还没有完成首次运行时返回 `None`
+    pub fn load(path: &Path) -> Result<Option<Self>, SetupError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)?;
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SetupError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// 首次运行是否还需要选择，以及建议的数据目录
+#[derive(Debug, Clone, Serialize)]
+pub struct OnboardingStatus {
+    pub required: bool,
+    pub default_data_dir: String,
+}
+
+/// 所选来源需要的空间（字节）和数据目录所在磁盘的可用空间
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SpaceEstimate {
+    pub required: u64,
+    pub available: Option<u64>,
+}
+
+/// 检查选择的路径，把相对路径转换为绝对路径，使保存的选择不依赖启动时的工作目录
+pub fn resolve(choice: SetupChoice) -> Result<SetupChoice, SetupError> {
+    let existing = |path: &str| {
+        fs::canonicalize(path.trim())
+            .map_err(|e| SetupError::Invalid(format!("{}: {}", path.trim(), e)))
+    };
+
+    match choice {
+        SetupChoice::Clone { url, shallow } => Ok(SetupChoice::Clone {
+            url: url.trim().to_string(),
+            shallow,
+        }),
+        SetupChoice::ExistingClone { path } => {
+            let path = existing(&path)?;
+            if git2::Repository::open(&path).is_err() {
+                return Err(SetupError::Invalid(format!(
+                    "{} is not a git repository",
+                    path.display()
+                )));
+            }
+            Ok(SetupChoice::ExistingClone {
+                path: path.to_string_lossy().into_owned(),
+            })
+        }
+        SetupChoice::Archive { path } => {
+            let path = existing(&path)?;
+            if !path.is_dir() && !source::is_archive(&path) {
+                return Err(SetupError::Invalid(format!(
+                    "{} is neither a directory nor a .tar.gz/.zip archive",
+                    path.display()
+                )));
+            }
+            Ok(SetupChoice::Archive {
+                path: path.to_string_lossy().into_owned(),
+            })
+        }
+        SetupChoice::IndexBundle { path } => {
+            let path = existing(&path)?;
+            if !path.is_file() || !source::is_archive(&path) {
+                return Err(SetupError::Invalid(format!(
+                    "{} is not a .tar.gz/.zip archive",
+                    path.display()
+                )));
+            }
+            Ok(SetupChoice::IndexBundle {
+                path: path.to_string_lossy().into_owned(),
+            })
+        }
+    }
+}
+
+/// 把选择写入配置：克隆的镜像排在最前，页面目录或压缩包作为页面来源
+///
+/// bundle 文件和已有的克隆只用于首次克隆，见 `initial_mirror`
+pub fn apply_to_config(choice: &SetupChoice, config: &mut Config) {
+    config.source = None;
+    match choice {
+        SetupChoice::Clone { url, shallow } => {
+            config.mirror.shallow = *shallow;
+            if !url.is_empty() && !git_ops::is_bundle(url) {
+                config.mirror.mirrors.retain(|mirror| &mirror.url != url);
+                config.mirror.mirrors.insert(0, Mirror::new(url));
+            }
+        }
+        SetupChoice::Archive { path } => config.source = Some(path.clone()),
+        SetupChoice::ExistingClone { .. } | SetupChoice::IndexBundle { .. } => {}
+    }
+}
+
+/// 首次克隆时优先使用的镜像，不写入配置，以后的同步仍使用配置中的镜像
+pub fn initial_mirror(choice: &SetupChoice) -> Option<Mirror> {
+    match choice {
+        SetupChoice::Clone { url, .. } if git_ops::is_bundle(url) => Some(Mirror::new(url)),
+        SetupChoice::ExistingClone { path } => Some(Mirror::new(path)),
+        _ => None,
+    }
+}
+
+/// 估计所选来源在数据目录中需要的空间；`mirror` 为已写入选择的镜像配置
+pub fn estimate(data_dir: &Path, choice: &SetupChoice, mirror: &MirrorConfig) -> SpaceEstimate {
+    let file_size = |path: &str| fs::metadata(path).map_or(0, |metadata| metadata.len());
+    let required = match choice {
+        SetupChoice::Clone { .. } => {
+            let mut mirror = mirror.clone();
+            if let Some(initial) = initial_mirror(choice) {
+                mirror.mirrors.insert(0, initial);
+            }
+            git_ops::estimated_clone_size(&mirror) + DATA_SIZE_ESTIMATE
+        }
+        SetupChoice::ExistingClone { path } => {
+            maintenance::dir_size(Path::new(path)) + DATA_SIZE_ESTIMATE
+        }
+        // 目录直接读取，压缩包先解压到数据目录
+        SetupChoice::Archive { path } if Path::new(path).is_dir() => DATA_SIZE_ESTIMATE,
+        SetupChoice::Archive { path } => file_size(path) * ARCHIVE_EXPANSION + DATA_SIZE_ESTIMATE,
+        SetupChoice::IndexBundle { path } => file_size(path) * INDEX_BUNDLE_EXPANSION,
+    };
+
+    SpaceEstimate {
+        required,
+        available: maintenance::available_space(data_dir),
+    }
+}
+
+/// 把索引包中的数据库、搜索索引和（可选的）镜像解压到 `data_dir`，替换已有的目录
+///
+/// 包中可以有一层顶级目录，例如 `nlab-index/nlab_page_data.db/...`
+pub fn import_index_bundle(
+    archive: &Path,
+    data_dir: &Path,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<(), SetupError> {
+    let staging = data_dir.join(IMPORT_PATH);
+    let select = |path: &Path| {
+        [DB_PATH, INDEX_PATH, GIT_REPO_PATH]
+            .iter()
+            .find_map(|name| source::path_from(path, name))
+    };
+    let count = source::extract_selected(archive, &staging, select, progress, cancel)?;
+
+    let result = if staging.join(DB_PATH).is_dir() && staging.join(INDEX_PATH).is_dir() {
+        move_into(&staging, data_dir)
+    } else {
+        Err(SetupError::Invalid(format!(
+            "{} does not contain {} and {}",
+            archive.display(),
+            DB_PATH,
+            INDEX_PATH
+        )))
+    };
+    fs::remove_dir_all(&staging).ok();
+    result?;
+
+    println!("索引包导入完成: {} 个文件", count);
+    Ok(())
+}
+
+fn move_into(staging: &Path, data_dir: &Path) -> Result<(), SetupError> {
+    for name in [DB_PATH, INDEX_PATH, GIT_REPO_PATH] {
+        let (from, to) = (staging.join(name), data_dir.join(name));
+        if !from.exists() {
+            continue;
+        }
+        if to.exists() {
+            fs::remove_dir_all(&to)?;
+        }
+        fs::rename(&from, &to)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::NoProgress;
+    use crate::REPO_URL;
+    use flate2::write::GzEncoder;
+    use std::fs::File;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_apply_to_config() {
+        let mut config = Config::default();
+        let url = "https://gitee.com/example/nlab-content-html.git";
+        apply_to_config(
+            &SetupChoice::Clone {
+                url: url.to_string(),
+                shallow: true,
+            },
+            &mut config,
+        );
+        assert!(config.mirror.shallow);
+        assert_eq!(
+            config.mirror.mirrors,
+            vec![Mirror::new(url), Mirror::new(REPO_URL)]
+        );
+
+        let archive = SetupChoice::Archive {
+            path: "/data/nlab.tar.gz".to_string(),
+        };
+        apply_to_config(&archive, &mut config);
+        assert_eq!(config.source.as_deref(), Some("/data/nlab.tar.gz"));
+        assert!(initial_mirror(&archive).is_none());
+
+        let existing = SetupChoice::ExistingClone {
+            path: "/data/nlab-content-html".to_string(),
+        };
+        apply_to_config(&existing, &mut config);
+        assert!(config.source.is_none());
+        assert_eq!(
+            initial_mirror(&existing),
+            Some(Mirror::new("/data/nlab-content-html"))
+        );
+    }
+
+    #[test]
+    fn test_import_index_bundle() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("nlab-index.tar.gz");
+        let mut builder = tar::Builder::new(GzEncoder::new(
+            File::create(&archive).unwrap(),
+            flate2::Compression::default(),
+        ));
+        for name in [
+            "nlab-index/nlab_page_data.db/storage/db",
+            "nlab-index/nlab_page_index/index/meta.json",
+            "nlab-index/notes.txt",
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, &b"data"[..])
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let choice = resolve(SetupChoice::IndexBundle {
+            path: archive.to_string_lossy().into_owned(),
+        })
+        .unwrap();
+        assert_eq!(
+            estimate(temp_dir.path(), &choice, &MirrorConfig::default()).required,
+            fs::metadata(&archive).unwrap().len() * INDEX_BUNDLE_EXPANSION
+        );
+
+        let data_dir = temp_dir.path().join("data");
+        fs::create_dir_all(data_dir.join(DB_PATH).join("stale")).unwrap();
+        import_index_bundle(&archive, &data_dir, &NoProgress, &CancelToken::new()).unwrap();
+        assert!(data_dir.join("nlab_page_data.db/storage/db").is_file());
+        assert!(data_dir.join("nlab_page_index/index/meta.json").is_file());
+        assert!(!data_dir.join(DB_PATH).join("stale").exists());
+        assert!(!data_dir.join("notes.txt").exists());
+        assert!(!data_dir.join(IMPORT_PATH).exists());
+
+        assert!(resolve(SetupChoice::IndexBundle {
+            path: temp_dir
+                .path()
+                .join("missing.zip")
+                .to_string_lossy()
+                .into_owned(),
+        })
+        .is_err());
+    }
+}
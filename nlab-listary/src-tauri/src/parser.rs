@@ -1,8 +1,12 @@
-use crate::models::NLabPage;
+use crate::cancel::CancelToken;
+use crate::models::{normalize_slug, page_id_from_path, NLabPage, Section};
+use crate::progress::{Phase, Progress, ProgressSink};
 use rayon::prelude::*;
-use scraper::{Html, Selector};
+use scraper::{CaseSensitivity, ElementRef, Html, Selector};
+use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Mutex;
 use thiserror::Error;
 use walkdir::WalkDir;
@@ -28,14 +32,30 @@ pub enum ParseHtmlError {
     #[error("Unexpected href format: {0}")]
     UnexpectedHrefFormat(String),
 
+    #[error("No page ID in path: {0}")]
+    MissingPageId(String),
+
     #[error("Failed to parse selector")]
     SelectorParseError,
 
     #[error("WalkDir error")]
     WalkDirError(#[from] walkdir::Error),
+
+    #[error("Git error: {0}")]
+    GitError(#[from] git2::Error),
+
+    #[error("Parsing cancelled")]
+    Cancelled,
 }
 
-pub fn index_local_files(repo_path: &Path) -> Result<Vec<NLabPage>, ParseHtmlError> {
+/// 每解析这么多个文件报告一次进度
+const PARSE_PROGRESS_STEP: usize = 100;
+
+pub fn index_local_files(
+    repo_path: &Path,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<Vec<NLabPage>, ParseHtmlError> {
     println!("\n--- 开始遍历和解析本地文件 ---");
 
     // 先收集所有HTML文件路径
@@ -52,23 +72,156 @@ pub fn index_local_files(repo_path: &Path) -> Result<Vec<NLabPage>, ParseHtmlErr
     let total_files = html_files.len();
     println!("找到 {} 个HTML文件", total_files);
 
+    parse_files(&html_files, repo_path, progress, cancel)
+}
+
+/// 从 `commit` 的树中只解析给定的文件（路径相对于仓库根目录），用于增量同步
+///
+/// 和 `index_commit` 一样直接读取对象库，工作目录被删除后也可以使用；树中不存在的文件被忽略
+pub fn index_commit_files(
+    repo_path: &Path,
+    commit: git2::Oid,
+    relative_paths: &[String],
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<Vec<NLabPage>, ParseHtmlError> {
+    println!("\n--- 开始解析 {} 个变化的文件 ---", relative_paths.len());
+
+    let repo = git2::Repository::open(repo_path)?;
+    let tree = repo.find_commit(commit)?.tree()?;
+    let blobs: Vec<(String, git2::Oid)> = relative_paths
+        .iter()
+        .filter_map(|relative| {
+            let entry = tree.get_path(Path::new(relative)).ok()?;
+            Some((relative.clone(), entry.id()))
+        })
+        .collect();
+
+    parse_blobs(repo_path, &blobs, progress, cancel)
+}
+
+/// 遍历 `commit` 的树，直接从对象库读取 HTML 文件并解析，不需要检出工作目录
+///
+/// `repo_path` 可以是裸仓库
+pub fn index_commit(
+    repo_path: &Path,
+    commit: git2::Oid,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<Vec<NLabPage>, ParseHtmlError> {
+    println!("\n--- 开始从提交 {} 解析文件 ---", commit);
+
+    let repo = git2::Repository::open(repo_path)?;
+    let tree = repo.find_commit(commit)?.tree()?;
+
+    let mut blobs: Vec<(String, git2::Oid)> = Vec::new();
+    tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+        let is_html = entry.name().is_some_and(|name| name.ends_with(".html"));
+        if is_html && entry.kind() == Some(git2::ObjectType::Blob) {
+            blobs.push((format!("{}{}", dir, entry.name().unwrap()), entry.id()));
+        }
+        git2::TreeWalkResult::Ok
+    })?;
+    println!("找到 {} 个HTML文件", blobs.len());
+
+    parse_blobs(repo_path, &blobs, progress, cancel)
+}
+
+/// 并行解析对象库中的 HTML 文件，`blobs` 为相对路径和对应的 blob
+fn parse_blobs(
+    repo_path: &Path,
+    blobs: &[(String, git2::Oid)],
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<Vec<NLabPage>, ParseHtmlError> {
+    // Repository 不能在线程间共享，每个工作线程各自打开一次
+    parse_entries(
+        blobs,
+        || git2::Repository::open(repo_path).ok(),
+        |repo, (relative_path, oid)| {
+            let repo = repo
+                .as_ref()
+                .ok_or_else(|| git2::Error::from_str("failed to open repository"))?;
+            let blob = repo.find_blob(*oid)?;
+            let html_content = String::from_utf8_lossy(blob.content());
+            parse_html_content(relative_path.clone(), &html_content)
+        },
+        |(relative_path, _)| relative_path.clone(),
+        progress,
+        cancel,
+    )
+}
+
+fn parse_files(
+    html_files: &[PathBuf],
+    repo_path: &Path,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<Vec<NLabPage>, ParseHtmlError> {
+    parse_entries(
+        html_files,
+        || (),
+        |_, path| parse_html_file(path, repo_path),
+        |path| path.display().to_string(),
+        progress,
+        cancel,
+    )
+}
+
+/// 并行解析 `entries`，跳过解析失败的条目
+///
+/// `init` 为每个工作线程创建一次状态，`parse` 解析单个条目，`describe` 用于打印跳过的条目
+fn parse_entries<T, S>(
+    entries: &[T],
+    init: impl Fn() -> S + Sync + Send,
+    parse: impl Fn(&mut S, &T) -> Result<Option<NLabPage>, ParseHtmlError> + Sync + Send,
+    describe: impl Fn(&T) -> String + Sync + Send,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<Vec<NLabPage>, ParseHtmlError>
+where
+    T: Sync,
+{
     // 使用 Mutex 来安全地收集错误信息
     let skipped_files = Mutex::new(Vec::new());
+    let total_files = entries.len();
+    let processed = AtomicUsize::new(0);
 
     // 并行处理所有文件
-    let pages: Vec<NLabPage> = html_files
+    let pages: Vec<NLabPage> = entries
         .par_iter()
-        .filter_map(|path| match parse_html_file(path, repo_path) {
-            Ok(Some(page)) => Some(page),
-            Ok(None) => None,
-            Err(e) => {
-                eprintln!("⚠ Skipping file due to error: {}", path.display());
-                skipped_files.lock().unwrap().push((path.clone(), e));
-                None
+        .map_init(init, |state, entry| {
+            // 取消后跳过剩余文件，rayon 无法中途停止迭代
+            if cancel.is_cancelled() {
+                return None;
+            }
+            let result = parse(state, entry);
+
+            let done = processed.fetch_add(1, Ordering::Relaxed) + 1;
+            if done.is_multiple_of(PARSE_PROGRESS_STEP) || done == total_files {
+                progress.report(Progress::new(Phase::Parse, done, total_files));
+            }
+
+            match result {
+                Ok(Some(page)) => Some(page),
+                Ok(None) => None,
+                Err(e) => {
+                    let name = describe(entry);
+                    eprintln!("⚠ Skipping file due to error: {}", name);
+                    progress.skipped(&name);
+                    skipped_files.lock().unwrap().push((name, e));
+                    None
+                }
             }
         })
+        .flatten()
         .collect();
 
+    if cancel.is_cancelled() {
+        println!("--- 解析已取消 ---");
+        return Err(ParseHtmlError::Cancelled);
+    }
+
     let skipped = skipped_files.into_inner().unwrap();
     let parsed_count = pages.len();
     let skipped_count = skipped.len();
@@ -79,8 +232,8 @@ pub fn index_local_files(repo_path: &Path) -> Result<Vec<NLabPage>, ParseHtmlErr
     if skipped_count > 0 {
         println!("跳过: {skipped_count} 个文件\n");
         println!("跳过的文件列表:");
-        for (path, error) in &skipped {
-            println!("  - {}: {}", path.display(), error);
+        for (name, error) in &skipped {
+            println!("  - {}: {}", name, error);
         }
     }
 
@@ -101,17 +254,39 @@ pub fn parse_html_file(
             path: file_path.to_path_buf(),
             source: e,
         })?;
-    let document = Html::parse_document(&html_content);
+
+    parse_html_content(relative_path, &html_content)
+}
+
+/// 从 HTML 文本中提取页面，`relative_path` 为文件相对于仓库根目录的路径
+pub fn parse_html_content(
+    relative_path: String,
+    html_content: &str,
+) -> Result<Option<NLabPage>, ParseHtmlError> {
+    let id = page_id_from_path(&relative_path)
+        .ok_or_else(|| ParseHtmlError::MissingPageId(relative_path.clone()))?;
+    let document = Html::parse_document(html_content);
 
     // 提取标题
     let title = extract_title(&document);
 
-    // 提取内容
-    let content = extract_content(&document);
+    // 提取内容和各节，公式单独保存
+    let fragments = revision_fragments(&document);
+    let (content, sections) = extract_content_and_sections(&fragments);
+    let math = extract_math(&fragments);
 
     let url = extract_url(&document)?;
 
-    Ok(Some(NLabPage::new(relative_path, title, url, content)))
+    let links = extract_links(&document, NLabPage::slug_from_url(&url));
+
+    let categories = extract_categories(&document);
+    let contexts = extract_contexts(&document);
+
+    Ok(Some(
+        NLabPage::new(id, relative_path, title, url, content, links, math)
+            .with_sections(sections)
+            .with_facets(categories, contexts),
+    ))
 }
 
 fn extract_title(document: &Html) -> String {
@@ -131,15 +306,231 @@ fn extract_title(document: &Html) -> String {
         })
 }
 
-fn extract_content(document: &Html) -> String {
+/// 正文文本，以及按标题分成的节在其中的范围
+fn extract_content_and_sections(fragments: &[Fragment]) -> (String, Vec<Section>) {
+    let mut content = String::new();
+    let mut sections: Vec<Section> = Vec::new();
+    let mut first = true;
+
+    for fragment in fragments {
+        match fragment {
+            Fragment::Text(text) => {
+                if !first {
+                    content.push(' ');
+                }
+                first = false;
+                content.push_str(text);
+            }
+            Fragment::Heading { heading, anchor } => {
+                if let Some(last) = sections.last_mut() {
+                    last.end = content.len();
+                }
+                sections.push(Section {
+                    heading: heading.clone(),
+                    anchor: anchor.clone(),
+                    start: content.len(),
+                    end: content.len(),
+                });
+            }
+            Fragment::Math(_) => {}
+        }
+    }
+    if let Some(last) = sections.last_mut() {
+        last.end = content.len();
+    }
+
+    (content, sections)
+}
+
+/// 正文中公式的 TeX 源码，按出现顺序去重
+fn extract_math(fragments: &[Fragment]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    fragments
+        .iter()
+        .filter_map(|fragment| match fragment {
+            Fragment::Math(tex) => Some(tex),
+            _ => None,
+        })
+        .filter(|tex| seen.insert(tex.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// 按文本节点分行的页面内容，清理方式与 `extract_content_and_sections` 相同，用于逐行比较两个版本
+///
+/// 公式以 `$...$` 包围的 TeX 源码单独成行
+pub fn extract_content_lines(html_content: &str) -> Vec<String> {
+    let document = Html::parse_document(html_content);
+    revision_fragments(&document)
+        .into_iter()
+        .filter_map(|fragment| match fragment {
+            Fragment::Text(text) => Some(text.trim().to_string()),
+            Fragment::Math(tex) => Some(format!("${}$", tex)),
+            // 标题的文本已经作为文本节点列出
+            Fragment::Heading { .. } => None,
+        })
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// 正文中的一段：文本节点、一个公式的 TeX 源码，或者一节的开始（之后是标题的文本节点）
+enum Fragment<'a> {
+    Text(&'a str),
+    Math(String),
+    Heading {
+        heading: String,
+        anchor: Option<String>,
+    },
+}
+
+/// 分节用的标题
+const SECTION_HEADINGS: [&str; 3] = ["h2", "h3", "h4"];
+
+/// 按文档顺序列出正文的文本和公式
+///
+/// MathML 中的文本只是渲染结果（单个字母、箭头等），不算作正文；公式取 `application/x-tex` 注释中的源码
+fn revision_fragments(document: &Html) -> Vec<Fragment<'_>> {
     let content_selector = Selector::parse("div#revision").unwrap();
+    let Some(revision) = document.select(&content_selector).next() else {
+        return Vec::new();
+    };
+
+    let mut fragments = Vec::new();
+    for node in revision.descendants() {
+        if let Some(text) = node.value().as_text() {
+            let in_math = node
+                .ancestors()
+                .any(|ancestor| ancestor.value().as_element().is_some_and(is_math));
+            if !in_math {
+                fragments.push(Fragment::Text(text));
+            }
+        } else if let Some(element) = ElementRef::wrap(node) {
+            if is_math(element.value()) {
+                if let Some(tex) = tex_source(element) {
+                    fragments.push(Fragment::Math(tex));
+                }
+            } else if SECTION_HEADINGS.contains(&element.value().name()) && !in_sidebar(element) {
+                fragments.push(Fragment::Heading {
+                    heading: element
+                        .text()
+                        .flat_map(str::split_whitespace)
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    anchor: heading_anchor(element),
+                });
+            }
+        }
+    }
+    fragments
+}
+
+fn is_math(element: &scraper::node::Element) -> bool {
+    element.name() == "math"
+}
+
+/// 侧栏（`div.rightHandSide`）中的标题是领域和目录，不是页面的节
+fn in_sidebar(element: ElementRef) -> bool {
+    element
+        .ancestors()
+        .filter_map(ElementRef::wrap)
+        .any(|ancestor| {
+            ancestor
+                .value()
+                .has_class("rightHandSide", CaseSensitivity::CaseSensitive)
+        })
+}
+
+/// 标题的 `id`，没有时使用标题中第一个带 `id` 或 `name` 的元素（例如 `<a name="...">`）
+fn heading_anchor(heading: ElementRef) -> Option<String> {
+    heading
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .find_map(|e| e.value().attr("id").or_else(|| e.value().attr("name")))
+        .filter(|anchor| !anchor.is_empty())
+        .map(str::to_string)
+}
+
+fn tex_source(math: ElementRef) -> Option<String> {
+    math.descendants()
+        .filter_map(ElementRef::wrap)
+        .find(|e| {
+            e.value().name() == "annotation"
+                && e.value().attr("encoding") == Some("application/x-tex")
+        })
+        .map(|annotation| annotation.text().collect::<String>().trim().to_string())
+        .filter(|tex| !tex.is_empty())
+}
+
+/// 正文中指向其他 nLab 页面的链接，返回规范化的页面名，按出现顺序去重，不含指向自身的链接
+fn extract_links(document: &Html, own_slug: Option<&str>) -> Vec<String> {
+    let link_selector = Selector::parse("div#revision a[href]").unwrap();
+    let own_slug = own_slug.map(normalize_slug);
+    let mut seen = HashSet::new();
 
     document
-        .select(&content_selector)
-        .next()
-        .map_or_else(String::new, |element| {
-            element.text().collect::<Vec<_>>().join(" ")
+        .select(&link_selector)
+        .filter_map(|link| link.value().attr("href"))
+        .filter_map(link_slug)
+        .filter(|slug| own_slug.as_ref() != Some(slug))
+        .filter(|slug| seen.insert(slug.clone()))
+        .collect()
+}
+
+/// 页面末尾 `category: people, reference` 行中的分类，按出现顺序去重
+///
+/// nLab 把这一行渲染为 `<div class="property">`，手写的页面中也可能是普通段落
+fn extract_categories(document: &Html) -> Vec<String> {
+    let line_selector = Selector::parse("div#revision div.property, div#revision p").unwrap();
+    let mut seen = HashSet::new();
+
+    document
+        .select(&line_selector)
+        .filter_map(|line| {
+            let text = line.text().collect::<String>();
+            let rest = text.trim_start().strip_prefix("category")?;
+            Some(rest.trim_start().strip_prefix(':')?.to_string())
+        })
+        .flat_map(|rest| {
+            rest.split(',')
+                .map(|category| category.split_whitespace().collect::<Vec<_>>().join(" "))
+                .collect::<Vec<_>>()
         })
+        .filter(|category| !category.is_empty() && seen.insert(category.clone()))
+        .collect()
+}
+
+/// 侧栏 "Context" 中列出的领域，例如 `Category theory`、`Cohesive toposes`
+///
+/// 侧栏的结构是 `<h3>Context</h3>` 之后每个领域一个标题，领域下的链接列表放在折叠的 `div` 中
+fn extract_contexts(document: &Html) -> Vec<String> {
+    let heading_selector = Selector::parse(
+        "div#revision div.rightHandSide div.toc > h3, div#revision div.rightHandSide div.toc > h4",
+    )
+    .unwrap();
+    let mut seen = HashSet::new();
+
+    document
+        .select(&heading_selector)
+        .map(|heading| {
+            heading
+                .text()
+                .flat_map(str::split_whitespace)
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .filter(|context| !context.is_empty() && context != "Context" && context != "Contents")
+        .filter(|context| seen.insert(context.clone()))
+        .collect()
+}
+
+/// `/nlab/show/...` 或 `https://ncatlab.org/nlab/show/...` 中的页面名
+fn link_slug(href: &str) -> Option<String> {
+    let path = href
+        .strip_prefix("https://ncatlab.org")
+        .or_else(|| href.strip_prefix("http://ncatlab.org"))
+        .unwrap_or(href);
+    let slug = normalize_slug(path.strip_prefix("/nlab/show/")?);
+    (!slug.is_empty()).then_some(slug)
 }
 
 fn extract_url(document: &Html) -> Result<String, ParseHtmlError> {
@@ -253,6 +644,195 @@ mod tests {
         // assert_eq!(failed_files.len(), 0, "Some files failed to extract URLs");
     }
 
+    #[test]
+    fn test_index_commit_reads_bare_repository() {
+        use crate::cancel::CancelToken;
+        use crate::progress::NoProgress;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let repo = git2::Repository::init_bare(temp_dir.path()).unwrap();
+
+        // 直接向对象库写入 pages/1/1/content.html 和一个非 HTML 文件
+        let html = r#"<h1 id="pageName">category theory</h1>
+            <div id="revision">Category theory studies categories.</div>
+            <a id="edit" href="/nlab/edit/category+theory">Edit</a>"#;
+        let mut page_dir = repo.treebuilder(None).unwrap();
+        let blob = repo.blob(html.as_bytes()).unwrap();
+        page_dir.insert("content.html", blob, 0o100644).unwrap();
+        let readme = repo.blob(b"not a page").unwrap();
+        page_dir.insert("README.md", readme, 0o100644).unwrap();
+        let mut pages = repo.treebuilder(None).unwrap();
+        let mut one = repo.treebuilder(None).unwrap();
+        one.insert("1", page_dir.write().unwrap(), 0o040000)
+            .unwrap();
+        pages.insert("1", one.write().unwrap(), 0o040000).unwrap();
+        let mut root = repo.treebuilder(None).unwrap();
+        root.insert("pages", pages.write().unwrap(), 0o040000)
+            .unwrap();
+        let tree = repo.find_tree(root.write().unwrap()).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let commit = repo
+            .commit(None, &signature, &signature, "initial", &tree, &[])
+            .unwrap();
+
+        let pages =
+            index_commit(temp_dir.path(), commit, &NoProgress, &CancelToken::new()).unwrap();
+
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].id, 1);
+        assert_eq!(pages[0].file_path, "pages/1/1/content.html");
+        assert_eq!(pages[0].title, "category theory");
+        assert_eq!(
+            pages[0].url,
+            "https://ncatlab.org/nlab/show/category+theory"
+        );
+
+        let changed = vec![
+            "pages/1/1/content.html".to_string(),
+            "pages/2/2/content.html".to_string(),
+        ];
+        let pages = index_commit_files(
+            temp_dir.path(),
+            commit,
+            &changed,
+            &NoProgress,
+            &CancelToken::new(),
+        )
+        .unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].title, "category theory");
+    }
+
+    #[test]
+    fn test_extract_links() {
+        let html = r#"<h1 id="pageName">topos</h1>
+            <div id="revision">
+              A <a href="/nlab/show/category">category</a> of
+              <a href="https://ncatlab.org/nlab/show/sheaf#idea">sheaves</a>, see
+              <a href="/nlab/show/category%20theory">category theory</a> and
+              <a href="/nlab/show/category">categories</a> and <a href="/nlab/show/topos">topos</a>.
+              <a href="https://en.wikipedia.org/wiki/Topos">Wikipedia</a>
+              <a href="/nlab/history/topos">history</a>
+            </div>
+            <a href="/nlab/show/HomePage">Home</a>
+            <a id="edit" href="/nlab/edit/topos">Edit</a>"#;
+
+        let page = parse_html_content("pages/2/2/content.html".to_string(), html)
+            .unwrap()
+            .unwrap();
+        assert_eq!(page.links, vec!["category", "sheaf", "category+theory"]);
+    }
+
+    #[test]
+    fn test_extract_math() {
+        let html = r#"<h1 id="pageName">tensor product</h1>
+            <div id="revision">
+              The product
+              <math xmlns="http://www.w3.org/1998/Math/MathML"><semantics><mrow><mi>A</mi><mo>⊗</mo><mi>B</mi></mrow><annotation encoding="application/x-tex">A \otimes B</annotation></semantics></math>
+              of <math><semantics><mi>A</mi><annotation encoding="application/x-tex">A</annotation></semantics></math>
+              and <math><semantics><mrow><mi>A</mi><mo>⊗</mo><mi>B</mi></mrow><annotation encoding="application/x-tex">A \otimes B</annotation></semantics></math>.
+            </div>
+            <a id="edit" href="/nlab/edit/tensor+product">Edit</a>"#;
+
+        let page = parse_html_content("pages/3/3/content.html".to_string(), html)
+            .unwrap()
+            .unwrap();
+        assert_eq!(page.math, vec![r"A \otimes B", "A"]);
+        assert!(!page.content.contains('⊗'));
+        assert_eq!(
+            page.content.split_whitespace().collect::<Vec<_>>(),
+            vec!["The", "product", "of", "and", "."]
+        );
+
+        let lines = extract_content_lines(html);
+        assert_eq!(lines[1], r"$A \otimes B$");
+    }
+
+    #[test]
+    fn test_extract_sections() {
+        let html = r#"<h1 id="pageName">topos</h1>
+            <div id="revision">
+              <p>Intro text.</p>
+              <h2 id="idea">Idea</h2>
+              <p>A topos is a category.</p>
+              <h3><a name="examples_of_toposes"></a>Examples</h3>
+              <p>Sheaves on a space.</p>
+              <h2>Related concepts</h2>
+              <p>locale</p>
+            </div>
+            <a id="edit" href="/nlab/edit/topos">Edit</a>"#;
+
+        let page = parse_html_content("pages/1/1/content.html".to_string(), html)
+            .unwrap()
+            .unwrap();
+        let sections: Vec<_> = page
+            .sections
+            .iter()
+            .map(|section| {
+                (
+                    section.heading.as_str(),
+                    section.anchor.as_deref(),
+                    page.section_text(section)
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                )
+            })
+            .collect();
+        assert_eq!(
+            sections,
+            vec![
+                (
+                    "Idea",
+                    Some("idea"),
+                    "Idea A topos is a category.".to_string()
+                ),
+                (
+                    "Examples",
+                    Some("examples_of_toposes"),
+                    "Examples Sheaves on a space.".to_string()
+                ),
+                (
+                    "Related concepts",
+                    None,
+                    "Related concepts locale".to_string()
+                ),
+            ]
+        );
+        assert!(page.content.contains("Intro text."));
+    }
+
+    #[test]
+    fn test_extract_facets() {
+        let html = r#"<h1 id="pageName">Urs Schreiber</h1>
+            <div id="revision">
+              <div class="rightHandSide">
+                <div class="toc clickDown" tabindex="0">
+                  <h3 id="context">Context</h3>
+                  <h4 id="higher_geometry">Higher geometry</h4>
+                  <div class="hide"><div><h4>Hidden heading</h4><p>cohesion</p></div></div>
+                  <h4 id="cohesive_toposes"><strong>Cohesive  toposes</strong></h4>
+                </div>
+              </div>
+              <h2 id="idea">Idea</h2>
+              <p>A mathematical physicist.</p>
+              <p>category theory is not a category line</p>
+              <div class="property"> category: <a class="category_link" href="/nlab/list/people">people</a></div>
+              <p>category: reference, people</p>
+            </div>
+            <a id="edit" href="/nlab/edit/Urs+Schreiber">Edit</a>"#;
+
+        let page = parse_html_content("pages/5/5/content.html".to_string(), html)
+            .unwrap()
+            .unwrap();
+        assert_eq!(page.categories, vec!["people", "reference"]);
+        assert_eq!(page.contexts, vec!["Higher geometry", "Cohesive toposes"]);
+        // 侧栏中的标题不分节
+        let headings: Vec<_> = page.sections.iter().map(|s| s.heading.as_str()).collect();
+        assert_eq!(headings, vec!["Idea"]);
+    }
+
     #[test]
     fn test_walkdir_finds_specific_file() {
         let target_file_path = "nlab_mirror/pages/0/0/0/0/10000/content.html";
@@ -325,7 +905,7 @@ mod tests {
             if title.is_empty() { "(empty)" } else { &title }
         );
 
-        let content = extract_content(&document);
+        let (content, _) = extract_content_and_sections(&revision_fragments(&document));
         println!("Content length: {} chars", content.len());
         println!(
             "Content preview: {}",
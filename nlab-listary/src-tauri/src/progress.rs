@@ -0,0 +1,161 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 长时间操作所处的阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// 克隆远程仓库时接收对象
+    Clone,
+    /// 更新时从远程获取对象
+    Fetch,
+    /// 检出工作目录
+    Checkout,
+    /// 解析 HTML 文件
+    Parse,
+    /// 写入搜索索引
+    Index,
+    /// 导出 bundle 时打包对象
+    Pack,
+    /// 解压页面压缩包
+    Extract,
+}
+
+/// 一次进度报告
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Progress {
+    pub phase: Phase,
+    /// 当前阶段已完成的数量：对象、检出步骤、已解析文件或已索引文档
+    pub current: usize,
+    pub total: usize,
+    /// 已接收的字节数，只在 Clone/Fetch 阶段有意义
+    pub received_bytes: usize,
+}
+
+impl Progress {
+    pub fn new(phase: Phase, current: usize, total: usize) -> Self {
+        Self {
+            phase,
+            current,
+            total,
+            received_bytes: 0,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.current >= self.total
+    }
+}
+
+/// 进度的接收方，`git_ops`、`parser` 和 `search` 向它报告进度
+///
+/// 解析是并行的，因此需要 `Sync`
+pub trait ProgressSink: Sync {
+    fn report(&self, progress: Progress);
+
+    /// 某个文件解析失败而被跳过
+    fn skipped(&self, _name: &str) {}
+
+    /// 进度之外的说明，例如远程仓库发来的消息
+    fn message(&self, _message: &str) {}
+}
+
+/// 丢弃所有进度
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {
+    fn report(&self, _progress: Progress) {}
+}
+
+/// 限制转发给 `inner` 的频率：阶段切换和阶段结束总是转发，同一阶段内至少间隔 `interval`
+pub struct Throttled<S> {
+    inner: S,
+    interval: Duration,
+    last_report: Mutex<Option<(Phase, Instant)>>,
+}
+
+impl<S: ProgressSink> Throttled<S> {
+    pub fn new(inner: S, interval: Duration) -> Self {
+        Self {
+            inner,
+            interval,
+            last_report: Mutex::new(None),
+        }
+    }
+}
+
+impl<S: ProgressSink> ProgressSink for Throttled<S> {
+    fn report(&self, progress: Progress) {
+        let Ok(mut last_report) = self.last_report.lock() else {
+            return;
+        };
+
+        let due = match *last_report {
+            Some((phase, at)) => {
+                phase != progress.phase || progress.is_done() || at.elapsed() >= self.interval
+            }
+            None => true,
+        };
+        if due {
+            *last_report = Some((progress.phase, Instant::now()));
+            self.inner.report(progress);
+        }
+    }
+
+    fn skipped(&self, name: &str) {
+        self.inner.skipped(name);
+    }
+
+    fn message(&self, message: &str) {
+        self.inner.message(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Recording(Mutex<Vec<Progress>>);
+
+    impl ProgressSink for &Recording {
+        fn report(&self, progress: Progress) {
+            self.0.lock().unwrap().push(progress);
+        }
+    }
+
+    #[test]
+    fn test_throttled_always_reports_phase_changes_and_completion() {
+        let recording = Recording::default();
+        let sink = Throttled::new(&recording, Duration::from_secs(3600));
+        for current in 0..=100 {
+            sink.report(Progress::new(Phase::Parse, current, 100));
+        }
+        sink.report(Progress::new(Phase::Index, 0, 10));
+        sink.report(Progress::new(Phase::Index, 5, 10));
+        sink.report(Progress::new(Phase::Index, 10, 10));
+
+        // 中间的进度都被节流，第一次、阶段切换和 100% 的进度总是送达
+        let reported = recording.0.into_inner().unwrap();
+        assert_eq!(
+            reported,
+            vec![
+                Progress::new(Phase::Parse, 0, 100),
+                Progress::new(Phase::Parse, 100, 100),
+                Progress::new(Phase::Index, 0, 10),
+                Progress::new(Phase::Index, 10, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_throttled_reports_after_interval() {
+        let recording = Recording::default();
+        let sink = Throttled::new(&recording, Duration::ZERO);
+        for current in 0..5 {
+            sink.report(Progress::new(Phase::Fetch, current, 10));
+        }
+        assert_eq!(recording.0.lock().unwrap().len(), 5);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::storage::SyncAttempt;
+use auto_launch::AutoLaunchBuilder;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ScheduleError {
+    #[error("Failed to update launch at login: {0}")]
+    AutoLaunch(#[from] auto_launch::Error),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// 定时同步线程检查是否到期的间隔
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// 自动同步失败后，最多等待这么久（秒）再重试
+const RETRY_DELAY_SECS: i64 = 3600;
+/// 开机启动时传入数据目录
+const WORKDIR_ARG: &str = "--workdir";
+const APP_NAME: &str = "nlab-listary";
+
+/// 下一次自动同步的时间（Unix 时间戳）；`interval_hours` 为 0 时不自动同步
+///
+/// 从未尝试过时立即同步，上次失败时按较短的间隔重试
+pub fn next_sync_at(last: Option<&SyncAttempt>, interval_hours: u64) -> Option<i64> {
+    if interval_hours == 0 {
+        return None;
+    }
+    let Some(last) = last else {
+        return Some(0);
+    };
+
+    let interval = interval_hours as i64 * 3600;
+    let delay = if last.error.is_some() {
+        interval.min(RETRY_DELAY_SECS)
+    } else {
+        interval
+    };
+    Some(last.timestamp + delay)
+}
+
+/// 开启或关闭登录时启动，启动参数中带上数据目录 `workdir`
+pub fn set_launch_at_login(enabled: bool, workdir: &Path) -> Result<(), ScheduleError> {
+    let exe = std::env::current_exe()?;
+    let auto_launch = AutoLaunchBuilder::new()
+        .set_app_name(APP_NAME)
+        .set_app_path(&exe.to_string_lossy())
+        .set_args(&[WORKDIR_ARG, &workdir.to_string_lossy()])
+        .set_use_launch_agent(true)
+        .build()?;
+
+    if auto_launch.is_enabled()? == enabled {
+        return Ok(());
+    }
+    if enabled {
+        auto_launch.enable()?;
+    } else {
+        auto_launch.disable()?;
+    }
+    println!("登录时启动: {}", if enabled { "已开启" } else { "已关闭" });
+    Ok(())
+}
+
+/// 由开机启动运行时 `--workdir` 指定的数据目录
+pub fn workdir_arg() -> Option<PathBuf> {
+    let mut args = std::env::args().skip_while(|arg| arg != WORKDIR_ARG);
+    args.nth(1).map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_sync_at() {
+        assert_eq!(next_sync_at(None, 0), None);
+        assert_eq!(next_sync_at(None, 24), Some(0));
+
+        let succeeded = SyncAttempt {
+            timestamp: 1_700_000_000,
+            error: None,
+        };
+        assert_eq!(next_sync_at(Some(&succeeded), 24), Some(1_700_086_400));
+        assert_eq!(next_sync_at(Some(&succeeded), 0), None);
+
+        let failed = SyncAttempt {
+            timestamp: 1_700_000_000,
+            error: Some("network unreachable".to_string()),
+        };
+        assert_eq!(next_sync_at(Some(&failed), 24), Some(1_700_003_600));
+    }
+}
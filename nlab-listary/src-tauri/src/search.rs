@@ -1,8 +1,16 @@
 
+use crate::cancel::CancelToken;
 use crate::models::NLabPage;
+use crate::progress::{NoProgress, Phase, Progress, ProgressSink};
+use crate::tex::{self, TexTokenizer, TEX_TOKENIZER};
+use serde::{Deserialize, Serialize};
 use std::path::Path;
-use tantivy::schema::Value;
-use tantivy::{doc, query::QueryParser, IndexWriter, TantivyDocument};
+use tantivy::collector::FacetCollector;
+use tantivy::query::{BooleanQuery, Occur, PhraseQuery, Query, QueryParser, TermQuery};
+use tantivy::schema::{
+    Facet, FacetOptions, IndexRecordOption, TextFieldIndexing, TextOptions, Value,
+};
+use tantivy::{doc, IndexWriter, TantivyDocument};
 use thiserror::Error;
 
 pub trait SearchEngine {
@@ -15,9 +23,8 @@ pub trait SearchEngine {
     fn build_index(&mut self, docs: &[NLabPage]) -> Result<(), SearchError>;
 
     fn update_page(&mut self, page: &NLabPage) -> Result<(), SearchError>;
-    fn delete_page(&mut self, page_id: &str) -> Result<(), SearchError>;
+    fn delete_page(&mut self, page_id: u64) -> Result<(), SearchError>;
 
-    fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>, SearchError>;
     fn search_with_filters(
         &self,
         query: &str,
@@ -28,10 +35,12 @@ pub trait SearchEngine {
 
 #[derive(Debug)]
 pub struct SearchResult {
-    pub id: String,
+    pub id: u64,
     score: f32,
-    pub title: String,
     content: String,
+    /// 匹配的一节的标题和锚点
+    pub section: Option<String>,
+    pub anchor: Option<String>,
 }
 
 #[derive(Error, Debug)]
@@ -44,6 +53,9 @@ pub enum SearchError {
 
     #[error("Query parsing error: {0}")]
     QueryParseError(#[from] tantivy::query::QueryParserError),
+
+    #[error("Indexing cancelled")]
+    Cancelled,
 }
 
 #[derive(Clone)]
@@ -55,6 +67,10 @@ pub struct TantivySearch {
 pub struct SearchFilters {
     pub title_only: bool,
     pub min_score: f32,
+    /// 结果必须带有的分类或领域：同一种的多个值满足其一即可，不同种之间都要满足
+    pub include: Vec<FacetFilter>,
+    /// 结果不能带有的分类或领域，例如排除 `people` 分类
+    pub exclude: Vec<FacetFilter>,
 }
 
 impl Default for SearchFilters {
@@ -62,20 +78,166 @@ impl Default for SearchFilters {
         Self {
             title_only: false,
             min_score: 0.0,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+/// 可以筛选和计数的页面属性，对应 `NLabPage` 的 `categories` 和 `contexts`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FacetKind {
+    Category,
+    Context,
+}
+
+impl FacetKind {
+    const ALL: [FacetKind; 2] = [FacetKind::Category, FacetKind::Context];
+
+    fn field_name(self) -> &'static str {
+        match self {
+            FacetKind::Category => "category",
+            FacetKind::Context => "context",
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FacetFilter {
+    pub kind: FacetKind,
+    pub value: String,
+}
+
+/// 搜索结果中带有某个分类或领域的页面数
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FacetCount {
+    pub kind: FacetKind,
+    pub value: String,
+    pub count: u64,
+}
+
+/// 每种属性最多返回的计数
+const FACET_COUNT_LIMIT: usize = 30;
+
 // 辅助方法：创建 schema
 fn create_schema() -> tantivy::schema::Schema {
     let mut schema_builder = tantivy::schema::Schema::builder();
-    // 使用 STRING 而不是 TEXT，因为 id 需要精确匹配，不需要分词
-    schema_builder.add_text_field("id", tantivy::schema::STRING | tantivy::schema::STORED);
+    // 页面编号，只需要精确匹配（删除和更新时按它找到旧文档）
+    schema_builder.add_u64_field("id", tantivy::schema::INDEXED | tantivy::schema::STORED);
     schema_builder.add_text_field("title", tantivy::schema::TEXT | tantivy::schema::STORED);
     schema_builder.add_text_field("content", tantivy::schema::TEXT | tantivy::schema::STORED);
+    // 公式的 TeX 源码，用 `TexTokenizer` 分词，保留位置以便按短语查询
+    let math_indexing = TextFieldIndexing::default()
+        .set_tokenizer(TEX_TOKENIZER)
+        .set_index_option(IndexRecordOption::WithFreqsAndPositions);
+    schema_builder.add_text_field(
+        "math",
+        TextOptions::default().set_indexing_options(math_indexing),
+    );
+    // 页面的每一节另外作为一个文档，`id` 与页面相同，只有 `heading`、`anchor` 和 `content`
+    schema_builder.add_text_field("heading", tantivy::schema::TEXT | tantivy::schema::STORED);
+    schema_builder.add_text_field("anchor", tantivy::schema::STORED);
+    // 分类和领域，每个值是只有一层的 facet（例如 `/people`）；各节的文档也带有页面的值，以便一起筛选
+    for kind in FacetKind::ALL {
+        schema_builder.add_facet_field(kind.field_name(), FacetOptions::default());
+    }
+    // 计数时只算页面本身的文档
+    schema_builder.add_bool_field("is_section", tantivy::schema::INDEXED);
     schema_builder.build()
 }
 
+/// 每批命中按每个页面这么多个文档来取：页面本身和它的各节会分别命中，取结果时按页面去重
+const HITS_PER_PAGE: usize = 4;
+
+/// 页面的分类和领域
+fn add_facets(document: &mut TantivyDocument, schema: &tantivy::schema::Schema, page: &NLabPage) {
+    for (kind, values) in [
+        (FacetKind::Category, &page.categories),
+        (FacetKind::Context, &page.contexts),
+    ] {
+        let field = schema.get_field(kind.field_name()).unwrap();
+        for value in values {
+            document.add_facet(field, Facet::from_path([value]));
+        }
+    }
+}
+
+/// 页面对应的索引文档，每个公式是 `math` 字段的一个值；之后是有锚点的各节的文档
+fn page_documents(schema: &tantivy::schema::Schema, page: &NLabPage) -> Vec<TantivyDocument> {
+    let page_id = schema.get_field("id").unwrap();
+    let page_content = schema.get_field("content").unwrap();
+    let is_section = schema.get_field("is_section").unwrap();
+
+    let mut document = doc!(
+        page_id => page.id,
+        schema.get_field("title").unwrap() => page.title.clone(),
+        page_content => page.content.clone(),
+        is_section => false,
+    );
+    add_facets(&mut document, schema, page);
+    let math = schema.get_field("math").unwrap();
+    for formula in &page.math {
+        document.add_text(math, formula);
+    }
+
+    let mut documents = vec![document];
+    // 没有锚点的节无法直接打开，它们的内容已经在页面文档中
+    for section in &page.sections {
+        let Some(anchor) = &section.anchor else {
+            continue;
+        };
+        let mut document = doc!(
+            page_id => page.id,
+            schema.get_field("heading").unwrap() => section.heading.clone(),
+            schema.get_field("anchor").unwrap() => anchor.clone(),
+            page_content => page.section_text(section).to_string(),
+            is_section => true,
+        );
+        add_facets(&mut document, schema, page);
+        documents.push(document);
+    }
+    documents
+}
+
+fn add_page(
+    writer: &IndexWriter<TantivyDocument>,
+    schema: &tantivy::schema::Schema,
+    page: &NLabPage,
+) -> Result<(), SearchError> {
+    for document in page_documents(schema, page) {
+        writer.add_document(document)?;
+    }
+    Ok(())
+}
+
+/// 公式中的词在 `math` 字段中按顺序相邻出现
+fn formula_query(math: tantivy::schema::Field, formula: &str) -> Option<Box<dyn Query>> {
+    let mut terms: Vec<tantivy::Term> = tex::tex_terms(formula)
+        .iter()
+        .map(|term| tantivy::Term::from_field_text(math, term))
+        .collect();
+    match terms.len() {
+        0 => None,
+        1 => Some(Box::new(TermQuery::new(
+            terms.remove(0),
+            IndexRecordOption::WithFreqs,
+        ))),
+        _ => Some(Box::new(PhraseQuery::new(terms))),
+    }
+}
+
+/// `BooleanQuery` 的子句
+type Clauses = Vec<(Occur, Box<dyn Query>)>;
+
+fn facet_query(schema: &tantivy::schema::Schema, filter: &FacetFilter) -> Box<dyn Query> {
+    let field = schema.get_field(filter.kind.field_name()).unwrap();
+    Box::new(TermQuery::new(
+        tantivy::Term::from_facet(field, &Facet::from_path([&filter.value])),
+        IndexRecordOption::Basic,
+    ))
+}
+
 impl SearchEngine for TantivySearch {
     fn new(index_dir: impl AsRef<Path>) -> Result<Self, SearchError> {
         let index_path = index_dir.as_ref();
@@ -87,70 +249,42 @@ impl SearchEngine for TantivySearch {
             std::fs::create_dir_all(index_path)?;
             tantivy::Index::create_in_dir(index_path, create_schema())?
         };
+        // 自定义分词器不保存在索引中，每次打开都要注册
+        index.tokenizers().register(TEX_TOKENIZER, TexTokenizer);
 
         let reader = index.reader()?;
         Ok(TantivySearch { index, reader })
     }
 
     fn build_index(&mut self, docs: &[NLabPage]) -> Result<(), SearchError> {
-        let schema = self.index.schema();
-        let page_id = schema.get_field("id").unwrap();
-        let page_title = schema.get_field("title").unwrap();
-        let page_content = schema.get_field("content").unwrap();
-
-        let mut writer = self.index.writer(50_000_000)?;
-
-        for doc in docs {
-            writer.add_document(doc!(
-                page_id => doc.id,
-                page_title => doc.title,
-                page_content => doc.content,
-            ))?;
-        }
-
-        writer.commit()?;
-
-        // 重新加载 reader 以看到新数据
-        self.reader.reload()?;
-
-        Ok(())
+        self.build_index_with_progress(docs, &NoProgress, &CancelToken::new())
     }
 
     fn update_page(&mut self, page: &NLabPage) -> Result<(), SearchError> {
         let schema = self.index.schema();
         let page_id = schema.get_field("id").unwrap();
-        let page_title = schema.get_field("title").unwrap();
-        let page_content = schema.get_field("content").unwrap();
 
         let mut writer = self.index.writer(50_000_000)?;
-        writer.delete_term(tantivy::Term::from_field_text(page_id, &page.id));
-        writer.add_document(doc!(
-            page_id => page.id.clone(),
-            page_title => page.title.clone(),
-            page_content => page.content.clone(),
-        ))?;
+        writer.delete_term(tantivy::Term::from_field_u64(page_id, page.id));
+        add_page(&writer, &schema, page)?;
         writer.commit()?;
 
         self.reader.reload()?;
         Ok(())
     }
 
-    fn delete_page(&mut self, page_id: &str) -> Result<(), SearchError> {
+    fn delete_page(&mut self, page_id: u64) -> Result<(), SearchError> {
         let schema = self.index.schema();
         let page_id_field = schema.get_field("id").unwrap();
 
         let mut writer: IndexWriter<TantivyDocument> = self.index.writer(50_000_000)?;
-        writer.delete_term(tantivy::Term::from_field_text(page_id_field, page_id));
+        writer.delete_term(tantivy::Term::from_field_u64(page_id_field, page_id));
         writer.commit()?;
 
         self.reader.reload()?;
         Ok(())
     }
 
-    fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>, SearchError> {
-        self.search_with_filters(query, limit, SearchFilters::default())
-    }
-
     fn search_with_filters(
         &self,
         query: &str,
@@ -159,87 +293,323 @@ impl SearchEngine for TantivySearch {
     ) -> Result<Vec<SearchResult>, SearchError> {
         let schema = self.index.schema();
         let page_id = schema.get_field("id").unwrap();
-        let page_title = schema.get_field("title").unwrap();
         let page_content = schema.get_field("content").unwrap();
 
+        let page_heading = schema.get_field("heading").unwrap();
+        let page_anchor = schema.get_field("anchor").unwrap();
+
         let searcher = self.reader.searcher();
-        let query_parser = if filters.title_only {
-            QueryParser::for_index(&self.index, vec![page_title])
-        } else {
-            QueryParser::for_index(&self.index, vec![page_title, page_content])
-        };
-        let query = query_parser.parse_query(query)?;
+        let query = BooleanQuery::new(self.query_clauses(query, &filters)?);
+
+        // 同一页面的多个节可能占满一批命中，去重后不足 `limit` 个页面时接着取下一批
+        let batch_size = limit * HITS_PER_PAGE;
+        let mut offset = 0;
+        let mut results: Vec<SearchResult> = Vec::new();
+        while results.len() < limit {
+            let top_docs = searcher.search(
+                &query,
+                &tantivy::collector::TopDocs::with_limit(batch_size).and_offset(offset),
+            )?;
+            let exhausted = top_docs.len() < batch_size;
+            offset += batch_size;
 
-        let top_docs = searcher.search(&query, &tantivy::collector::TopDocs::with_limit(limit))?;
+            for (score, doc_address) in top_docs {
+                // 命中按得分从高到低排列，之后的都低于 `min_score`
+                if score < filters.min_score {
+                    return Ok(results);
+                }
+                let retrieved_doc: TantivyDocument = searcher.doc(doc_address)?;
+                let id = retrieved_doc
+                    .get_first(page_id)
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or_default();
+                // 同一页面只保留得分最高的文档：最好的匹配是某一节时链接到这一节
+                if results.iter().any(|result| result.id == id) {
+                    continue;
+                }
+                if results.len() == limit {
+                    break;
+                }
+                let content = retrieved_doc
+                    .get_first(page_content)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string();
 
-        let mut results = Vec::new();
-        for (score, doc_address) in top_docs {
-            if score < filters.min_score {
-                continue;
+                let section = retrieved_doc
+                    .get_first(page_heading)
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string);
+                let anchor = retrieved_doc
+                    .get_first(page_anchor)
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string);
+
+                results.push(SearchResult {
+                    id,
+                    score,
+                    content,
+                    section,
+                    anchor,
+                });
+            }
+            if exhausted {
+                break;
             }
-            let retrieved_doc: TantivyDocument = searcher.doc(doc_address)?;
-            let id = retrieved_doc
-                .get_first(page_id)
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-            let title = retrieved_doc
-                .get_first(page_title)
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-            let content = retrieved_doc
-                .get_first(page_content)
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-
-            results.push(SearchResult {
-                id,
-                score,
-                title,
-                content,
-            });
         }
 
         Ok(results)
     }
 }
 
+// 每写入这么多个文档报告一次进度
+const INDEX_PROGRESS_STEP: usize = 1000;
+
+fn report_indexed(progress: &dyn ProgressSink, indexed: usize, total: usize) {
+    if indexed.is_multiple_of(INDEX_PROGRESS_STEP) || indexed == total {
+        progress.report(Progress::new(Phase::Index, indexed, total));
+    }
+}
+
+// 取消时丢弃尚未提交的修改，索引保持上一次提交的状态
+fn rollback_if_cancelled(
+    writer: &mut IndexWriter<TantivyDocument>,
+    cancel: &CancelToken,
+) -> Result<(), SearchError> {
+    if cancel.is_cancelled() {
+        writer.rollback()?;
+        return Err(SearchError::Cancelled);
+    }
+    Ok(())
+}
+
+/// 在以页面编号为 `id` 之后加入的字段，旧索引缺少它们时需要重建
+const ADDED_FIELDS: &[&str] = &[
+    "math",
+    "heading",
+    "anchor",
+    "category",
+    "context",
+    "is_section",
+];
+
 impl TantivySearch {
-    pub fn update_pages_batch(&mut self, pages: &[NLabPage]) -> Result<(), SearchError> {
+    /// 查询文本、公式和分类、领域的筛选条件
+    fn query_clauses(&self, query: &str, filters: &SearchFilters) -> Result<Clauses, SearchError> {
         let schema = self.index.schema();
-        let page_id = schema.get_field("id").unwrap();
         let page_title = schema.get_field("title").unwrap();
         let page_content = schema.get_field("content").unwrap();
+        let page_math = schema.get_field("math").unwrap();
+        let page_heading = schema.get_field("heading").unwrap();
+
+        let query_parser = if filters.title_only {
+            QueryParser::for_index(&self.index, vec![page_title])
+        } else {
+            QueryParser::for_index(&self.index, vec![page_title, page_heading, page_content])
+        };
+        // 公式不经过 QueryParser：`\`、`^` 和 `{}` 在它的语法中另有含义
+        let (text, formulas) = tex::split_formulas(query);
+        let mut clauses: Clauses = Vec::new();
+        if !text.trim().is_empty() {
+            clauses.push((Occur::Must, query_parser.parse_query(&text)?));
+        }
+        for formula in &formulas {
+            if let Some(formula_query) = formula_query(page_math, formula) {
+                clauses.push((Occur::Must, formula_query));
+            }
+        }
+        // 没有查询时不返回任何结果，筛选条件只用来缩小范围
+        if clauses.is_empty() {
+            return Ok(clauses);
+        }
+
+        for kind in FacetKind::ALL {
+            let any_of: Clauses = filters
+                .include
+                .iter()
+                .filter(|filter| filter.kind == kind)
+                .map(|filter| (Occur::Should, facet_query(&schema, filter)))
+                .collect();
+            if !any_of.is_empty() {
+                clauses.push((Occur::Must, Box::new(BooleanQuery::new(any_of))));
+            }
+        }
+        for filter in &filters.exclude {
+            clauses.push((Occur::MustNot, facet_query(&schema, filter)));
+        }
+        Ok(clauses)
+    }
+
+    /// 满足查询和筛选条件的页面中各分类和领域的页面数，每种按页面数从多到少
+    pub fn facet_counts(
+        &self,
+        query: &str,
+        filters: &SearchFilters,
+    ) -> Result<Vec<FacetCount>, SearchError> {
+        let schema = self.index.schema();
+        let is_section = schema.get_field("is_section").unwrap();
+
+        let mut clauses = self.query_clauses(query, filters)?;
+        if clauses.is_empty() {
+            return Ok(Vec::new());
+        }
+        // 各节的文档与页面带有相同的值，只数页面本身
+        clauses.push((
+            Occur::Must,
+            Box::new(TermQuery::new(
+                tantivy::Term::from_field_bool(is_section, false),
+                IndexRecordOption::Basic,
+            )),
+        ));
+        let query = BooleanQuery::new(clauses);
+
+        let searcher = self.reader.searcher();
+        let mut counts = Vec::new();
+        for kind in FacetKind::ALL {
+            let mut collector = FacetCollector::for_field(kind.field_name());
+            collector.add_facet(Facet::root());
+            let facet_counts = searcher.search(&query, &collector)?;
+            for (facet, count) in facet_counts.top_k(Facet::root(), FACET_COUNT_LIMIT) {
+                counts.push(FacetCount {
+                    kind,
+                    value: facet.to_path().concat(),
+                    count,
+                });
+            }
+        }
+        Ok(counts)
+    }
+
+    pub fn build_index_with_progress(
+        &mut self,
+        docs: &[NLabPage],
+        progress: &dyn ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<(), SearchError> {
+        let schema = self.index.schema();
 
         let mut writer = self.index.writer(50_000_000)?;
-        
-        println!("Starting batch update for {} pages", pages.len());
-        
-        for page in pages {
-            println!("Deleting page with id: {}", page.id);
-            let page_id_term = tantivy::Term::from_field_text(page_id, &page.id);
-            writer.delete_term(page_id_term.clone());
-            
-            println!("Adding page: {} (id: {})", page.title, page.id);
-            writer.add_document(doc!(
-                page_id => page.id.clone(),
-                page_title => page.title.clone(),
-                page_content => page.content.clone(),
-            ))?;
+
+        for (i, doc) in docs.iter().enumerate() {
+            rollback_if_cancelled(&mut writer, cancel)?;
+            add_page(&writer, &schema, doc)?;
+            report_indexed(progress, i + 1, docs.len());
         }
-        
-        println!("Committing changes...");
+
         writer.commit()?;
 
-        println!("Reloading reader...");
+        // 重新加载 reader 以看到新数据
         self.reader.reload()?;
-        
-        println!("Batch update completed successfully");
-        
+
         Ok(())
     }
+
+    /// 在同一次提交中删除 `deleted_ids` 并写入 `pages`，用于增量同步
+    ///
+    /// 只需要 `&self`：提交前的搜索仍然看到旧数据，提交后 reader 重新加载
+    pub fn apply_changes(
+        &self,
+        pages: &[NLabPage],
+        deleted_ids: &[u64],
+        progress: &dyn ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<(), SearchError> {
+        let schema = self.index.schema();
+        let page_id = schema.get_field("id").unwrap();
+
+        let mut writer = self.index.writer(50_000_000)?;
+
+        for id in deleted_ids {
+            writer.delete_term(tantivy::Term::from_field_u64(page_id, *id));
+        }
+
+        for (i, page) in pages.iter().enumerate() {
+            rollback_if_cancelled(&mut writer, cancel)?;
+            writer.delete_term(tantivy::Term::from_field_u64(page_id, page.id));
+            add_page(&writer, &schema, page)?;
+            report_indexed(progress, i + 1, pages.len());
+        }
+
+        writer.commit()?;
+        self.reader.reload()?;
+
+        println!(
+            "Applied {} updated and {} deleted pages",
+            pages.len(),
+            deleted_ids.len()
+        );
+        Ok(())
+    }
+
+    /// 把索引对应的提交写入提交的 payload
+    ///
+    /// 在写入数据之后单独提交：中途退出时 payload 仍是旧提交，启动检查会发现不一致
+    pub fn set_indexed_commit(&self, commit: &str) -> Result<(), SearchError> {
+        let mut writer: IndexWriter<TantivyDocument> = self.index.writer(50_000_000)?;
+        let mut prepared = writer.prepare_commit()?;
+        prepared.set_payload(commit);
+        prepared.commit()?;
+        Ok(())
+    }
+
+    pub fn indexed_commit(&self) -> Result<Option<String>, SearchError> {
+        Ok(self.index.load_metas()?.payload)
+    }
+
+    /// 把所有段合并为一个，清除已删除的文档，并删除不再使用的段文件
+    ///
+    /// 每次提交都会留下新的段，返回合并前的段数
+    pub fn optimize(&self) -> Result<usize, SearchError> {
+        let segment_ids = self.index.searchable_segment_ids()?;
+        let mut writer: IndexWriter<TantivyDocument> = self.index.writer(50_000_000)?;
+        if segment_ids.len() > 1 {
+            writer.merge(&segment_ids).wait()?;
+        }
+        writer.garbage_collect_files().wait()?;
+        writer.wait_merging_threads()?;
+
+        self.reader.reload()?;
+        Ok(segment_ids.len())
+    }
+
+    /// 索引的结构是否为当前版本；旧版本的索引以标题为 `id` 或者缺少公式、分节或分类的字段，需要用 `rebuild` 重建
+    pub fn has_current_schema(&self) -> bool {
+        let schema = self.index.schema();
+        let numeric_id = schema.get_field("id").is_ok_and(|field| {
+            matches!(
+                schema.get_field_entry(field).field_type(),
+                tantivy::schema::FieldType::U64(_)
+            )
+        });
+        numeric_id
+            && ADDED_FIELDS
+                .iter()
+                .all(|name| schema.get_field(name).is_ok())
+    }
+
+    /// 用 `pages` 在 `index_dir` 重建索引，保留原来索引对应的提交
+    ///
+    /// 先写入临时目录，完成后才替换原来的索引
+    pub fn rebuild(self, index_dir: &Path, pages: &[NLabPage]) -> Result<Self, SearchError> {
+        println!("正在用 {} 个页面重建搜索索引...", pages.len());
+        let commit = self.indexed_commit()?;
+        drop(self);
+
+        let partial = index_dir.with_extension("partial");
+        if partial.exists() {
+            std::fs::remove_dir_all(&partial)?;
+        }
+        {
+            let mut rebuilt = Self::new(&partial)?;
+            rebuilt.build_index(pages)?;
+            if let Some(commit) = commit {
+                rebuilt.set_indexed_commit(&commit)?;
+            }
+        }
+        std::fs::remove_dir_all(index_dir)?;
+        std::fs::rename(&partial, index_dir)?;
+        Self::new(index_dir)
+    }
 }
 
 #[cfg(test)]
@@ -247,13 +617,18 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
 
-    fn create_test_page(id: &str, title: &str, content: &str) -> NLabPage {
+    fn create_test_page(id: u64, title: &str, content: &str) -> NLabPage {
         NLabPage {
-            id: id.to_string(),
+            id,
             title: title.to_string(),
             content: content.to_string(),
-            file_path: format!("/test/path/{}.html", id),
+            file_path: format!("pages/{}/{}/content.html", id, id),
             url: format!("https://example.com/{}", id),
+            links: Vec::new(),
+            math: Vec::new(),
+            sections: Vec::new(),
+            categories: Vec::new(),
+            contexts: Vec::new(),
         }
     }
 
@@ -265,93 +640,101 @@ mod tests {
         // 确保目录存在并创建新索引
         std::fs::create_dir_all(index_path).unwrap();
         let index = tantivy::Index::create_in_dir(index_path, create_schema()).unwrap();
+        index.tokenizers().register(TEX_TOKENIZER, TexTokenizer);
         let reader = index.reader().unwrap();
         
         let search_engine = TantivySearch { index, reader };
         (search_engine, temp_dir)
     }
 
+    fn search(search_engine: &TantivySearch, query: &str) -> Vec<SearchResult> {
+        search_engine
+            .search_with_filters(query, 10, SearchFilters::default())
+            .unwrap()
+    }
+
     #[test]
-    fn test_update_pages_batch_delete_and_add() {
+    fn test_apply_changes_delete_and_add() {
         // 创建临时目录用于测试索引
         let (mut search_engine, _temp_dir) = create_test_search_engine();
+        let cancel = CancelToken::new();
 
         // 1. 首先添加一些初始页面
         let initial_pages = vec![
-            create_test_page("page1", "First Page", "This is the first page content"),
-            create_test_page("page2", "Second Page", "This is the second page content"),
-            create_test_page("page3", "Third Page", "This is the third page content"),
+            create_test_page(1, "First Page", "This is the first page content"),
+            create_test_page(2, "Second Page", "This is the second page content"),
+            create_test_page(3, "Third Page", "This is the third page content"),
         ];
 
         search_engine.build_index(&initial_pages).unwrap();
 
         // 验证初始页面已添加
-        let results = search_engine.search("first", 10).unwrap();
+        let results = search(&search_engine, "first");
         assert_eq!(results.len(), 1);
-        assert_eq!(results[0].id, "page1");
+        assert_eq!(results[0].id, 1);
         println!("✓ Initial pages added successfully");
 
         // 2. 测试批量更新：删除旧内容并添加新内容
         let updated_pages = vec![
-            create_test_page("page1", "Updated First Page", "This is the updated first page with new content"),
-            create_test_page("page2", "Updated Second Page", "This is the updated second page with new content"),
+            create_test_page(1, "Updated First Page", "This is the updated first page with new content"),
+            create_test_page(2, "Updated Second Page", "This is the updated second page with new content"),
         ];
 
         println!("\n--- Testing batch update (delete + add) ---");
-        search_engine.update_pages_batch(&updated_pages).unwrap();
+        search_engine
+            .apply_changes(&updated_pages, &[], &NoProgress, &cancel)
+            .unwrap();
 
         // 3. 验证更新后的内容
         // 搜索新内容应该能找到
-        let new_results = search_engine.search("updated first", 10).unwrap();
+        let new_results = search(&search_engine, "updated first");
         assert!(new_results.len() >= 1, "Should find updated content");
         
         // 验证找到的是新内容而不是旧内容
-        let page1_result = new_results.iter().find(|r| r.id == "page1");
+        let page1_result = new_results.iter().find(|r| r.id == 1);
         assert!(page1_result.is_some(), "Should find page1");
-        assert_eq!(page1_result.unwrap().title, "Updated First Page");
         println!("✓ New content added successfully");
 
         // 验证第二个更新的页面
-        let second_results = search_engine.search("updated second", 10).unwrap();
+        let second_results = search(&search_engine, "updated second");
         assert!(second_results.len() >= 1, "Should find updated second page");
-        let page2_result = second_results.iter().find(|r| r.id == "page2");
+        let page2_result = second_results.iter().find(|r| r.id == 2);
         assert!(page2_result.is_some(), "Should find page2");
-        assert_eq!(page2_result.unwrap().title, "Updated Second Page");
         println!("✓ Second page updated successfully");
 
         // 4. 验证未更新的页面仍然存在
-        let third_results = search_engine.search("third", 10).unwrap();
+        let third_results = search(&search_engine, "third");
         assert_eq!(third_results.len(), 1);
-        assert_eq!(third_results[0].id, "page3");
-        assert_eq!(third_results[0].title, "Third Page");
+        assert_eq!(third_results[0].id, 3);
+        assert_eq!(search(&search_engine, "title:third")[0].id, 3);
         println!("✓ Untouched page still exists");
 
         // 4.5 验证更新后page1的搜索结果只有一个
         println!("\n--- Verifying no duplicate pages after update ---");
-        let page1_results = search_engine.search("first", 10).unwrap();
-        let page1_matches: Vec<_> = page1_results.iter().filter(|r| r.id == "page1").collect();
+        let page1_results = search(&search_engine, "first");
+        let page1_matches: Vec<_> = page1_results.iter().filter(|r| r.id == 1).collect();
         assert_eq!(page1_matches.len(), 1, "Page1 should appear exactly once after update, found {} times", page1_matches.len());
-        assert_eq!(page1_matches[0].title, "Updated First Page", "Should be the updated version");
         println!("✓ Page1 appears exactly once with updated content");
         
         // 验证page2也只有一个结果
-        let page2_results = search_engine.search("second", 10).unwrap();
-        let page2_matches: Vec<_> = page2_results.iter().filter(|r| r.id == "page2").collect();
+        let page2_results = search(&search_engine, "second");
+        let page2_matches: Vec<_> = page2_results.iter().filter(|r| r.id == 2).collect();
         assert_eq!(page2_matches.len(), 1, "Page2 should appear exactly once after update, found {} times", page2_matches.len());
-        assert_eq!(page2_matches[0].title, "Updated Second Page", "Should be the updated version");
         println!("✓ Page2 appears exactly once with updated content");
 
         // 5. 测试添加新页面
         let new_pages = vec![
-            create_test_page("page4", "Fourth Page", "This is a brand new page"),
+            create_test_page(4, "Fourth Page", "This is a brand new page"),
         ];
 
         println!("\n--- Testing adding new page via batch update ---");
-        search_engine.update_pages_batch(&new_pages).unwrap();
+        search_engine
+            .apply_changes(&new_pages, &[], &NoProgress, &cancel)
+            .unwrap();
 
-        let fourth_results = search_engine.search("brand new", 10).unwrap();
+        let fourth_results = search(&search_engine, "brand new");
         assert!(fourth_results.len() >= 1, "Should find new page");
-        let page4_result = fourth_results.iter().find(|r| r.id == "page4");
+        let page4_result = fourth_results.iter().find(|r| r.id == 4);
         assert!(page4_result.is_some(), "Should find page4");
         println!("✓ New page added successfully");
 
@@ -359,13 +742,16 @@ mod tests {
         println!("\n--- Verifying updates by searching for specific content ---");
         
         // 搜索只有新内容才有的词
-        let unique_new_content = search_engine.search("updated", 10).unwrap();
+        let unique_new_content = search(&search_engine, "updated");
         println!("Found {} results for 'updated'", unique_new_content.len());
         assert!(unique_new_content.len() >= 2, "Should find at least 2 updated pages");
         
         // 验证这些结果包含正确的页面
-        let has_page1 = unique_new_content.iter().any(|r| r.id == "page1" && r.title == "Updated First Page");
-        let has_page2 = unique_new_content.iter().any(|r| r.id == "page2" && r.title == "Updated Second Page");
+        let updated_titles = search(&search_engine, "title:updated");
+        let has_page1 = unique_new_content.iter().any(|r| r.id == 1)
+            && updated_titles.iter().any(|r| r.id == 1);
+        let has_page2 = unique_new_content.iter().any(|r| r.id == 2)
+            && updated_titles.iter().any(|r| r.id == 2);
         assert!(has_page1, "Should find updated page1");
         assert!(has_page2, "Should find updated page2");
         println!("✓ Both updated pages verified with correct titles");
@@ -374,12 +760,354 @@ mod tests {
     }
 
     #[test]
-    fn test_update_pages_batch_empty() {
+    fn test_apply_changes_updates_and_deletes() {
+        let (mut search_engine, _temp_dir) = create_test_search_engine();
+
+        let initial_pages = vec![
+            create_test_page(1, "First Page", "alpha content"),
+            create_test_page(2, "Second Page", "beta content"),
+        ];
+        search_engine.build_index(&initial_pages).unwrap();
+
+        let updated = vec![create_test_page(1, "First Page", "gamma content")];
+        search_engine
+            .apply_changes(&updated, &[2], &NoProgress, &CancelToken::new())
+            .unwrap();
+
+        assert!(search(&search_engine, "alpha").is_empty());
+        assert!(search(&search_engine, "beta").is_empty());
+        let results = search(&search_engine, "gamma");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, 1);
+    }
+
+    #[test]
+    fn test_indexed_commit_payload() {
+        let (search_engine, temp_dir) = create_test_search_engine();
+        assert_eq!(search_engine.indexed_commit().unwrap(), None);
+
+        search_engine.set_indexed_commit("abc123").unwrap();
+        assert_eq!(
+            search_engine.indexed_commit().unwrap().as_deref(),
+            Some("abc123")
+        );
+
+        let reopened = TantivySearch::new(temp_dir.path()).unwrap();
+        assert_eq!(reopened.indexed_commit().unwrap().as_deref(), Some("abc123"));
+    }
+
+    #[test]
+    fn test_optimize_merges_segments() {
         let (mut search_engine, _temp_dir) = create_test_search_engine();
+        search_engine
+            .build_index(&[
+                create_test_page(1, "First Page", "alpha content"),
+                create_test_page(2, "Second Page", "beta content"),
+            ])
+            .unwrap();
+        search_engine
+            .update_page(&create_test_page(1, "First Page", "gamma content"))
+            .unwrap();
+        search_engine.set_indexed_commit("abc123").unwrap();
+
+        assert_eq!(search_engine.optimize().unwrap(), 2);
+        assert_eq!(
+            search_engine.index.searchable_segment_ids().unwrap().len(),
+            1
+        );
+        assert!(search(&search_engine, "alpha").is_empty());
+        assert_eq!(search(&search_engine, "beta").len(), 1);
+        assert_eq!(search(&search_engine, "gamma").len(), 1);
+        assert_eq!(
+            search_engine.indexed_commit().unwrap().as_deref(),
+            Some("abc123")
+        );
+    }
+
+    #[test]
+    fn test_apply_changes_cancelled_keeps_old_index() {
+        let (mut search_engine, _temp_dir) = create_test_search_engine();
+        search_engine
+            .build_index(&[create_test_page(1, "First Page", "alpha content")])
+            .unwrap();
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let result = search_engine.apply_changes(
+            &[create_test_page(2, "Second Page", "beta content")],
+            &[1],
+            &NoProgress,
+            &cancel,
+        );
+
+        assert!(matches!(result, Err(SearchError::Cancelled)));
+        assert_eq!(search(&search_engine, "alpha").len(), 1);
+        assert!(search(&search_engine, "beta").is_empty());
+    }
+
+    #[test]
+    fn test_rebuild_title_keyed_index() {
+        let temp_dir = TempDir::new().unwrap();
+        let index_path = temp_dir.path().join("index");
+        {
+            // 旧版本的索引：`id` 是标题字符串
+            let mut schema_builder = tantivy::schema::Schema::builder();
+            let id = schema_builder
+                .add_text_field("id", tantivy::schema::STRING | tantivy::schema::STORED);
+            schema_builder.add_text_field("title", tantivy::schema::TEXT | tantivy::schema::STORED);
+            schema_builder
+                .add_text_field("content", tantivy::schema::TEXT | tantivy::schema::STORED);
+            std::fs::create_dir_all(&index_path).unwrap();
+            let index = tantivy::Index::create_in_dir(&index_path, schema_builder.build()).unwrap();
+            let mut writer: IndexWriter<TantivyDocument> = index.writer(50_000_000).unwrap();
+            writer.add_document(doc!(id => "First Page")).unwrap();
+            writer.commit().unwrap();
+        }
+
+        let old = TantivySearch::new(&index_path).unwrap();
+        assert!(!old.has_current_schema());
+        old.set_indexed_commit("abc123").unwrap();
+
+        let pages = vec![
+            create_test_page(1, "First Page", "alpha content"),
+            create_test_page(2, "First Page", "beta content"),
+        ];
+        let rebuilt = old.rebuild(&index_path, &pages).unwrap();
+        assert!(rebuilt.has_current_schema());
+        assert_eq!(rebuilt.indexed_commit().unwrap().as_deref(), Some("abc123"));
+        // 标题相同的两个页面都能搜到
+        let mut ids: Vec<u64> = search(&rebuilt, "first").iter().map(|r| r.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2]);
+        assert!(!index_path.with_extension("partial").exists());
+    }
+
+    #[test]
+    fn test_formula_search() {
+        let (mut search_engine, _temp_dir) = create_test_search_engine();
+
+        let mut monoidal = create_test_page(1, "monoidal category", "a category with a product");
+        monoidal.math = vec![r"A \otimes B".to_string(), r"\mathbf{H}".to_string()];
+        let mut spectrum = create_test_page(2, "spectrum", "stable homotopy");
+        spectrum.math = vec![r"\Omega^\infty \Sigma^\infty X".to_string()];
+        let mut omega = create_test_page(3, "loop space", "loops in a space");
+        omega.math = vec![r"\Omega X".to_string(), r"\infty".to_string()];
+        search_engine
+            .build_index(&[monoidal, spectrum, omega])
+            .unwrap();
+
+        let ids = |query: &str| -> Vec<u64> {
+            let mut ids: Vec<u64> = search(&search_engine, query).iter().map(|r| r.id).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(r"\otimes"), vec![1]);
+        assert_eq!(ids(r"\mathbf H"), vec![1]);
+        // 按短语匹配：`\Omega` 和 `\infty` 分别出现在不同公式中的页面不算
+        assert_eq!(ids(r"\Omega^\infty"), vec![2]);
+        assert_eq!(ids(r"\Omega"), vec![2, 3]);
+        assert_eq!(ids(r"category $\otimes$"), vec![1]);
+        assert_eq!(ids(r"space $\otimes$"), Vec::<u64>::new());
+        // 公式的渲染结果不在正文中
+        assert_eq!(ids("infty"), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn test_section_results() {
+        use crate::models::Section;
+
+        let (mut search_engine, _temp_dir) = create_test_search_engine();
+
+        let content = "A topos is a category. Properties Every topos is cartesian closed. \
+                       Examples Sheaves on a space form a topos.";
+        let properties = content.find("Properties").unwrap();
+        let examples = content.find("Examples").unwrap();
+        let topos = create_test_page(1, "topos", content).with_sections(vec![
+            Section {
+                heading: "Properties".to_string(),
+                anchor: Some("properties".to_string()),
+                start: properties,
+                end: examples,
+            },
+            Section {
+                heading: "Examples".to_string(),
+                anchor: Some("examples".to_string()),
+                start: examples,
+                end: content.len(),
+            },
+        ]);
+        let sheaf = create_test_page(2, "sheaf", "A sheaf on a space.");
+        search_engine.build_index(&[topos.clone(), sheaf]).unwrap();
+
+        // 只在一节中出现的词链接到这一节，每个页面只出现一次
+        let results = search(&search_engine, "cartesian");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, 1);
+        assert_eq!(results[0].anchor.as_deref(), Some("properties"));
+        assert_eq!(results[0].section.as_deref(), Some("Properties"));
+
+        let results = search(&search_engine, "sheaves space");
+        let ids: Vec<u64> = results.iter().map(|r| r.id).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&1) && ids.contains(&2));
+
+        // 按标题匹配的是页面本身
+        let results = search(&search_engine, "title:topos");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].anchor, None);
+
+        // 删除页面时它的各节一起删除
+        search_engine.delete_page(1).unwrap();
+        assert!(search(&search_engine, "cartesian").is_empty());
+    }
+
+    #[test]
+    fn test_limit_counts_pages_not_sections() {
+        use crate::models::Section;
+
+        let (mut search_engine, _temp_dir) = create_test_search_engine();
+
+        // 一个页面有很多节都匹配，得分都高于其余页面
+        let mut content = String::new();
+        let mut sections = Vec::new();
+        for i in 0..20 {
+            let start = content.len();
+            content.push_str(&format!("Part {} topos topos topos. ", i));
+            sections.push(Section {
+                heading: format!("Part {}", i),
+                anchor: Some(format!("part-{}", i)),
+                start,
+                end: content.len(),
+            });
+        }
+        let mut pages = vec![create_test_page(1, "topos", &content).with_sections(sections)];
+        for id in 2..=5 {
+            let filler = "filler text that makes the match weak. ".repeat(20);
+            let page = create_test_page(id, "other", &format!("a topos {}", filler));
+            pages.push(page);
+        }
+        search_engine.build_index(&pages).unwrap();
+
+        let results = search_engine
+            .search_with_filters("topos", 3, SearchFilters::default())
+            .unwrap();
+        assert_eq!(results[0].id, 1);
+        let mut ids: Vec<u64> = results.iter().map(|r| r.id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 3);
+
+        // 命中不足时返回全部页面
+        assert_eq!(search(&search_engine, "topos").len(), 5);
+    }
+
+    #[test]
+    fn test_facet_filters_and_counts() {
+        use crate::models::Section;
+
+        let (mut search_engine, _temp_dir) = create_test_search_engine();
+
+        let content = "Work on homotopy type theory. Publications Papers on homotopy.";
+        let publications = content.find("Publications").unwrap();
+        let person = create_test_page(1, "Some Person", content)
+            .with_sections(vec![Section {
+                heading: "Publications".to_string(),
+                anchor: Some("publications".to_string()),
+                start: publications,
+                end: content.len(),
+            }])
+            .with_facets(
+                vec!["people".to_string()],
+                vec!["Homotopy theory".to_string()],
+            );
+        let spectrum = create_test_page(2, "spectrum", "stable homotopy")
+            .with_facets(Vec::new(), vec!["Homotopy theory".to_string()]);
+        let topos = create_test_page(3, "topos", "homotopy and sheaves").with_facets(
+            vec!["reference".to_string()],
+            vec!["Topos theory".to_string()],
+        );
+        search_engine
+            .build_index(&[person, spectrum, topos])
+            .unwrap();
+
+        let ids = |include: Vec<FacetFilter>, exclude: Vec<FacetFilter>| -> Vec<u64> {
+            let filters = SearchFilters {
+                include,
+                exclude,
+                ..SearchFilters::default()
+            };
+            let mut ids: Vec<u64> = search_engine
+                .search_with_filters("homotopy", 10, filters)
+                .unwrap()
+                .iter()
+                .map(|r| r.id)
+                .collect();
+            ids.sort();
+            ids
+        };
+        let category = |value: &str| FacetFilter {
+            kind: FacetKind::Category,
+            value: value.to_string(),
+        };
+        let context = |value: &str| FacetFilter {
+            kind: FacetKind::Context,
+            value: value.to_string(),
+        };
+
+        assert_eq!(ids(vec![], vec![]), vec![1, 2, 3]);
+        // 排除分类时页面的各节也被排除
+        assert_eq!(ids(vec![], vec![category("people")]), vec![2, 3]);
+        assert_eq!(ids(vec![context("Homotopy theory")], vec![]), vec![1, 2]);
+        assert_eq!(
+            ids(
+                vec![context("Homotopy theory"), context("Topos theory")],
+                vec![category("people")]
+            ),
+            vec![2, 3]
+        );
+        assert_eq!(
+            ids(
+                vec![context("Homotopy theory"), category("reference")],
+                vec![]
+            ),
+            Vec::<u64>::new()
+        );
+
+        // 同时匹配页面和一节的页面只算一次
+        let counts = search_engine
+            .facet_counts("homotopy", &SearchFilters::default())
+            .unwrap();
+        let count = |kind: FacetKind, value: &str| {
+            counts
+                .iter()
+                .find(|c| c.kind == kind && c.value == value)
+                .map(|c| c.count)
+        };
+        assert_eq!(count(FacetKind::Context, "Homotopy theory"), Some(2));
+        assert_eq!(count(FacetKind::Context, "Topos theory"), Some(1));
+        assert_eq!(count(FacetKind::Category, "people"), Some(1));
+        assert_eq!(count(FacetKind::Category, "reference"), Some(1));
+
+        let filters = SearchFilters {
+            exclude: vec![category("people")],
+            ..SearchFilters::default()
+        };
+        let counts = search_engine.facet_counts("homotopy", &filters).unwrap();
+        assert!(counts.iter().all(|c| c.value != "people"));
+        assert!(search_engine
+            .facet_counts("", &SearchFilters::default())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_apply_changes_empty() {
+        let (search_engine, _temp_dir) = create_test_search_engine();
 
         // 测试空批量更新
         let empty_pages: Vec<NLabPage> = vec![];
-        let result = search_engine.update_pages_batch(&empty_pages);
+        let result =
+            search_engine.apply_changes(&empty_pages, &[], &NoProgress, &CancelToken::new());
         assert!(result.is_ok());
         println!("✓ Empty batch update handled correctly");
     }
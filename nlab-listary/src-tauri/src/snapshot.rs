@@ -0,0 +1,260 @@
+use crate::cancel::CancelToken;
+use crate::maintenance::{ensure_free_space, estimated_data_size, InsufficientSpace};
+use crate::parser::{index_commit, ParseHtmlError};
+use crate::progress::ProgressSink;
+use crate::search::{SearchEngine, SearchError, TantivySearch};
+use crate::storage::{Storage, StorageError};
+use git2::Oid;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("Git error: {0}")]
+    Git(#[from] git2::Error),
+
+    #[error("Parser error: {0}")]
+    Parser(#[from] ParseHtmlError),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error("Search error: {0}")]
+    Search(#[from] SearchError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid snapshot info: {0}")]
+    Info(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    DiskSpace(#[from] InsufficientSpace),
+}
+
+/// 快照的描述，保存在快照目录的 `snapshot.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    /// 用户请求的版本：提交号、标签或日期
+    pub revision: String,
+    pub commit: String,
+    /// 提交时间（Unix 时间戳）
+    pub commit_time: i64,
+}
+
+/// 某个历史提交的独立存储和搜索索引，与实时索引互不影响
+pub struct Snapshot {
+    pub info: SnapshotInfo,
+    pub storage: Storage,
+    pub search_engine: TantivySearch,
+}
+
+const INFO_FILE: &str = "snapshot.json";
+/// 构建中的快照目录后缀，构建完成后重命名
+const PARTIAL_SUFFIX: &str = ".partial";
+
+impl Snapshot {
+    /// 打开已构建的快照目录
+    pub fn open(dir: &Path) -> Result<Self, SnapshotError> {
+        let info = read_info(dir)?;
+        let storage = Storage::new(dir.join("storage").to_str().unwrap())?;
+        let mut search_engine = TantivySearch::new(dir.join("index"))?;
+        // 旧版本构建的快照以标题为页面 ID，用迁移后的页面重建索引
+        if !search_engine.has_current_schema() {
+            search_engine = search_engine.rebuild(&dir.join("index"), &storage.pages()?)?;
+        }
+        Ok(Self {
+            info,
+            storage,
+            search_engine,
+        })
+    }
+
+    /// 直接从对象库中 `commit` 的树构建快照，不改变工作目录；已构建过时直接打开
+    pub fn build(
+        repo_path: &Path,
+        root: &Path,
+        revision: &str,
+        commit: Oid,
+        progress: &dyn ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<Self, SnapshotError> {
+        let dir = snapshot_dir(root, commit);
+        if dir.exists() {
+            return Self::open(&dir);
+        }
+
+        let commit_time = git2::Repository::open(repo_path)?
+            .find_commit(commit)?
+            .time()
+            .seconds();
+        let info = SnapshotInfo {
+            revision: revision.to_string(),
+            commit: commit.to_string(),
+            commit_time,
+        };
+
+        // 在临时目录中构建，失败或取消时删除，避免留下不完整的快照
+        let partial = root.join(format!("{}{}", commit, PARTIAL_SUFFIX));
+        if partial.exists() {
+            fs::remove_dir_all(&partial)?;
+        }
+        if let Err(e) = build_into(&partial, repo_path, &info, commit, progress, cancel) {
+            fs::remove_dir_all(&partial).ok();
+            return Err(e);
+        }
+
+        fs::rename(&partial, &dir)?;
+        Self::open(&dir)
+    }
+}
+
+fn build_into(
+    dir: &Path,
+    repo_path: &Path,
+    info: &SnapshotInfo,
+    commit: Oid,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<(), SnapshotError> {
+    let pages = index_commit(repo_path, commit, progress, cancel)?;
+    ensure_free_space(dir, estimated_data_size(&pages))?;
+
+    let storage = Storage::new(dir.join("storage").to_str().unwrap())?;
+    storage.save_pages_batch(&pages)?;
+    let mut search_engine = TantivySearch::new(dir.join("index"))?;
+    search_engine.build_index_with_progress(&pages, progress, cancel)?;
+
+    fs::write(dir.join(INFO_FILE), serde_json::to_string_pretty(info)?)?;
+    Ok(())
+}
+
+fn read_info(dir: &Path) -> Result<SnapshotInfo, SnapshotError> {
+    let content = fs::read_to_string(dir.join(INFO_FILE))?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+pub fn snapshot_dir(root: &Path, commit: Oid) -> PathBuf {
+    root.join(commit.to_string())
+}
+
+/// 列出 `root` 下已构建完成的快照，按提交时间排序
+pub fn list_snapshots(root: &Path) -> Vec<SnapshotInfo> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+
+    let mut snapshots: Vec<SnapshotInfo> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            !entry
+                .file_name()
+                .to_string_lossy()
+                .ends_with(PARTIAL_SUFFIX)
+        })
+        .filter_map(|entry| read_info(&entry.path()).ok())
+        .collect();
+    snapshots.sort_by_key(|info| info.commit_time);
+    snapshots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::NoProgress;
+    use crate::search::SearchFilters;
+    use tempfile::TempDir;
+
+    fn page_html(name: &str, content: &str) -> String {
+        format!(
+            r#"<h1 id="pageName">{name}</h1>
+            <div id="revision">{content}</div>
+            <a id="edit" href="/nlab/edit/{name}">Edit</a>"#
+        )
+    }
+
+    // 提交 pages/1/1/content.html，返回提交的 OID
+    fn commit_page(repo: &git2::Repository, html: &str) -> Oid {
+        let workdir = repo.workdir().unwrap();
+        fs::create_dir_all(workdir.join("pages/1/1")).unwrap();
+        fs::write(workdir.join("pages/1/1/content.html"), html).unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("pages/1/1/content.html")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let parents: Vec<git2::Commit> = repo
+            .head()
+            .ok()
+            .and_then(|head| head.peel_to_commit().ok())
+            .into_iter()
+            .collect();
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "page",
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_snapshot_searches_old_revision() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo_path = temp_dir.path().join("repo");
+        let repo = git2::Repository::init(&repo_path).unwrap();
+        let old = commit_page(&repo, &page_html("topos", "an elementary topos"));
+        commit_page(&repo, &page_html("topos", "a Grothendieck topos"));
+
+        let root = temp_dir.path().join("snapshots");
+        fs::create_dir_all(&root).unwrap();
+        let snapshot = Snapshot::build(
+            &repo_path,
+            &root,
+            "first",
+            old,
+            &NoProgress,
+            &CancelToken::new(),
+        )
+        .unwrap();
+
+        let search = |query: &str| {
+            snapshot
+                .search_engine
+                .search_with_filters(query, 10, SearchFilters::default())
+                .unwrap()
+        };
+        assert_eq!(search("elementary").len(), 1);
+        assert!(search("Grothendieck").is_empty());
+        drop(snapshot);
+
+        let snapshots = list_snapshots(&root);
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].commit, old.to_string());
+        assert_eq!(snapshots[0].revision, "first");
+        assert!(!root.join(format!("{}{}", old, PARTIAL_SUFFIX)).exists());
+    }
+
+    #[test]
+    fn test_cancelled_snapshot_leaves_nothing() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo_path = temp_dir.path().join("repo");
+        let repo = git2::Repository::init(&repo_path).unwrap();
+        let commit = commit_page(&repo, &page_html("topos", "an elementary topos"));
+
+        let root = temp_dir.path().join("snapshots");
+        fs::create_dir_all(&root).unwrap();
+        let cancel = CancelToken::new();
+        cancel.cancel();
+
+        assert!(Snapshot::build(&repo_path, &root, "HEAD", commit, &NoProgress, &cancel).is_err());
+        assert!(list_snapshots(&root).is_empty());
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,420 @@
+use crate::cancel::CancelToken;
+use crate::git_ops::{head_commit, is_page_file};
+use crate::models::NLabPage;
+use crate::parser::{index_commit, index_local_files, ParseHtmlError};
+use crate::progress::{Phase, Progress, ProgressSink};
+use flate2::read::GzDecoder;
+use std::cell::Cell;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SourceError {
+    #[error("Parser error: {0}")]
+    Parser(#[from] ParseHtmlError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Zip error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
+    #[error("Unsupported source: {0}")]
+    Unsupported(String),
+
+    #[error("Extraction cancelled")]
+    Cancelled,
+}
+
+/// 页面数据的来源，在配置文件的 `source` 中设置；未设置时使用 git 镜像
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// git 镜像，支持增量同步、修改历史和快照
+    Git(PathBuf),
+    /// 导出的页面目录，例如 `nlab-content-html` 的检出
+    Directory(PathBuf),
+    /// `nlab-content-html` 的 `.tar.gz` 或 `.zip` 压缩包，解析前先解压到 `extract_dir`
+    Archive {
+        archive: PathBuf,
+        extract_dir: PathBuf,
+    },
+}
+
+/// 解压时每处理这么多个条目报告一次进度
+const EXTRACT_PROGRESS_STEP: usize = 100;
+
+impl Source {
+    /// `source` 为配置中的路径：目录、`.tar.gz`/`.tgz` 或 `.zip`；为空时使用 `git_path` 的镜像
+    pub fn from_config(
+        source: Option<&str>,
+        git_path: &Path,
+        extract_dir: &Path,
+    ) -> Result<Self, SourceError> {
+        let Some(source) = source else {
+            return Ok(Source::Git(git_path.to_path_buf()));
+        };
+
+        let path = PathBuf::from(source);
+        if path.is_dir() {
+            return Ok(Source::Directory(path));
+        }
+        if !path.is_file() {
+            return Err(SourceError::Unsupported(format!(
+                "{} does not exist",
+                source
+            )));
+        }
+        if archive_kind(&path).is_none() {
+            return Err(SourceError::Unsupported(format!(
+                "{} is not a directory, .tar.gz or .zip archive",
+                source
+            )));
+        }
+        Ok(Source::Archive {
+            archive: path,
+            extract_dir: extract_dir.to_path_buf(),
+        })
+    }
+
+    pub fn is_git(&self) -> bool {
+        matches!(self, Source::Git(_))
+    }
+
+    /// 记录在存储中的来源说明
+    pub fn describe(&self) -> String {
+        match self {
+            Source::Git(path) | Source::Directory(path) => path.display().to_string(),
+            Source::Archive { archive, .. } => archive.display().to_string(),
+        }
+    }
+
+    /// 数据对应的版本：git 镜像为 HEAD 提交，其余来源没有版本，使用来源路径
+    pub fn revision(&self) -> Option<String> {
+        match self {
+            Source::Git(path) => head_commit(path).ok().map(|oid| oid.to_string()),
+            _ => Some(self.describe()),
+        }
+    }
+
+    /// 读取并解析全部页面
+    ///
+    /// git 镜像从 HEAD 的对象库解析（HEAD 无效时退回工作目录），压缩包先解压
+    pub fn read_pages(
+        &self,
+        progress: &dyn ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<Vec<NLabPage>, SourceError> {
+        let pages = match self {
+            Source::Git(path) => match head_commit(path) {
+                Ok(head) => index_commit(path, head, progress, cancel)?,
+                Err(e) => {
+                    eprintln!("failed to resolve HEAD, parsing working tree: {}", e);
+                    index_local_files(path, progress, cancel)?
+                }
+            },
+            Source::Directory(path) => index_local_files(path, progress, cancel)?,
+            Source::Archive {
+                archive,
+                extract_dir,
+            } => {
+                extract_archive(archive, extract_dir, progress, cancel)?;
+                index_local_files(extract_dir, progress, cancel)?
+            }
+        };
+        Ok(pages)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArchiveKind {
+    TarGz,
+    Zip,
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    archive_kind(path).is_some()
+}
+
+fn archive_kind(path: &Path) -> Option<ArchiveKind> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(ArchiveKind::TarGz)
+    } else if name.ends_with(".zip") {
+        Some(ArchiveKind::Zip)
+    } else {
+        None
+    }
+}
+
+/// 把压缩包中的 `pages/**/content.html` 解压到 `dest`，返回解压的文件数
+pub fn extract_archive(
+    archive: &Path,
+    dest: &Path,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<usize, SourceError> {
+    let count = extract_selected(archive, dest, page_path, progress, cancel)?;
+    println!("解压完成: {} 个页面文件", count);
+    Ok(count)
+}
+
+/// 解压压缩包中 `select` 选中的文件，`select` 返回文件在 `dest` 中的相对路径
+///
+/// 先解压到临时目录，完成后替换 `dest`；失败或取消时保留原来的 `dest`
+pub fn extract_selected(
+    archive: &Path,
+    dest: &Path,
+    select: impl Fn(&Path) -> Option<PathBuf>,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<usize, SourceError> {
+    println!("正在解压 {} ...", archive.display());
+    let partial = dest.with_extension("partial");
+    if partial.exists() {
+        fs::remove_dir_all(&partial)?;
+    }
+    fs::create_dir_all(&partial)?;
+
+    let result = match archive_kind(archive) {
+        Some(ArchiveKind::TarGz) => extract_tar_gz(archive, &partial, &select, progress, cancel),
+        Some(ArchiveKind::Zip) => extract_zip(archive, &partial, &select, progress, cancel),
+        None => Err(SourceError::Unsupported(archive.display().to_string())),
+    };
+    let count = match result {
+        Ok(count) => count,
+        Err(e) => {
+            fs::remove_dir_all(&partial).ok();
+            return Err(e);
+        }
+    };
+
+    if dest.exists() {
+        fs::remove_dir_all(dest)?;
+    }
+    fs::rename(&partial, dest)?;
+    Ok(count)
+}
+
+/// 记录已读取字节数的 reader，用于报告 `.tar.gz` 的解压进度
+struct CountingReader<R> {
+    inner: R,
+    read: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read.set(self.read.get() + read as u64);
+        Ok(read)
+    }
+}
+
+/// tar 没有目录，无法预先知道条目数，进度按已读取的压缩数据计算
+fn extract_tar_gz(
+    archive: &Path,
+    dest: &Path,
+    select: &dyn Fn(&Path) -> Option<PathBuf>,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<usize, SourceError> {
+    let file = File::open(archive)?;
+    let total = file.metadata()?.len() as usize;
+    let read = Rc::new(Cell::new(0));
+    let reader = CountingReader {
+        inner: file,
+        read: read.clone(),
+    };
+    let mut tar = tar::Archive::new(GzDecoder::new(reader));
+
+    let mut count = 0;
+    for (index, entry) in tar.entries()?.enumerate() {
+        if cancel.is_cancelled() {
+            return Err(SourceError::Cancelled);
+        }
+        let mut entry = entry?;
+        let is_file = entry.header().entry_type().is_file();
+        if let Some(relative) = select(&entry.path()?).filter(|_| is_file) {
+            let target = dest.join(relative);
+            fs::create_dir_all(target.parent().unwrap())?;
+            entry.unpack(&target)?;
+            count += 1;
+        }
+        if (index + 1).is_multiple_of(EXTRACT_PROGRESS_STEP) {
+            progress.report(Progress::new(Phase::Extract, read.get() as usize, total));
+        }
+    }
+
+    progress.report(Progress::new(Phase::Extract, total, total));
+    Ok(count)
+}
+
+fn extract_zip(
+    archive: &Path,
+    dest: &Path,
+    select: &dyn Fn(&Path) -> Option<PathBuf>,
+    progress: &dyn ProgressSink,
+    cancel: &CancelToken,
+) -> Result<usize, SourceError> {
+    let mut zip = zip::ZipArchive::new(File::open(archive)?)?;
+    let total = zip.len();
+
+    let mut count = 0;
+    for index in 0..total {
+        if cancel.is_cancelled() {
+            return Err(SourceError::Cancelled);
+        }
+        let mut file = zip.by_index(index)?;
+        // `enclosed_name` 拒绝包含 `..` 或绝对路径的条目
+        let relative = file
+            .enclosed_name()
+            .filter(|_| file.is_file())
+            .and_then(|name| select(&name));
+        if let Some(relative) = relative {
+            let target = dest.join(relative);
+            fs::create_dir_all(target.parent().unwrap())?;
+            io::copy(&mut file, &mut File::create(&target)?)?;
+            count += 1;
+        }
+        if (index + 1).is_multiple_of(EXTRACT_PROGRESS_STEP) || index + 1 == total {
+            progress.report(Progress::new(Phase::Extract, index + 1, total));
+        }
+    }
+
+    Ok(count)
+}
+
+/// 压缩包内的页面路径去掉顶层目录（例如 `nlab-content-html-master/`），得到 `pages/**/content.html`
+///
+/// 不是页面文件或包含 `..` 等非普通路径时返回 `None`
+fn page_path(path: &Path) -> Option<PathBuf> {
+    let relative = path_from(path, "pages")?;
+    is_page_file(relative.to_str()?).then_some(relative)
+}
+
+/// 从第一个名为 `name` 的目录开始的路径，去掉它之前的目录；包含 `..` 等非普通路径时返回 `None`
+pub fn path_from(path: &Path, name: &str) -> Option<PathBuf> {
+    let components: Vec<Component> = path.components().collect();
+    let start = components
+        .iter()
+        .position(|component| component.as_os_str() == name)?;
+    let rest = &components[start..];
+    if !rest
+        .iter()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    Some(rest.iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::NoProgress;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    const PAGE_HTML: &str = r#"<h1 id="pageName">topos</h1>
+        <div id="revision">an elementary topos</div>
+        <a id="edit" href="/nlab/edit/topos">Edit</a>"#;
+
+    // 压缩包中的文件：一个页面和一个不需要解压的文件
+    const ENTRIES: [(&str, &str); 2] = [
+        ("nlab-content-html-master/pages/1/1/content.html", PAGE_HTML),
+        ("nlab-content-html-master/README.md", "readme"),
+    ];
+
+    fn assert_single_page(source: &Source) {
+        let pages = source.read_pages(&NoProgress, &CancelToken::new()).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].title, "topos");
+        assert_eq!(pages[0].file_path, "pages/1/1/content.html");
+    }
+
+    #[test]
+    fn test_tar_gz_source() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("nlab.tar.gz");
+        let mut builder = tar::Builder::new(GzEncoder::new(
+            File::create(&archive).unwrap(),
+            flate2::Compression::default(),
+        ));
+        for (name, content) in ENTRIES {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let extract_dir = temp_dir.path().join("extracted");
+        let source = Source::from_config(
+            archive.to_str(),
+            &temp_dir.path().join("mirror"),
+            &extract_dir,
+        )
+        .unwrap();
+        assert!(!source.is_git());
+        assert_single_page(&source);
+        assert!(!extract_dir.join("README.md").exists());
+        assert!(!extract_dir.with_extension("partial").exists());
+    }
+
+    #[test]
+    fn test_zip_and_directory_sources() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("nlab.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&archive).unwrap());
+        for (name, content) in ENTRIES {
+            writer
+                .start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let extract_dir = temp_dir.path().join("extracted");
+        let source = Source::from_config(
+            archive.to_str(),
+            &temp_dir.path().join("mirror"),
+            &extract_dir,
+        )
+        .unwrap();
+        assert_single_page(&source);
+
+        // 解压后的目录本身也可以作为来源
+        let source = Source::from_config(
+            extract_dir.to_str(),
+            &temp_dir.path().join("mirror"),
+            &temp_dir.path().join("unused"),
+        )
+        .unwrap();
+        assert_eq!(source, Source::Directory(extract_dir.clone()));
+        assert_single_page(&source);
+
+        assert!(Source::from_config(
+            temp_dir.path().join("missing.zip").to_str(),
+            &temp_dir.path().join("mirror"),
+            &extract_dir,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_page_path() {
+        assert_eq!(
+            page_path(Path::new("nlab-content-html-master/pages/1/1/content.html")),
+            Some(PathBuf::from("pages/1/1/content.html"))
+        );
+        assert_eq!(page_path(Path::new("pages/1/1/source.md")), None);
+        assert_eq!(page_path(Path::new("pages/../1/content.html")), None);
+    }
+}
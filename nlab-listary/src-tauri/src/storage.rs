@@ -1,4 +1,8 @@
-use crate::{models::NLabPage, parser};
+use crate::models::{normalize_slug, page_id_from_path, NLabPage, Section};
+use crate::parser;
+use bincode::{Decode, Encode};
+use serde::Serialize;
+use std::path::Path;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -38,30 +42,350 @@ impl From<parser::ParseHtmlError> for StorageError {
 pub type Result<T> = std::result::Result<T, StorageError>;
 
 pub struct Storage {
+    // 默认树只保存 `meta:` 开头的元数据
     db: sled::Db,
+    // 页面编号（大端序）到页面的映射
+    pages: sled::Tree,
+    // 文件路径到页面编号的映射，用于增量同步时找到被删除或改名的页面
+    paths: sled::Tree,
+    // 标题和页面编号拼成的键，标题可能重复，按前缀查找
+    titles: sled::Tree,
+    // 规范化的 URL 页面名到页面编号的映射
+    slugs: sled::Tree,
+    // 链接目标的页面名和链接所在页面的编号拼成的键，用于查找链接到某个页面的页面
+    backlinks: sled::Tree,
+    // 同步记录，按写入顺序以递增 ID 为键
+    history: sled::Tree,
 }
 
 const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+const PAGES_TREE: &str = "pages";
+const PATHS_TREE: &str = "paths";
+const TITLES_TREE: &str = "titles";
+const SLUGS_TREE: &str = "slugs";
+const BACKLINKS_TREE: &str = "backlinks";
+const HISTORY_TREE: &str = "sync_history";
+/// 最近一次成功同步所用的镜像地址
+pub const META_MIRROR: &str = "meta:mirror";
+/// 最近一次成功同步或构建索引的 `SyncRecord`
+pub const META_LAST_SYNC: &str = "meta:last_sync";
+/// 最近一次同步尝试（无论成功与否）的 `SyncAttempt`，定时同步据此计算下一次时间
+pub const META_LAST_ATTEMPT: &str = "meta:last_attempt";
+/// 数据格式的版本，没有时为 1（以标题为页面 ID，页面保存在默认树中）
+const META_SCHEMA_VERSION: &str = "meta:schema_version";
+/// 迁移后标记：旧数据缺少需要从页面文件中读取的内容，下一次同步需要重新读取全部页面
+const META_REREAD_PENDING: &str = "meta:reread_pending";
+/// 版本 2 以页面编号为键，版本 3 保存页面中的链接，版本 4 单独保存公式，版本 5 保存分节，
+/// 版本 6 保存分类和领域
+const SCHEMA_VERSION: u32 = 6;
+/// `reopen` 等待文件锁释放的最大重试次数
+const REOPEN_ATTEMPTS: u32 = 50;
+
+/// 版本 1 的页面格式，只用于迁移
+#[derive(Decode)]
+struct LegacyPage {
+    _id: String,
+    title: String,
+    file_path: String,
+    url: String,
+    content: String,
+}
+
+/// 版本 2 的页面格式（没有链接），只用于迁移
+#[derive(Decode)]
+struct PageV2 {
+    id: u64,
+    title: String,
+    file_path: String,
+    url: String,
+    content: String,
+}
+
+/// 版本 3 的页面格式（没有公式，公式的渲染结果混在内容中），只用于迁移
+#[derive(Decode)]
+struct PageV3 {
+    id: u64,
+    title: String,
+    file_path: String,
+    url: String,
+    content: String,
+    links: Vec<String>,
+}
+
+/// 版本 4 的页面格式（没有分节），只用于迁移
+#[derive(Decode)]
+struct PageV4 {
+    id: u64,
+    title: String,
+    file_path: String,
+    url: String,
+    content: String,
+    links: Vec<String>,
+    math: Vec<String>,
+}
+
+/// 版本 5 的页面格式（没有分类和领域），只用于迁移
+#[derive(Decode)]
+struct PageV5 {
+    id: u64,
+    title: String,
+    file_path: String,
+    url: String,
+    content: String,
+    links: Vec<String>,
+    math: Vec<String>,
+    sections: Vec<Section>,
+}
+
+/// 按版本 2 到 5 的格式读取页面，缺少的字段留空
+fn decode_old_page(
+    version: u32,
+    bytes: &[u8],
+) -> std::result::Result<NLabPage, bincode::error::DecodeError> {
+    let page = match version {
+        2 => {
+            let (old, _): (PageV2, usize) = bincode::decode_from_slice(bytes, BINCODE_CONFIG)?;
+            NLabPage::new(
+                old.id,
+                old.file_path,
+                old.title,
+                old.url,
+                old.content,
+                Vec::new(),
+                Vec::new(),
+            )
+        }
+        3 => {
+            let (old, _): (PageV3, usize) = bincode::decode_from_slice(bytes, BINCODE_CONFIG)?;
+            NLabPage::new(
+                old.id,
+                old.file_path,
+                old.title,
+                old.url,
+                old.content,
+                old.links,
+                Vec::new(),
+            )
+        }
+        4 => {
+            let (old, _): (PageV4, usize) = bincode::decode_from_slice(bytes, BINCODE_CONFIG)?;
+            NLabPage::new(
+                old.id,
+                old.file_path,
+                old.title,
+                old.url,
+                old.content,
+                old.links,
+                old.math,
+            )
+        }
+        _ => {
+            let (old, _): (PageV5, usize) = bincode::decode_from_slice(bytes, BINCODE_CONFIG)?;
+            NLabPage::new(
+                old.id,
+                old.file_path,
+                old.title,
+                old.url,
+                old.content,
+                old.links,
+                old.math,
+            )
+            .with_sections(old.sections)
+        }
+    };
+    Ok(page)
+}
+
+/// 一次成功的同步或索引构建
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub struct SyncRecord {
+    /// 数据对应的镜像提交
+    pub commit: String,
+    /// 完成时间（Unix 时间戳）
+    pub timestamp: i64,
+    pub page_count: usize,
+    pub duration_ms: u64,
+}
+
+/// 一次同步尝试的结果
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub struct SyncAttempt {
+    /// 结束时间（Unix 时间戳）
+    pub timestamp: i64,
+    /// 失败时的错误信息；成功或取消时为 `None`
+    pub error: Option<String>,
+}
+
+/// 一次运行的类型
+#[derive(Debug, Clone, Copy, PartialEq, Encode, Decode, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunKind {
+    /// 首次运行时的克隆和全量构建
+    Initial,
+    /// 手动、启动时或定时的同步
+    Sync,
+    /// 导入 bundle
+    Bundle,
+    /// 启动时把镜像退回索引所在的提交
+    Repair,
+    /// 整理镜像、索引和数据库占用的空间
+    Maintenance,
+}
+
+/// 同步记录中的一次运行
+#[derive(Debug, Clone, PartialEq, Encode, Decode, Serialize)]
+pub struct SyncRun {
+    pub kind: RunKind,
+    /// 开始和结束时间（Unix 时间戳）
+    pub started_at: i64,
+    pub finished_at: i64,
+    pub mirror: Option<String>,
+    pub old_commit: Option<String>,
+    pub new_commit: Option<String>,
+    /// 仓库更新的结果，例如 `fast_forwarded`、`reset`、`recloned`；维护时为前后占用的字节数
+    pub outcome: Option<String>,
+    /// 全量重建时为解析出的全部页面数
+    pub pages_added: usize,
+    pub pages_modified: usize,
+    pub pages_deleted: usize,
+    /// 解析失败而被跳过的文件数
+    pub parse_failures: usize,
+    pub error: Option<String>,
+}
+
+impl SyncRun {
+    pub fn new(kind: RunKind, started_at: i64) -> Self {
+        Self {
+            kind,
+            started_at,
+            finished_at: started_at,
+            mirror: None,
+            old_commit: None,
+            new_commit: None,
+            outcome: None,
+            pages_added: 0,
+            pages_modified: 0,
+            pages_deleted: 0,
+            parse_failures: 0,
+            error: None,
+        }
+    }
+}
 
 impl Storage {
     pub fn new(path: &str) -> Result<Self> {
         let db: sled::Db = sled::open(path)?;
-        Ok(Self { db })
+        let storage = Self {
+            pages: db.open_tree(PAGES_TREE)?,
+            paths: db.open_tree(PATHS_TREE)?,
+            titles: db.open_tree(TITLES_TREE)?,
+            slugs: db.open_tree(SLUGS_TREE)?,
+            backlinks: db.open_tree(BACKLINKS_TREE)?,
+            history: db.open_tree(HISTORY_TREE)?,
+            db,
+        };
+        storage.migrate()?;
+        Ok(storage)
     }
 
-    // 页面元数据存储
-    // Key: page_id (String)
-    // Value: NLabPage (bincode 序列化)
-    pub fn save_page(&self, page: &NLabPage) -> Result<()> {
-        // 先计算实际大小，避免固定大小数组的浪费
-        let serialized: Vec<u8> = bincode::encode_to_vec(page, BINCODE_CONFIG)?;
+    /// 把旧版本的页面迁移到当前格式，返回是否迁移了页面
+    ///
+    /// 版本 1 以标题为键、保存在默认树中，标题相同而被覆盖的页面无法恢复；之后的版本缺少链接、
+    /// 公式、分节或分类。这些情况都标记下一次同步重新读取全部页面
+    fn migrate(&self) -> Result<bool> {
+        let version = match self.get_metadata(META_SCHEMA_VERSION)? {
+            Some(bytes) => u32::from_be_bytes(bytes.as_slice().try_into().unwrap_or_default()),
+            None => 1,
+        };
+        if version >= SCHEMA_VERSION {
+            return Ok(false);
+        }
+
+        let mut pages = Vec::new();
+        // 迁移完成后要删除的旧数据
+        let mut legacy_keys = sled::Batch::default();
+        let mut unreadable = sled::Batch::default();
+        let mut legacy_count = 0;
+        if version == 1 {
+            for entry in self.db.iter() {
+                let (key, bytes) = entry?;
+                if key.starts_with(b"meta:") {
+                    continue;
+                }
+                legacy_keys.remove(key);
+                legacy_count += 1;
+                match bincode::decode_from_slice::<LegacyPage, _>(&bytes, BINCODE_CONFIG) {
+                    Ok((legacy, _)) => match page_id_from_path(&legacy.file_path) {
+                        Some(id) => pages.push(NLabPage::new(
+                            id,
+                            legacy.file_path,
+                            legacy.title,
+                            legacy.url,
+                            legacy.content,
+                            Vec::new(),
+                            Vec::new(),
+                        )),
+                        None => eprintln!("dropping page without ID: {}", legacy.file_path),
+                    },
+                    Err(e) => eprintln!("dropping unreadable page: {}", e),
+                }
+            }
+        } else {
+            for entry in self.pages.iter() {
+                let (key, bytes) = entry?;
+                legacy_count += 1;
+                match decode_old_page(version, &bytes) {
+                    Ok(page) => pages.push(page),
+                    Err(e) => {
+                        eprintln!("dropping unreadable page: {}", e);
+                        unreadable.remove(key);
+                    }
+                }
+            }
+        }
+
+        if legacy_count > 0 {
+            println!(
+                "正在迁移 {} 个页面到版本 {} 的格式...",
+                legacy_count, SCHEMA_VERSION
+            );
+            // 二级索引全部由页面重新生成
+            for tree in [&self.paths, &self.titles, &self.slugs, &self.backlinks] {
+                tree.clear()?;
+            }
+            let mut batch = PageBatch::default();
+            for page in &pages {
+                insert_page(page, &mut batch)?;
+            }
+            self.pages.apply_batch(unreadable)?;
+            self.apply(batch)?;
+            self.db.apply_batch(legacy_keys)?;
+            self.set_metadata(META_REREAD_PENDING, &[1])?;
+        }
+        self.set_metadata(META_SCHEMA_VERSION, &SCHEMA_VERSION.to_be_bytes())?;
+        self.db.flush()?;
+        Ok(legacy_count > 0)
+    }
 
-        self.db.insert(page.id.as_bytes(), serialized)?;
+    /// 迁移后是否还需要重新读取全部页面
+    pub fn reread_pending(&self) -> Result<bool> {
+        Ok(self.get_metadata(META_REREAD_PENDING)?.is_some())
+    }
+
+    pub fn clear_reread_pending(&self) -> Result<()> {
+        self.db.remove(META_REREAD_PENDING)?;
         Ok(())
     }
 
-    pub fn get_page(&self, page_id: &str) -> Result<Option<NLabPage>> {
-        match self.db.get(page_id.as_bytes())? {
+    // 页面存储
+    // Key: 页面编号（u64 大端序）
+    // Value: NLabPage (bincode 序列化)
+    pub fn save_page(&self, page: &NLabPage) -> Result<()> {
+        self.save_pages_batch(std::slice::from_ref(page))
+    }
+
+    pub fn get_page(&self, page_id: u64) -> Result<Option<NLabPage>> {
+        match self.pages.get(page_id.to_be_bytes())? {
             Some(bytes) => {
                 let (page, _): (NLabPage, usize) =
                     bincode::decode_from_slice(&bytes, BINCODE_CONFIG)?;
@@ -71,19 +395,125 @@ impl Storage {
         }
     }
 
+    /// 全部页面，按页面编号排序
+    pub fn pages(&self) -> Result<Vec<NLabPage>> {
+        self.iter_pages().collect()
+    }
+
+    /// 按页面编号顺序逐个读取页面，不把全部页面放进内存
+    pub fn iter_pages(&self) -> impl Iterator<Item = Result<NLabPage>> {
+        self.pages.iter().map(|entry| {
+            let (_, bytes) = entry?;
+            let (page, _): (NLabPage, usize) = bincode::decode_from_slice(&bytes, BINCODE_CONFIG)?;
+            Ok(page)
+        })
+    }
+
+    /// 全部页面编号，按顺序排列，不解码页面
+    pub fn page_ids(&self) -> Result<Vec<u64>> {
+        self.pages
+            .iter()
+            .keys()
+            .map(|key| Ok(decode_id(key?).unwrap_or_default()))
+            .collect()
+    }
+
     // 批量操作（用于初始化和同步）
     pub fn save_pages_batch(&self, pages: &[NLabPage]) -> Result<()> {
-        let mut batch = sled::Batch::default();
+        let mut batch = PageBatch::default();
 
         for page in pages {
-            let serialized: Vec<u8> = bincode::encode_to_vec(&page, BINCODE_CONFIG)?;
-            batch.insert(page.id.as_bytes(), serialized);
+            // 页面改名后旧标题和旧页面名不能再指向它
+            if let Some(old) = self.get_page(page.id)? {
+                self.remove_lookups(&old, &mut batch)?;
+            }
+            insert_page(page, &mut batch)?;
         }
 
-        self.db.apply_batch(batch)?;
+        self.apply(batch)
+    }
+
+    pub fn delete_pages_batch(&self, page_ids: &[u64]) -> Result<()> {
+        let mut batch = PageBatch::default();
+
+        for &page_id in page_ids {
+            if let Some(page) = self.get_page(page_id)? {
+                self.remove_lookups(&page, &mut batch)?;
+            }
+            batch.pages.remove(&page_id.to_be_bytes());
+        }
+
+        self.apply(batch)
+    }
+
+    /// 删除 `page` 的二级索引；路径和页面名只在仍指向该页面时删除，避免误删改名后的新页面
+    fn remove_lookups(&self, page: &NLabPage, batch: &mut PageBatch) -> Result<()> {
+        if self.get_page_id_by_path(&page.file_path)? == Some(page.id) {
+            batch.paths.remove(page.file_path.as_bytes());
+        }
+        batch.titles.remove(lookup_key(&page.title, page.id));
+        if let Some(slug) = page.slug() {
+            if self.get_page_id_by_slug(slug)? == Some(page.id) {
+                batch.slugs.remove(normalize_slug(slug).as_bytes());
+            }
+        }
+        for target in &page.links {
+            batch.backlinks.remove(lookup_key(target, page.id));
+        }
         Ok(())
     }
 
+    fn apply(&self, batch: PageBatch) -> Result<()> {
+        self.pages.apply_batch(batch.pages)?;
+        self.paths.apply_batch(batch.paths)?;
+        self.titles.apply_batch(batch.titles)?;
+        self.slugs.apply_batch(batch.slugs)?;
+        self.backlinks.apply_batch(batch.backlinks)?;
+        Ok(())
+    }
+
+    pub fn get_page_id_by_path(&self, file_path: &str) -> Result<Option<u64>> {
+        Ok(self.paths.get(file_path.as_bytes())?.and_then(decode_id))
+    }
+
+    /// 标题为 `title` 的页面编号；nLab 中标题可能重复
+    pub fn get_page_ids_by_title(&self, title: &str) -> Result<Vec<u64>> {
+        scan_ids(&self.titles, title)
+    }
+
+    /// URL 中页面名为 `slug`（例如 `category+theory`）的页面编号
+    pub fn get_page_id_by_slug(&self, slug: &str) -> Result<Option<u64>> {
+        Ok(self
+            .slugs
+            .get(normalize_slug(slug).as_bytes())?
+            .and_then(decode_id))
+    }
+
+    /// 页面 `page_id` 中链接到的页面名和对应的页面编号，目标页面不存在时编号为 `None`
+    pub fn links_from(&self, page_id: u64) -> Result<Vec<(String, Option<u64>)>> {
+        let page = self
+            .get_page(page_id)?
+            .ok_or_else(|| StorageError::PageNotFound(page_id.to_string()))?;
+        page.links
+            .into_iter()
+            .map(|slug| {
+                let target = self.get_page_id_by_slug(&slug)?;
+                Ok((slug, target))
+            })
+            .collect()
+    }
+
+    /// 链接到页面 `page_id` 的页面编号
+    pub fn links_to(&self, page_id: u64) -> Result<Vec<u64>> {
+        let page = self
+            .get_page(page_id)?
+            .ok_or_else(|| StorageError::PageNotFound(page_id.to_string()))?;
+        match page.slug() {
+            Some(slug) => scan_ids(&self.backlinks, &normalize_slug(slug)),
+            None => Ok(Vec::new()),
+        }
+    }
+
     // 元数据存储
     // Key: "meta:last_sync", "meta:total_pages" 等
     pub fn set_metadata(&self, key: &str, value: &[u8]) -> Result<()> {
@@ -94,6 +524,138 @@ impl Storage {
         Ok(())
     }
 
+    pub fn set_last_sync(&self, record: &SyncRecord) -> Result<()> {
+        let serialized = bincode::encode_to_vec(record, BINCODE_CONFIG)?;
+        self.set_metadata(META_LAST_SYNC, &serialized)
+    }
+
+    pub fn get_last_sync(&self) -> Result<Option<SyncRecord>> {
+        match self.get_metadata(META_LAST_SYNC)? {
+            Some(bytes) => {
+                let (record, _): (SyncRecord, usize) =
+                    bincode::decode_from_slice(&bytes, BINCODE_CONFIG)?;
+                Ok(Some(record))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_last_attempt(&self, attempt: &SyncAttempt) -> Result<()> {
+        let serialized = bincode::encode_to_vec(attempt, BINCODE_CONFIG)?;
+        self.set_metadata(META_LAST_ATTEMPT, &serialized)
+    }
+
+    pub fn get_last_attempt(&self) -> Result<Option<SyncAttempt>> {
+        match self.get_metadata(META_LAST_ATTEMPT)? {
+            Some(bytes) => {
+                let (attempt, _): (SyncAttempt, usize) =
+                    bincode::decode_from_slice(&bytes, BINCODE_CONFIG)?;
+                Ok(Some(attempt))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn append_sync_run(&self, run: &SyncRun) -> Result<()> {
+        // 不用 `generate_id`：压缩后的新数据库会从 0 重新计数
+        let id = match self.history.last()? {
+            Some((key, _)) => u64::from_be_bytes(key.as_ref().try_into().unwrap_or_default()) + 1,
+            None => 0,
+        };
+        let serialized = bincode::encode_to_vec(run, BINCODE_CONFIG)?;
+        self.history.insert(id.to_be_bytes(), serialized)?;
+        Ok(())
+    }
+
+    /// 最近的 `limit` 次运行，最新的在前
+    pub fn sync_history(&self, limit: usize) -> Result<Vec<SyncRun>> {
+        self.history
+            .iter()
+            .rev()
+            .take(limit)
+            .map(|entry| {
+                let (_, bytes) = entry?;
+                let (run, _): (SyncRun, usize) =
+                    bincode::decode_from_slice(&bytes, BINCODE_CONFIG)?;
+                Ok(run)
+            })
+            .collect()
+    }
+
+    /// 把全部数据写入 `dest` 处的新数据库，之后用 `replace` 替换当前数据库
+    ///
+    /// sled 不会回收被覆盖或删除的数据占用的空间，重写一遍才能压缩
+    pub fn write_compacted(&self, dest: &Path) -> Result<()> {
+        if dest.exists() {
+            std::fs::remove_dir_all(dest)?;
+        }
+        // 不用 `sled::Db::import`：它在写入失败时直接 panic
+        let result = sled::open(dest).and_then(|compacted| {
+            for name in self.db.tree_names() {
+                let source = self.db.open_tree(&name)?;
+                let target = compacted.open_tree(&name)?;
+                for entry in source.iter() {
+                    let (key, value) = entry?;
+                    target.insert(key, value)?;
+                }
+            }
+            compacted.flush()
+        });
+        if let Err(e) = result {
+            // 例如磁盘空间不足：不留下写了一半的数据库
+            std::fs::remove_dir_all(dest).ok();
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// 关闭数据库，用 `write_compacted` 写入的 `compacted` 替换 `path`，再重新打开
+    ///
+    /// 出错时尽量保留原来的数据库，调用方需要重新打开 `path`
+    pub fn replace(self, path: &Path, compacted: &Path) -> Result<Self> {
+        drop(self);
+        let old = path.with_extension("old");
+        if old.exists() {
+            std::fs::remove_dir_all(&old)?;
+        }
+        std::fs::rename(path, &old)?;
+        if let Err(e) = std::fs::rename(compacted, path) {
+            std::fs::rename(&old, path)?;
+            return Err(e.into());
+        }
+        std::fs::remove_dir_all(&old)?;
+        Self::reopen(path)
+    }
+
+    /// 打开刚关闭的数据库
+    ///
+    /// sled 的后台线程在关闭后才释放文件锁，立即打开可能因为拿不到锁而失败，稍等后重试
+    fn reopen(path: &Path) -> Result<Self> {
+        let mut attempts = 0;
+        loop {
+            match Self::new(path.to_str().unwrap_or_default()) {
+                Err(StorageError::DatabaseError(sled::Error::Io(_)))
+                    if attempts < REOPEN_ATTEMPTS =>
+                {
+                    attempts += 1;
+                    std::thread::sleep(std::time::Duration::from_millis(20));
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// 把缓存中的写入落盘，磁盘空间不足等错误在这里返回，而不是在关闭时被忽略
+    pub fn flush(&self) -> Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// 已保存的页面数量
+    pub fn page_count(&self) -> usize {
+        self.paths.len()
+    }
+
     pub fn get_metadata(&self, key: &str) -> Result<Option<Vec<u8>>> {
         if !key.starts_with("meta:") {
             return Err(StorageError::InvalidMetadataKey(key.to_string()));
@@ -105,9 +667,63 @@ impl Storage {
     }
 }
 
+/// 页面数据和各个二级索引的写入，分别应用到对应的树
+#[derive(Default)]
+struct PageBatch {
+    pages: sled::Batch,
+    paths: sled::Batch,
+    titles: sled::Batch,
+    slugs: sled::Batch,
+    backlinks: sled::Batch,
+}
+
+/// 写入页面和它的二级索引
+fn insert_page(page: &NLabPage, batch: &mut PageBatch) -> Result<()> {
+    let serialized: Vec<u8> = bincode::encode_to_vec(page, BINCODE_CONFIG)?;
+    let id = page.id.to_be_bytes();
+    batch.pages.insert(&id, serialized);
+    batch.paths.insert(page.file_path.as_bytes(), &id);
+    batch.titles.insert(lookup_key(&page.title, page.id), &[]);
+    if let Some(slug) = page.slug() {
+        batch.slugs.insert(normalize_slug(slug).as_bytes(), &id);
+    }
+    for target in &page.links {
+        batch.backlinks.insert(lookup_key(target, page.id), &[]);
+    }
+    Ok(())
+}
+
+/// 标题（或页面名）后接 `\0` 和大端序的页面编号，同一标题的页面排在一起
+fn lookup_key(name: &str, page_id: u64) -> Vec<u8> {
+    let mut key = lookup_prefix(name);
+    key.extend_from_slice(&page_id.to_be_bytes());
+    key
+}
+
+fn lookup_prefix(name: &str) -> Vec<u8> {
+    let mut prefix = name.as_bytes().to_vec();
+    prefix.push(0);
+    prefix
+}
+
+/// `tree` 中以 `name` 为前缀的 `lookup_key` 里的页面编号
+fn scan_ids(tree: &sled::Tree, name: &str) -> Result<Vec<u64>> {
+    tree.scan_prefix(lookup_prefix(name))
+        .map(|entry| {
+            let (key, _) = entry?;
+            Ok(decode_id(key.subslice(key.len() - 8, 8)).unwrap_or_default())
+        })
+        .collect()
+}
+
+fn decode_id(bytes: sled::IVec) -> Option<u64> {
+    bytes.as_ref().try_into().ok().map(u64::from_be_bytes)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::Section;
     use crate::REPO_URL;
     use std::path::Path;
     use std::{fs, u8};
@@ -117,11 +733,21 @@ mod tests {
 
     fn create_test_page() -> NLabPage {
         NLabPage {
-            id: "test/page.md".to_string(),
+            id: 1474,
             title: "Test Page".to_string(),
-            file_path: "test/page.md".to_string(),
+            file_path: "pages/4/7/4/1/1474/content.html".to_string(),
             url: "https://ncatlab.org/nlab/show/test".to_string(),
             content: "This is test content.".to_string(),
+            links: vec!["sheaf".to_string(), "topos".to_string()],
+            math: vec![r"\otimes".to_string()],
+            sections: vec![Section {
+                heading: "Idea".to_string(),
+                anchor: Some("idea".to_string()),
+                start: 5,
+                end: 21,
+            }],
+            categories: vec!["reference".to_string()],
+            contexts: vec!["Category theory".to_string()],
         }
     }
 
@@ -133,13 +759,17 @@ mod tests {
         let page = create_test_page();
         storage.save_page(&page)?;
 
-        let retrieved = storage.get_page(&page.id)?;
+        let retrieved = storage.get_page(page.id)?;
         assert!(retrieved.is_some());
 
         let retrieved_page = retrieved.unwrap();
         assert_eq!(retrieved_page.id, page.id);
         assert_eq!(retrieved_page.title, page.title);
         assert_eq!(retrieved_page.content, page.content);
+        assert_eq!(retrieved_page.math, page.math);
+        assert_eq!(retrieved_page.sections, page.sections);
+        assert_eq!(retrieved_page.categories, page.categories);
+        assert_eq!(retrieved_page.contexts, page.contexts);
 
         Ok(())
     }
@@ -196,7 +826,7 @@ mod tests {
         println!("  ✓ 成功存储到数据库");
 
         // 从数据库读取
-        let retrieved = storage.get_page(&page.id)?;
+        let retrieved = storage.get_page(page.id)?;
         assert!(retrieved.is_some(), "无法从数据库读取页面");
 
         let retrieved_page = retrieved.unwrap();
@@ -219,12 +849,106 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let storage = Storage::new(temp_dir.path().to_str().unwrap())?;
 
-        let result = storage.get_page("nonexistent")?;
+        let result = storage.get_page(404)?;
         assert!(result.is_none());
 
         Ok(())
     }
 
+    #[test]
+    fn test_last_sync_round_trip() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().to_str().unwrap())?;
+        assert_eq!(storage.get_last_sync()?, None);
+
+        let record = SyncRecord {
+            commit: "abc123".to_string(),
+            timestamp: 1_700_000_000,
+            page_count: 2,
+            duration_ms: 1500,
+        };
+        storage.set_last_sync(&record)?;
+        assert_eq!(storage.get_last_sync()?, Some(record));
+
+        let attempt = SyncAttempt {
+            timestamp: 1_700_086_400,
+            error: Some("all mirrors failed".to_string()),
+        };
+        storage.set_last_attempt(&attempt)?;
+        assert_eq!(storage.get_last_attempt()?, Some(attempt));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_history_is_newest_first() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().to_str().unwrap())?;
+        assert!(storage.sync_history(10)?.is_empty());
+
+        let mut initial = SyncRun::new(RunKind::Initial, 1_700_000_000);
+        initial.pages_added = 2;
+        storage.append_sync_run(&initial)?;
+        let mut failed = SyncRun::new(RunKind::Sync, 1_700_086_400);
+        failed.error = Some("all mirrors failed".to_string());
+        storage.append_sync_run(&failed)?;
+
+        assert_eq!(storage.sync_history(10)?, vec![failed.clone(), initial]);
+        assert_eq!(storage.sync_history(1)?, vec![failed]);
+        // 同步记录不是页面
+        assert_eq!(storage.page_count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compaction_keeps_data() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("storage");
+        let storage = Storage::new(path.to_str().unwrap())?;
+        storage.save_page(&create_test_page())?;
+        storage.set_metadata(META_MIRROR, b"https://example.com/nlab.git")?;
+        let first = SyncRun::new(RunKind::Initial, 1_700_000_000);
+        storage.append_sync_run(&first)?;
+
+        let compacted = temp_dir.path().join("storage.compact");
+        storage.write_compacted(&compacted)?;
+        let storage = storage.replace(&path, &compacted)?;
+
+        assert_eq!(
+            storage.get_page(1474)?.map(|page| page.title),
+            Some("Test Page".to_string())
+        );
+        assert_eq!(storage.page_count(), 1);
+        assert_eq!(
+            storage.get_metadata(META_MIRROR)?.as_deref(),
+            Some(&b"https://example.com/nlab.git"[..])
+        );
+        // 压缩之后追加的记录仍然排在最前
+        let second = SyncRun::new(RunKind::Sync, 1_700_086_400);
+        storage.append_sync_run(&second)?;
+        assert_eq!(storage.sync_history(10)?, vec![second, first]);
+        assert!(!compacted.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_pages_batch_removes_page_and_path() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().to_str().unwrap())?;
+
+        let page = create_test_page();
+        storage.save_page(&page)?;
+        assert_eq!(storage.get_page_id_by_path(&page.file_path)?, Some(page.id));
+
+        storage.delete_pages_batch(&[page.id])?;
+        assert!(storage.get_page(page.id)?.is_none());
+        assert!(storage.get_page_id_by_path(&page.file_path)?.is_none());
+        assert!(storage.get_page_ids_by_title(&page.title)?.is_empty());
+        assert!(storage.get_page_id_by_slug("test")?.is_none());
+        assert!(storage.backlinks.is_empty());
+
+        Ok(())
+    }
+
     #[test]
     fn test_save_pages_batch() -> Result<()> {
         let temp_dir = TempDir::new().unwrap();
@@ -233,22 +957,186 @@ mod tests {
         let pages = vec![
             create_test_page(),
             NLabPage {
-                id: "test/page2.md".to_string(),
+                id: 2,
                 title: "Test Page 2".to_string(),
-                file_path: "test/page2.md".to_string(),
+                file_path: "pages/2/2/content.html".to_string(),
                 url: "https://ncatlab.org/nlab/show/test2".to_string(),
                 content: "Second test content.".to_string(),
+                links: Vec::new(),
+                math: Vec::new(),
+                sections: Vec::new(),
+                categories: Vec::new(),
+                contexts: Vec::new(),
             },
         ];
 
         storage.save_pages_batch(&pages)?;
 
         for page in pages {
-            let retrieved = storage.get_page(&page.id)?;
+            let retrieved = storage.get_page(page.id)?;
             assert!(retrieved.is_some());
             assert_eq!(retrieved.unwrap().id, page.id);
         }
 
         Ok(())
     }
+
+    #[test]
+    fn test_same_title_and_lookups() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().to_str().unwrap())?;
+
+        // 标题相同（或为空）的页面不再互相覆盖
+        let mut other = create_test_page();
+        other.id = 7;
+        other.file_path = "pages/7/7/content.html".to_string();
+        other.url = "https://ncatlab.org/nlab/show/test+(disambiguation)".to_string();
+        storage.save_pages_batch(&[create_test_page(), other.clone()])?;
+        assert_eq!(storage.page_count(), 2);
+        assert_eq!(storage.get_page_ids_by_title("Test Page")?, vec![7, 1474]);
+        assert!(storage.get_page_ids_by_title("Test")?.is_empty());
+        assert_eq!(storage.get_page_id_by_slug("test")?, Some(1474));
+
+        // 改名后旧标题和旧页面名不再指向该页面
+        other.title = "Renamed".to_string();
+        other.url = "https://ncatlab.org/nlab/show/renamed".to_string();
+        storage.save_page(&other)?;
+        assert_eq!(storage.get_page_ids_by_title("Test Page")?, vec![1474]);
+        assert_eq!(storage.get_page_ids_by_title("Renamed")?, vec![7]);
+        assert!(storage
+            .get_page_id_by_slug("test+(disambiguation)")?
+            .is_none());
+        assert_eq!(storage.get_page_id_by_slug("renamed")?, Some(7));
+        Ok(())
+    }
+
+    #[test]
+    fn test_links_and_backlinks() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().to_str().unwrap())?;
+
+        let page = create_test_page();
+        let sheaf = NLabPage::new(
+            2,
+            "pages/2/2/content.html".to_string(),
+            "sheaf".to_string(),
+            "https://ncatlab.org/nlab/show/sheaf".to_string(),
+            "content".to_string(),
+            vec!["test".to_string()],
+            Vec::new(),
+        );
+        storage.save_pages_batch(&[page.clone(), sheaf.clone()])?;
+
+        assert_eq!(
+            storage.links_from(page.id)?,
+            vec![("sheaf".to_string(), Some(2)), ("topos".to_string(), None)]
+        );
+        assert_eq!(storage.links_to(2)?, vec![page.id]);
+        assert_eq!(storage.links_to(page.id)?, vec![2]);
+
+        // 页面不再链接到 sheaf 后，sheaf 的反向链接随之更新
+        let mut edited = page.clone();
+        edited.links = vec!["topos".to_string()];
+        storage.save_page(&edited)?;
+        assert!(storage.links_to(2)?.is_empty());
+
+        storage.delete_pages_batch(&[sheaf.id])?;
+        assert!(storage.links_to(page.id)?.is_empty());
+        assert!(storage.links_from(sheaf.id).is_err());
+        Ok(())
+    }
+
+    #[derive(Encode)]
+    struct LegacyTestPage {
+        id: String,
+        title: String,
+        file_path: String,
+        url: String,
+        content: String,
+    }
+
+    #[test]
+    fn test_migrates_title_keyed_pages() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("storage");
+        {
+            // 版本 1：页面以标题为键保存在默认树中
+            let db = sled::open(&path)?;
+            for (title, file_path) in [
+                ("topos", "pages/1/1/content.html"),
+                ("sheaf", "pages/2/2/content.html"),
+                ("", "pages/3/3/content.html"),
+            ] {
+                let legacy = LegacyTestPage {
+                    id: title.to_string(),
+                    title: title.to_string(),
+                    file_path: file_path.to_string(),
+                    url: format!("https://ncatlab.org/nlab/show/{}", title),
+                    content: "content".to_string(),
+                };
+                db.insert(title, bincode::encode_to_vec(&legacy, BINCODE_CONFIG)?)?;
+                db.open_tree(PATHS_TREE)?.insert(file_path, title)?;
+            }
+            db.insert(META_MIRROR, "https://example.com/nlab.git")?;
+        }
+
+        let storage = Storage::new(path.to_str().unwrap())?;
+        assert_eq!(storage.page_count(), 3);
+        assert_eq!(
+            storage.get_page(2)?.map(|page| page.title),
+            Some("sheaf".to_string())
+        );
+        assert_eq!(
+            storage.get_page_id_by_path("pages/3/3/content.html")?,
+            Some(3)
+        );
+        assert_eq!(storage.get_page_ids_by_title("topos")?, vec![1]);
+        assert!(storage.get_metadata(META_MIRROR)?.is_some());
+        assert!(storage.reread_pending()?);
+        storage.clear_reread_pending()?;
+
+        // 再次打开时不会重复迁移（不真的重新打开：sled 的后台线程可能还没有释放文件锁）
+        assert!(!storage.migrate()?);
+        assert!(!storage.reread_pending()?);
+        assert_eq!(storage.pages()?.len(), 3);
+        Ok(())
+    }
+
+    #[derive(Encode)]
+    struct PageV2TestPage {
+        id: u64,
+        title: String,
+        file_path: String,
+        url: String,
+        content: String,
+    }
+
+    #[test]
+    fn test_migrates_pages_without_links() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("storage");
+        {
+            let db = sled::open(&path)?;
+            let page = PageV2TestPage {
+                id: 1474,
+                title: "topos".to_string(),
+                file_path: "pages/4/7/4/1/1474/content.html".to_string(),
+                url: "https://ncatlab.org/nlab/show/topos".to_string(),
+                content: "content".to_string(),
+            };
+            db.open_tree(PAGES_TREE)?.insert(
+                1474u64.to_be_bytes(),
+                bincode::encode_to_vec(&page, BINCODE_CONFIG)?,
+            )?;
+            db.insert(META_SCHEMA_VERSION, &2u32.to_be_bytes())?;
+        }
+
+        let storage = Storage::new(path.to_str().unwrap())?;
+        let page = storage.get_page(1474)?.unwrap();
+        assert_eq!(page.title, "topos");
+        assert!(page.links.is_empty());
+        assert_eq!(storage.get_page_id_by_slug("topos")?, Some(1474));
+        assert!(storage.reread_pending()?);
+        Ok(())
+    }
 }
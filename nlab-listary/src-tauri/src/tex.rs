@@ -0,0 +1,192 @@
+use tantivy::tokenizer::{Token, TokenStream, Tokenizer};
+
+/// 注册到索引中的 TeX 分词器名字
+pub const TEX_TOKENIZER: &str = "tex";
+
+/// 只影响排版间距的控制符，不作为词
+const SPACING_SYMBOLS: &[char] = &[',', ';', ':', '!', ' ', '\\'];
+
+/// 按 TeX 的记号切分公式：控制序列（`\otimes`）、字母数字串（`H`、`12`）和单个符号（`^`、`=`）
+///
+/// 区分大小写，`\Omega` 和 `\omega` 是不同的词；`{`、`}`、`$`、`&` 和间距命令被忽略，
+/// 因此 `\mathbf{H}` 和 `\mathbf H` 得到相同的词
+#[derive(Clone, Default)]
+pub struct TexTokenizer;
+
+pub struct TexTokenStream {
+    tokens: Vec<Token>,
+    // 下一个要返回的词
+    next: usize,
+}
+
+impl Tokenizer for TexTokenizer {
+    type TokenStream<'a> = TexTokenStream;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> TexTokenStream {
+        let tokens = tex_spans(text)
+            .into_iter()
+            .enumerate()
+            .map(|(position, (offset_from, offset_to))| Token {
+                offset_from,
+                offset_to,
+                position,
+                text: text[offset_from..offset_to].to_string(),
+                position_length: 1,
+            })
+            .collect();
+        TexTokenStream { tokens, next: 0 }
+    }
+}
+
+impl TokenStream for TexTokenStream {
+    fn advance(&mut self) -> bool {
+        self.next += 1;
+        self.next <= self.tokens.len()
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.next - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.next - 1]
+    }
+}
+
+/// `text` 按 `TexTokenizer` 切分出的词
+pub fn tex_terms(text: &str) -> Vec<String> {
+    tex_spans(text)
+        .into_iter()
+        .map(|(from, to)| text[from..to].to_string())
+        .collect()
+}
+
+/// 每个词在 `text` 中的字节范围
+fn tex_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, next)) if next.is_ascii_alphabetic() => {
+                    let mut end = start + 2;
+                    while let Some(&(i, c)) = chars.peek() {
+                        if !c.is_ascii_alphabetic() {
+                            break;
+                        }
+                        end = i + 1;
+                        chars.next();
+                    }
+                    spans.push((start, end));
+                }
+                Some((i, next)) if !SPACING_SYMBOLS.contains(&next) => {
+                    spans.push((start, i + next.len_utf8()));
+                }
+                _ => {}
+            },
+            c if c.is_alphanumeric() => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, c)) = chars.peek() {
+                    if !c.is_alphanumeric() {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                spans.push((start, end));
+            }
+            c if c.is_whitespace() || matches!(c, '{' | '}' | '$' | '&' | '~') => {}
+            c => spans.push((start, start + c.len_utf8())),
+        }
+    }
+
+    spans
+}
+
+/// 把查询拆成普通文本和公式
+///
+/// `$...$` 之间的是公式；没有 `$` 但含有 `\` 时整个查询都是公式，例如 `\Omega^\infty`
+pub fn split_formulas(query: &str) -> (String, Vec<String>) {
+    if !query.contains('$') {
+        return if query.contains('\\') {
+            (String::new(), vec![query.trim().to_string()])
+        } else {
+            (query.to_string(), Vec::new())
+        };
+    }
+
+    let mut text = Vec::new();
+    let mut formulas = Vec::new();
+    for (i, part) in query.split('$').enumerate() {
+        let part = part.trim();
+        if part.is_empty() {
+            continue;
+        }
+        if i % 2 == 1 {
+            formulas.push(part.to_string());
+        } else {
+            text.push(part);
+        }
+    }
+    (text.join(" "), formulas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tex_terms() {
+        assert_eq!(tex_terms(r"A \otimes B"), vec!["A", r"\otimes", "B"]);
+        assert_eq!(tex_terms(r"\mathbf{H}"), vec![r"\mathbf", "H"]);
+        assert_eq!(tex_terms(r"\mathbf H"), tex_terms(r"\mathbf{H}"));
+        assert_eq!(
+            tex_terms(r"\Omega^\infty \Sigma^{\infty}_+ X"),
+            vec![r"\Omega", "^", r"\infty", r"\Sigma", "^", r"\infty", "_", "+", "X"]
+        );
+        assert_eq!(
+            tex_terms(r"f\colon x \,\to\, y_{12} \{ \}"),
+            vec!["f", r"\colon", "x", r"\to", "y", "_", "12", r"\{", r"\}"]
+        );
+        assert_eq!(tex_terms("a → b"), vec!["a", "→", "b"]);
+    }
+
+    #[test]
+    fn test_token_stream_positions() {
+        let mut tokenizer = TexTokenizer;
+        let mut stream = tokenizer.token_stream(r"x \otimes y");
+        let mut tokens = Vec::new();
+        while stream.advance() {
+            let token = stream.token();
+            tokens.push((token.text.clone(), token.position, token.offset_from));
+        }
+        assert_eq!(
+            tokens,
+            vec![
+                ("x".to_string(), 0, 0),
+                (r"\otimes".to_string(), 1, 2),
+                ("y".to_string(), 2, 10)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_formulas() {
+        assert_eq!(
+            split_formulas("category theory"),
+            ("category theory".to_string(), vec![])
+        );
+        assert_eq!(
+            split_formulas(r"\Omega^\infty"),
+            (String::new(), vec![r"\Omega^\infty".to_string()])
+        );
+        assert_eq!(
+            split_formulas(r"monoidal $\otimes$ unit $I$"),
+            (
+                "monoidal unit".to_string(),
+                vec![r"\otimes".to_string(), "I".to_string()]
+            )
+        );
+    }
+}
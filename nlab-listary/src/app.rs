@@ -22,163 +22,2021 @@ extern "C" {
 #[derive(Serialize, Deserialize)]
 struct SearchArgs {
     query: String,
+    include: Vec<FacetFilter>,
+    exclude: Vec<FacetFilter>,
+}
+
+/// 后端 `search::FacetFilter` 的镜像，`kind` 为 category 或 context
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct FacetFilter {
+    kind: String,
+    value: String,
+}
+
+/// 搜索时按分类和领域筛选的条件，见后端的 `SearchFilters`
+#[derive(Clone, Debug, Default, PartialEq)]
+struct FacetFilters {
+    include: Vec<FacetFilter>,
+    exclude: Vec<FacetFilter>,
+}
+
+impl FacetFilters {
+    fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    fn remove(&mut self, filter: &FacetFilter) {
+        self.include.retain(|f| f != filter);
+        self.exclude.retain(|f| f != filter);
+    }
+
+    /// 只看（`exclude` 为 false）或排除某个值，替换这个值原来的条件
+    fn set(&mut self, filter: FacetFilter, exclude: bool) {
+        self.remove(&filter);
+        if exclude {
+            self.exclude.push(filter);
+        } else {
+            self.include.push(filter);
+        }
+    }
+}
+
+/// 后端 `search::FacetCount` 的镜像
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct FacetCount {
+    kind: String,
+    value: String,
+    count: u64,
 }
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 struct SearchIndex {
+    id: u64,
     title: String,
     url: String,
+    file_path: String,
+    section: Option<String>,
 }
-async fn get_search_results(query: &str) -> Result<Vec<SearchIndex>, String> {
+async fn get_search_results(
+    query: &str,
+    filters: &FacetFilters,
+) -> Result<Vec<SearchIndex>, String> {
     if query.is_empty() {
         return Ok(vec![]);
     }
 
     let args = serde_wasm_bindgen::to_value(&SearchArgs {
         query: query.to_string(),
+        include: filters.include.clone(),
+        exclude: filters.exclude.clone(),
+    })
+    .map_err(|e| format!("Failed to serialize args: {:?}", e))?;
+
+    // invoke returns Result<JsValue, JsValue>
+    let ret = invoke("get_search_results", args).await;
+
+    // check if the result is an error message
+    if ret.is_undefined() || ret.is_null() {
+        return Err("Received invalid response from backend".to_string());
+    }
+
+    // try to parse the result as an error message
+    if let Ok(error_msg) = serde_wasm_bindgen::from_value::<String>(ret.clone()) {
+        return Err(error_msg);
+    }
+
+    // parse the result as search results
+    let results: Vec<SearchIndex> = serde_wasm_bindgen::from_value(ret)
+        .map_err(|e| format!("Failed to parse search results: {:?}", e))?;
+
+    Ok(results)
+}
+
+async fn get_facet_counts(query: &str, filters: &FacetFilters) -> Result<Vec<FacetCount>, String> {
+    let args = serde_wasm_bindgen::to_value(&SearchArgs {
+        query: query.to_string(),
+        include: filters.include.clone(),
+        exclude: filters.exclude.clone(),
+    })
+    .map_err(|e| format!("Failed to serialize args: {:?}", e))?;
+
+    let ret = invoke("get_facet_counts", args).await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    serde_wasm_bindgen::from_value(ret)
+        .map_err(|e| format!("Failed to parse facet counts: {:?}", e))
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotSearchArgs {
+    commit: String,
+    query: String,
+    include: Vec<FacetFilter>,
+    exclude: Vec<FacetFilter>,
+}
+async fn get_snapshot_search_results(
+    commit: &str,
+    query: &str,
+    filters: &FacetFilters,
+) -> Result<Vec<SearchIndex>, String> {
+    let args = serde_wasm_bindgen::to_value(&SnapshotSearchArgs {
+        commit: commit.to_string(),
+        query: query.to_string(),
+        include: filters.include.clone(),
+        exclude: filters.exclude.clone(),
+    })
+    .map_err(|e| format!("Failed to serialize args: {:?}", e))?;
+
+    let ret = invoke("get_snapshot_search_results", args).await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    serde_wasm_bindgen::from_value(ret)
+        .map_err(|e| format!("Failed to parse search results: {:?}", e))
+}
+
+/// 后端 `git_ops::PageRevision` 的镜像
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct PageRevision {
+    commit: String,
+    time: i64,
+    summary: String,
+}
+
+/// 后端 `git_ops::DiffLine` 的镜像，`kind` 为 hunk、context、added 或 removed
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct DiffLine {
+    kind: String,
+    text: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HistoryArgs {
+    file_path: String,
+}
+async fn get_page_history(file_path: &str) -> Result<Vec<PageRevision>, String> {
+    let args = serde_wasm_bindgen::to_value(&HistoryArgs {
+        file_path: file_path.to_string(),
+    })
+    .map_err(|e| format!("Failed to serialize args: {:?}", e))?;
+
+    let ret = invoke("get_page_history", args).await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    serde_wasm_bindgen::from_value(ret).map_err(|e| format!("Failed to parse history: {:?}", e))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DiffArgs {
+    file_path: String,
+    old: String,
+    new: String,
+}
+async fn get_page_diff(file_path: &str, old: &str, new: &str) -> Result<Vec<DiffLine>, String> {
+    let args = serde_wasm_bindgen::to_value(&DiffArgs {
+        file_path: file_path.to_string(),
+        old: old.to_string(),
+        new: new.to_string(),
+    })
+    .map_err(|e| format!("Failed to serialize args: {:?}", e))?;
+
+    let ret = invoke("get_page_diff", args).await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    serde_wasm_bindgen::from_value(ret).map_err(|e| format!("Failed to parse diff: {:?}", e))
+}
+
+/// 后端 `links::LinkedPage` 的镜像，`id` 为 `None` 表示页面还不存在
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct LinkedPage {
+    id: Option<u64>,
+    title: String,
+    url: String,
+}
+
+/// 后端 `links::PageLinks` 的镜像
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct PageLinks {
+    outgoing: Vec<LinkedPage>,
+    incoming: Vec<LinkedPage>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LinksArgs {
+    page_id: u64,
+}
+async fn get_page_links(page_id: u64) -> Result<PageLinks, String> {
+    let args = serde_wasm_bindgen::to_value(&LinksArgs { page_id })
+        .map_err(|e| format!("Failed to serialize args: {:?}", e))?;
+
+    let ret = invoke("get_page_links", args).await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    serde_wasm_bindgen::from_value(ret).map_err(|e| format!("Failed to parse links: {:?}", e))
+}
+
+/// 后端 `snapshot::SnapshotInfo` 的镜像
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct SnapshotInfo {
+    revision: String,
+    commit: String,
+    commit_time: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotArgs {
+    revision: String,
+}
+async fn build_snapshot(revision: &str) -> Result<(), String> {
+    let args = serde_wasm_bindgen::to_value(&SnapshotArgs {
+        revision: revision.to_string(),
+    })
+    .map_err(|e| format!("Failed to serialize args: {:?}", e))?;
+
+    let ret = invoke("build_snapshot", args).await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+/// 后端 `progress` 事件的内容，与 `progress::Progress` 对应
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct Progress {
+    phase: String,
+    current: usize,
+    total: usize,
+    received_bytes: usize,
+}
+
+/// 当前阶段开始时的时间（毫秒）和进度，用于估算剩余时间
+#[derive(Clone, Debug, PartialEq)]
+struct PhaseStart {
+    phase: String,
+    started_at: f64,
+    start_current: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct OpenArgs {
+    url: String,
+}
+async fn open_url(url: &str) -> Result<(), String> {
+    let args = serde_wasm_bindgen::to_value(&OpenArgs {
+        url: url.to_string(),
     })
     .map_err(|e| format!("Failed to serialize args: {:?}", e))?;
 
-    // invoke returns Result<JsValue, JsValue>
-    let ret = invoke("get_search_results", args).await;
+    let ret = invoke("open_url", args).await;
+    if let Some(err) = ret.as_string() {
+        eprintln!("Error opening URL: {}", err);
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+async fn sync_local_repo() -> Result<(), String> {
+    let ret = invoke_without_args("sync_local_repo").await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+/// 后端 `models::IndexStatus` 中前端用到的字段
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct IndexStatus {
+    summary: String,
+    inconsistency: Option<String>,
+}
+
+async fn get_status() -> Result<IndexStatus, String> {
+    let ret = invoke_without_args("get_status").await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    serde_wasm_bindgen::from_value(ret).map_err(|e| format!("Failed to parse status: {:?}", e))
+}
+
+/// 后端 `config::ScheduleConfig` 的镜像
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+struct ScheduleConfig {
+    interval_hours: u64,
+    launch_at_login: bool,
+}
+
+async fn get_schedule() -> Result<ScheduleConfig, String> {
+    let ret = invoke_without_args("get_schedule").await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    serde_wasm_bindgen::from_value(ret).map_err(|e| format!("Failed to parse schedule: {:?}", e))
+}
+
+#[derive(Serialize, Deserialize)]
+struct ScheduleArgs {
+    settings: ScheduleConfig,
+}
+async fn set_schedule(settings: ScheduleConfig) -> Result<(), String> {
+    let args = serde_wasm_bindgen::to_value(&ScheduleArgs { settings })
+        .map_err(|e| format!("Failed to serialize args: {:?}", e))?;
+
+    let ret = invoke("set_schedule", args).await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+/// 后端 `storage::SyncRun` 的镜像
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct SyncRun {
+    kind: String,
+    started_at: i64,
+    finished_at: i64,
+    mirror: Option<String>,
+    old_commit: Option<String>,
+    new_commit: Option<String>,
+    outcome: Option<String>,
+    pages_added: usize,
+    pages_modified: usize,
+    pages_deleted: usize,
+    parse_failures: usize,
+    error: Option<String>,
+}
+
+async fn get_sync_history() -> Result<Vec<SyncRun>, String> {
+    let ret = invoke_without_args("get_sync_history").await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    serde_wasm_bindgen::from_value(ret)
+        .map_err(|e| format!("Failed to parse sync history: {:?}", e))
+}
+
+/// 后端 `git_ops::PreviewPage` 的镜像，`kind` 为 added、modified 或 deleted
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct PreviewPage {
+    kind: String,
+    file_path: String,
+    title: String,
+}
+
+/// 后端 `git_ops::UpdatePreview` 的镜像
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct UpdatePreview {
+    mirror: String,
+    head: String,
+    target: String,
+    pages: Vec<PreviewPage>,
+}
+
+async fn preview_sync() -> Result<(), String> {
+    let ret = invoke_without_args("preview_sync").await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImportBundleArgs {
+    bundle_path: String,
+}
+async fn import_bundle(bundle_path: &str) -> Result<(), String> {
+    let args = serde_wasm_bindgen::to_value(&ImportBundleArgs {
+        bundle_path: bundle_path.to_string(),
+    })
+    .map_err(|e| format!("Failed to serialize args: {:?}", e))?;
+
+    let ret = invoke("import_bundle", args).await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportBundleArgs {
+    output: String,
+    since: Option<String>,
+}
+async fn export_bundle(output: &str, since: Option<String>) -> Result<(), String> {
+    let args = serde_wasm_bindgen::to_value(&ExportBundleArgs {
+        output: output.to_string(),
+        since,
+    })
+    .map_err(|e| format!("Failed to serialize args: {:?}", e))?;
+
+    let ret = invoke("export_bundle", args).await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportGraphArgs {
+    output: String,
+    format: String,
+}
+/// `format` 为 `dot` 或 `graphml`
+async fn export_link_graph(output: &str, format: &str) -> Result<(), String> {
+    let args = serde_wasm_bindgen::to_value(&ExportGraphArgs {
+        output: output.to_string(),
+        format: format.to_string(),
+    })
+    .map_err(|e| format!("Failed to serialize args: {:?}", e))?;
+
+    let ret = invoke("export_link_graph", args).await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+/// 后端 `links::GraphSummary` 的镜像
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct GraphSummary {
+    path: String,
+    nodes: usize,
+    edges: usize,
+}
+
+/// 后端 `git_ops::BundleSummary` 的镜像
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct BundleSummary {
+    path: String,
+    tip: String,
+    since: Option<String>,
+    object_count: usize,
+}
+
+/// 后端 `maintenance::DiskUsage` 的镜像
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct DiskUsage {
+    mirror: u64,
+    database: u64,
+    index: u64,
+    snapshots: u64,
+    worktree: bool,
+}
+
+impl DiskUsage {
+    fn total(&self) -> u64 {
+        self.mirror + self.database + self.index + self.snapshots
+    }
+}
+
+/// 后端 `maintenance::MaintenanceReport` 的镜像
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct MaintenanceReport {
+    before: DiskUsage,
+    after: DiskUsage,
+    packed_objects: Option<usize>,
+    merged_segments: usize,
+}
+
+async fn get_disk_usage() -> Result<DiskUsage, String> {
+    let ret = invoke_without_args("get_disk_usage").await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    serde_wasm_bindgen::from_value(ret).map_err(|e| format!("Failed to parse disk usage: {:?}", e))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RunMaintenanceArgs {
+    remove_worktree: bool,
+}
+async fn run_maintenance(remove_worktree: bool) -> Result<(), String> {
+    let args = serde_wasm_bindgen::to_value(&RunMaintenanceArgs { remove_worktree })
+        .map_err(|e| format!("Failed to serialize args: {:?}", e))?;
+
+    let ret = invoke("run_maintenance", args).await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+/// 后端 `onboarding::OnboardingStatus` 的镜像
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct OnboardingStatus {
+    required: bool,
+    default_data_dir: String,
+}
+
+/// 后端 `onboarding::SetupChoice` 的镜像
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum SetupChoice {
+    Clone { url: String, shallow: bool },
+    ExistingClone { path: String },
+    Archive { path: String },
+    IndexBundle { path: String },
+}
+
+/// 后端 `onboarding::SpaceEstimate` 的镜像
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct SpaceEstimate {
+    required: u64,
+    available: Option<u64>,
+}
+
+async fn get_onboarding() -> Result<OnboardingStatus, String> {
+    let ret = invoke_without_args("get_onboarding").await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    serde_wasm_bindgen::from_value(ret)
+        .map_err(|e| format!("Failed to parse onboarding status: {:?}", e))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetupArgs {
+    data_dir: String,
+    choice: SetupChoice,
+}
+async fn estimate_setup(data_dir: &str, choice: &SetupChoice) -> Result<SpaceEstimate, String> {
+    let args = serde_wasm_bindgen::to_value(&SetupArgs {
+        data_dir: data_dir.to_string(),
+        choice: choice.clone(),
+    })
+    .map_err(|e| format!("Failed to serialize args: {:?}", e))?;
+
+    let ret = invoke("estimate_setup", args).await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    serde_wasm_bindgen::from_value(ret).map_err(|e| format!("Failed to parse estimate: {:?}", e))
+}
+
+async fn complete_setup(data_dir: &str, choice: &SetupChoice) -> Result<(), String> {
+    let args = serde_wasm_bindgen::to_value(&SetupArgs {
+        data_dir: data_dir.to_string(),
+        choice: choice.clone(),
+    })
+    .map_err(|e| format!("Failed to serialize args: {:?}", e))?;
+
+    let ret = invoke("complete_setup", args).await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+async fn cancel_operation() -> Result<(), String> {
+    let ret = invoke_without_args("cancel_operation").await;
+    if let Some(err) = ret.as_string() {
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+async fn event_listener(
+    mut is_ready: Signal<bool>,
+    mut init_status: Signal<String>,
+    mut init_error: Signal<Option<String>>,
+    mut stale_warning: Signal<Option<String>>,
+    mut sync_status: Signal<Option<String>>,
+    mut progress: Signal<Option<Progress>>,
+    mut phase_start: Signal<Option<PhaseStart>>,
+) {
+    let status_closure = Closure::wrap(Box::new(move |event: JsValue| {
+        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
+            if let Some(status) = payload.as_string() {
+                init_status.set(status);
+            }
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen("init-status", status_closure.as_ref().unchecked_ref()).await;
+    status_closure.forget();
+
+    let progress_closure = Closure::wrap(Box::new(move |event: JsValue| {
+        let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) else {
+            return;
+        };
+        let Ok(new_progress) = serde_wasm_bindgen::from_value::<Progress>(payload) else {
+            return;
+        };
+
+        let phase_changed = phase_start
+            .peek()
+            .as_ref()
+            .is_none_or(|start| start.phase != new_progress.phase);
+        if phase_changed {
+            phase_start.set(Some(PhaseStart {
+                phase: new_progress.phase.clone(),
+                started_at: js_sys::Date::now(),
+                start_current: new_progress.current,
+            }));
+        }
+        progress.set(Some(new_progress));
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen("progress", progress_closure.as_ref().unchecked_ref()).await;
+    progress_closure.forget();
+
+    let complete_closure = Closure::wrap(Box::new(move |_: JsValue| {
+        is_ready.set(true);
+        progress.set(None);
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen("init-complete", complete_closure.as_ref().unchecked_ref()).await;
+    complete_closure.forget();
+
+    let error_closure = Closure::wrap(Box::new(move |event: JsValue| {
+        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
+            if let Some(error) = payload.as_string() {
+                init_error.set(Some(error));
+                progress.set(None);
+            }
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen("init-error", error_closure.as_ref().unchecked_ref()).await;
+    error_closure.forget();
+
+    let sync_status_closure = Closure::wrap(Box::new(move |event: JsValue| {
+        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
+            if let Some(status) = payload.as_string() {
+                sync_status.set(Some(status));
+            }
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen("sync-status", sync_status_closure.as_ref().unchecked_ref()).await;
+    sync_status_closure.forget();
+
+    // 后台同步失败时仍可搜索，只提示索引可能过期
+    let stale_closure = Closure::wrap(Box::new(move |event: JsValue| {
+        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
+            if let Some(warning) = payload.as_string() {
+                stale_warning.set(Some(warning));
+                sync_status.set(None);
+                progress.set(None);
+            }
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen("index-stale", stale_closure.as_ref().unchecked_ref()).await;
+    stale_closure.forget();
+
+    let synced_closure = Closure::wrap(Box::new(move |_: JsValue| {
+        stale_warning.set(None);
+        sync_status.set(None);
+        progress.set(None);
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen("sync-complete", synced_closure.as_ref().unchecked_ref()).await;
+    synced_closure.forget();
+
+    let cancelled_closure = Closure::wrap(Box::new(move |_: JsValue| {
+        sync_status.set(None);
+        progress.set(None);
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen("sync-cancelled", cancelled_closure.as_ref().unchecked_ref()).await;
+    cancelled_closure.forget();
+}
+
+/// 监听同步预览的结果
+async fn preview_listener(
+    mut sync_status: Signal<Option<String>>,
+    mut progress: Signal<Option<Progress>>,
+    mut preview: Signal<Option<UpdatePreview>>,
+) {
+    let preview_closure = Closure::wrap(Box::new(move |event: JsValue| {
+        sync_status.set(None);
+        progress.set(None);
+        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
+            if let Ok(update) = serde_wasm_bindgen::from_value::<UpdatePreview>(payload) {
+                preview.set(Some(update));
+            }
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen("sync-preview", preview_closure.as_ref().unchecked_ref()).await;
+    preview_closure.forget();
+
+    let preview_error_closure = Closure::wrap(Box::new(move |event: JsValue| {
+        sync_status.set(None);
+        progress.set(None);
+        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
+            if let Some(error) = payload.as_string() {
+                web_sys::window()
+                    .unwrap()
+                    .alert_with_message(&format!("Failed to preview sync: {}", error))
+                    .ok();
+            }
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen(
+        "sync-preview-error",
+        preview_error_closure.as_ref().unchecked_ref(),
+    )
+    .await;
+    preview_error_closure.forget();
+}
+
+/// 监听 bundle 导出的结果
+async fn bundle_listener(
+    mut sync_status: Signal<Option<String>>,
+    mut progress: Signal<Option<Progress>>,
+) {
+    let exported_closure = Closure::wrap(Box::new(move |event: JsValue| {
+        sync_status.set(None);
+        progress.set(None);
+        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
+            if let Ok(summary) = serde_wasm_bindgen::from_value::<BundleSummary>(payload) {
+                let range = match &summary.since {
+                    Some(since) => {
+                        format!("{}..{}", short_commit(since), short_commit(&summary.tip))
+                    }
+                    None => format!("full history up to {}", short_commit(&summary.tip)),
+                };
+                web_sys::window()
+                    .unwrap()
+                    .alert_with_message(&format!(
+                        "Exported {} objects ({}) to {}",
+                        summary.object_count, range, summary.path
+                    ))
+                    .ok();
+            }
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen("bundle-exported", exported_closure.as_ref().unchecked_ref()).await;
+    exported_closure.forget();
+
+    let export_error_closure = Closure::wrap(Box::new(move |event: JsValue| {
+        sync_status.set(None);
+        progress.set(None);
+        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
+            if let Some(error) = payload.as_string() {
+                web_sys::window()
+                    .unwrap()
+                    .alert_with_message(&format!("Failed to export bundle: {}", error))
+                    .ok();
+            }
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen(
+        "bundle-export-error",
+        export_error_closure.as_ref().unchecked_ref(),
+    )
+    .await;
+    export_error_closure.forget();
+}
+
+/// 监听链接图导出的结果
+async fn link_graph_listener(
+    mut sync_status: Signal<Option<String>>,
+    mut progress: Signal<Option<Progress>>,
+) {
+    let exported_closure = Closure::wrap(Box::new(move |event: JsValue| {
+        sync_status.set(None);
+        progress.set(None);
+        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
+            if let Ok(summary) = serde_wasm_bindgen::from_value::<GraphSummary>(payload) {
+                web_sys::window()
+                    .unwrap()
+                    .alert_with_message(&format!(
+                        "Exported {} pages and {} links to {}",
+                        summary.nodes, summary.edges, summary.path
+                    ))
+                    .ok();
+            }
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen(
+        "link-graph-exported",
+        exported_closure.as_ref().unchecked_ref(),
+    )
+    .await;
+    exported_closure.forget();
+
+    let export_error_closure = Closure::wrap(Box::new(move |event: JsValue| {
+        sync_status.set(None);
+        progress.set(None);
+        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
+            if let Some(error) = payload.as_string() {
+                web_sys::window()
+                    .unwrap()
+                    .alert_with_message(&format!("Failed to export link graph: {}", error))
+                    .ok();
+            }
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen(
+        "link-graph-export-error",
+        export_error_closure.as_ref().unchecked_ref(),
+    )
+    .await;
+    export_error_closure.forget();
+}
+
+/// 监听磁盘维护的结果
+async fn maintenance_listener(
+    mut sync_status: Signal<Option<String>>,
+    mut progress: Signal<Option<Progress>>,
+) {
+    let complete_closure = Closure::wrap(Box::new(move |event: JsValue| {
+        sync_status.set(None);
+        progress.set(None);
+        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
+            if let Ok(report) = serde_wasm_bindgen::from_value::<MaintenanceReport>(payload) {
+                let freed = report.before.total().saturating_sub(report.after.total());
+                let mut message = format!(
+                    "Maintenance finished: {} → {} ({} freed), merged {} index segments",
+                    format_size(report.before.total()),
+                    format_size(report.after.total()),
+                    format_size(freed),
+                    report.merged_segments
+                );
+                if let Some(objects) = report.packed_objects {
+                    message.push_str(&format!(", repacked {} objects", objects));
+                }
+                web_sys::window().unwrap().alert_with_message(&message).ok();
+            }
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen(
+        "maintenance-complete",
+        complete_closure.as_ref().unchecked_ref(),
+    )
+    .await;
+    complete_closure.forget();
+
+    let error_closure = Closure::wrap(Box::new(move |event: JsValue| {
+        sync_status.set(None);
+        progress.set(None);
+        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
+            if let Some(error) = payload.as_string() {
+                web_sys::window()
+                    .unwrap()
+                    .alert_with_message(&format!("Maintenance failed: {}", error))
+                    .ok();
+            }
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen("maintenance-error", error_closure.as_ref().unchecked_ref()).await;
+    error_closure.forget();
+}
+
+/// 磁盘空间不足时显示错误，直到下一次同步成功；具体的失败仍由各自的错误事件报告
+async fn disk_listener(mut disk_error: Signal<Option<String>>) {
+    let full_closure = Closure::wrap(Box::new(move |event: JsValue| {
+        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
+            if let Some(error) = payload.as_string() {
+                disk_error.set(Some(error));
+            }
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen("disk-full", full_closure.as_ref().unchecked_ref()).await;
+    full_closure.forget();
+
+    let synced_closure = Closure::wrap(Box::new(move |_: JsValue| {
+        disk_error.set(None);
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen("sync-complete", synced_closure.as_ref().unchecked_ref()).await;
+    synced_closure.forget();
+}
+
+/// 监听快照构建的结果，构建期间的进度沿用 `sync-status` 和 `progress`
+async fn snapshot_listener(
+    mut sync_status: Signal<Option<String>>,
+    mut progress: Signal<Option<Progress>>,
+    mut snapshot: Signal<Option<SnapshotInfo>>,
+) {
+    let snapshot_closure = Closure::wrap(Box::new(move |event: JsValue| {
+        sync_status.set(None);
+        progress.set(None);
+        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
+            if let Ok(info) = serde_wasm_bindgen::from_value::<SnapshotInfo>(payload) {
+                snapshot.set(Some(info));
+            }
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen(
+        "snapshot-complete",
+        snapshot_closure.as_ref().unchecked_ref(),
+    )
+    .await;
+    snapshot_closure.forget();
+
+    let snapshot_error_closure = Closure::wrap(Box::new(move |event: JsValue| {
+        sync_status.set(None);
+        progress.set(None);
+        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
+            if let Some(error) = payload.as_string() {
+                web_sys::window()
+                    .unwrap()
+                    .alert_with_message(&format!("Failed to build snapshot: {}", error))
+                    .ok();
+            }
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = listen(
+        "snapshot-error",
+        snapshot_error_closure.as_ref().unchecked_ref(),
+    )
+    .await;
+    snapshot_error_closure.forget();
+}
+
+pub fn App() -> Element {
+    let is_ready = use_signal(|| false); // Always ready for now
+    let init_status = use_signal(|| String::from("Initializing..."));
+    let init_error = use_signal(|| Option::<String>::None);
+    let stale_warning = use_signal(|| Option::<String>::None);
+    let disk_error = use_signal(|| Option::<String>::None);
+    let sync_status = use_signal(|| Option::<String>::None);
+    let progress = use_signal(|| Option::<Progress>::None);
+    let phase_start = use_signal(|| Option::<PhaseStart>::None);
+    let snapshot = use_signal(|| Option::<SnapshotInfo>::None);
+    let mut history_page = use_signal(|| Option::<SearchIndex>::None);
+    let mut links_page = use_signal(|| Option::<SearchIndex>::None);
+    let preview = use_signal(|| Option::<UpdatePreview>::None);
+    let mut input_value = use_signal(|| String::from(""));
+    let facet_filters = use_signal(FacetFilters::default);
+    let onboarding = use_resource(get_onboarding);
+    let mut setup_started = use_signal(|| false);
+
+    use_effect(move || {
+        spawn(async move {
+            event_listener(
+                is_ready,
+                init_status,
+                init_error,
+                stale_warning,
+                sync_status,
+                progress,
+                phase_start,
+            )
+            .await;
+            snapshot_listener(sync_status, progress, snapshot).await;
+            preview_listener(sync_status, progress, preview).await;
+            bundle_listener(sync_status, progress).await;
+            link_graph_listener(sync_status, progress).await;
+            maintenance_listener(sync_status, progress).await;
+            disk_listener(disk_error).await;
+        });
+    });
+
+    let search_results = use_resource(move || {
+        let query = input_value.read().clone();
+        let filters = facet_filters.read().clone();
+        async move {
+            if query.is_empty() {
+                return Ok(vec![]);
+            }
+            get_search_results(&query, &filters).await
+        }
+    });
+
+    let facet_counts = use_resource(move || {
+        let query = input_value.read().clone();
+        let filters = facet_filters.read().clone();
+        async move {
+            if query.is_empty() {
+                return Ok(vec![]);
+            }
+            get_facet_counts(&query, &filters).await
+        }
+    });
+
+    // 初始化完成和每次同步结束后刷新
+    let status = use_resource(move || {
+        let idle = is_ready() && sync_status().is_none();
+        async move {
+            if idle {
+                get_status().await.ok()
+            } else {
+                None
+            }
+        }
+    });
+
+    let snapshot_results = use_resource(move || {
+        let query = input_value.read().clone();
+        let commit = snapshot.read().as_ref().map(|info| info.commit.clone());
+        let filters = facet_filters.read().clone();
+        async move {
+            match commit {
+                Some(commit) if !query.is_empty() => {
+                    get_snapshot_search_results(&commit, &query, &filters).await
+                }
+                _ => Ok(vec![]),
+            }
+        }
+    });
+
+    // 首次运行时先选择数据目录和来源，后端在选择之后才开始初始化
+    let setup_dir = onboarding
+        .read()
+        .as_ref()
+        .and_then(|status| status.as_ref().ok())
+        .filter(|status| status.required && !setup_started())
+        .map(|status| status.default_data_dir.clone());
+    if let Some(default_data_dir) = setup_dir {
+        return rsx! {
+            document::Link { rel: "stylesheet", href: CSS }
+            document::Title { "nLab-listary" }
+
+            div { class: "app",
+                Onboarding {
+                    default_data_dir: default_data_dir,
+                    on_start: move |_| setup_started.set(true)
+                }
+            }
+        };
+    }
+
+    rsx! {
+        document::Link { rel: "stylesheet", href: CSS }
+        document::Title { "nLab-listary" }
+
+        div { class: "app",
+            if let Some(error) = init_error() {
+                div { class: "status-message error",
+                    "Failed to initialize: {error}"
+                }
+            }
+
+            if let Some(error) = disk_error().filter(|_| init_error().is_none()) {
+                div { class: "status-message error", "Out of disk space: {error}" }
+            } else if let Some(warning) = stale_warning() {
+                div { class: "status-message warning",
+                    "Could not sync with nLab, results may be out of date: {warning}"
+                }
+            }
+
+            if !is_ready() && init_error().is_none() {
+                div { class: "loading-banner",
+                    "{init_status()}"
+                    CancelButton {}
+                }
+            }
+
+            SearchBox {
+                is_ready: is_ready(),
+                input_value: input_value(),
+                oninput: move |event: FormEvent| input_value.set(event.value())
+            }
+
+            SyncBar {
+                is_ready: is_ready(),
+                sync_status: sync_status,
+                status: status.read().clone().flatten()
+            }
+
+            if is_ready() {
+                ScheduleBar {}
+            }
+
+            if let Some(update) = preview() {
+                PreviewPanel {
+                    preview: update,
+                    sync_status: sync_status,
+                    on_close: move |_| {
+                        let mut preview = preview;
+                        preview.set(None);
+                    }
+                }
+            }
+
+            SnapshotBar {
+                is_ready: is_ready(),
+                sync_status: sync_status,
+                snapshot: snapshot
+            }
+
+            if let Some(progress) = progress() {
+                ProgressBar {
+                    progress: progress,
+                    phase_start: phase_start()
+                }
+            }
+
+            if is_ready() && !input_value().is_empty() {
+                FacetBar {
+                    counts: facet_counts,
+                    filters: facet_filters
+                }
+            }
+
+            if let Some(info) = snapshot() {
+                div { class: "results-columns",
+                    div { class: "results-column",
+                        div { class: "results-heading", "Live" }
+                        SearchResultsList {
+                            is_ready: is_ready(),
+                            input_value: input_value(),
+                            search_results: search_results,
+                            on_history: move |page| history_page.set(Some(page)),
+                            on_links: move |page| links_page.set(Some(page))
+                        }
+                    }
+                    div { class: "results-column",
+                        div { class: "results-heading",
+                            "As of {info.revision} ({short_commit(&info.commit)}, {format_date(info.commit_time)})"
+                        }
+                        SearchResultsList {
+                            is_ready: is_ready(),
+                            input_value: input_value(),
+                            search_results: snapshot_results,
+                            on_history: move |page| history_page.set(Some(page)),
+                            on_links: move |page| links_page.set(Some(page))
+                        }
+                    }
+                }
+            } else {
+                SearchResultsList {
+                    is_ready: is_ready(),
+                    input_value: input_value(),
+                    search_results: search_results,
+                    on_history: move |page| history_page.set(Some(page)),
+                    on_links: move |page| links_page.set(Some(page))
+                }
+            }
+
+            if let Some(page) = history_page() {
+                HistoryPanel {
+                    key: "{page.file_path}",
+                    page: page,
+                    on_close: move |_| history_page.set(None)
+                }
+            }
+
+            if let Some(page) = links_page() {
+                LinksPanel {
+                    key: "{page.id}",
+                    page: page,
+                    on_close: move |_| links_page.set(None)
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn SearchBox(is_ready: bool, input_value: String, oninput: EventHandler<FormEvent>) -> Element {
+    rsx! {
+        div { class: "search-container",
+            input {
+                id: "search_bar",
+                class: "search-input",
+                r#type: "text",
+                placeholder: if is_ready { "Search pages in nLab, or formulas like \\otimes..." } else { "Initializing..." },
+                disabled: !is_ready,
+                value: "{input_value}",
+                autofocus: true,
+                oninput: move |event| oninput.call(event),
+            }
+        }
+    }
+}
+
+#[component]
+fn SyncBar(
+    is_ready: bool,
+    sync_status: Signal<Option<String>>,
+    status: Option<IndexStatus>,
+) -> Element {
+    let syncing = sync_status().is_some();
+
+    rsx! {
+        div { class: "sync-bar",
+            span { class: "sync-status",
+                if let Some(sync_status) = sync_status() {
+                    "{sync_status}"
+                } else if let Some(status) = status {
+                    "{status.summary}"
+                    if let Some(problem) = status.inconsistency {
+                        " (inconsistent: {problem})"
+                    }
+                }
+            }
+            button {
+                class: "sync-button",
+                disabled: !is_ready || syncing,
+                onclick: move |_| {
+                    let mut sync_status = sync_status;
+                    sync_status.set(Some("Starting sync...".to_string()));
+                    spawn(async move {
+                        if let Err(e) = sync_local_repo().await {
+                            sync_status.set(None);
+                            web_sys::window()
+                                .unwrap()
+                                .alert_with_message(&format!("Failed to start sync: {}", e))
+                                .ok();
+                        }
+                    });
+                },
+                if syncing { "Syncing..." } else { "Sync" }
+            }
+            button {
+                class: "sync-button",
+                disabled: !is_ready || syncing,
+                onclick: move |_| {
+                    let mut sync_status = sync_status;
+                    sync_status.set(Some("Starting preview...".to_string()));
+                    spawn(async move {
+                        if let Err(e) = preview_sync().await {
+                            sync_status.set(None);
+                            web_sys::window()
+                                .unwrap()
+                                .alert_with_message(&format!("Failed to start preview: {}", e))
+                                .ok();
+                        }
+                    });
+                },
+                "Preview"
+            }
+            button {
+                class: "sync-button",
+                disabled: !is_ready || syncing,
+                onclick: move |_| {
+                    let window = web_sys::window().unwrap();
+                    let Ok(Some(bundle_path)) = window
+                        .prompt_with_message("Path to a .bundle file exported from a connected machine")
+                    else {
+                        return;
+                    };
+                    let bundle_path = bundle_path.trim().to_string();
+                    if bundle_path.is_empty() {
+                        return;
+                    }
+                    let mut sync_status = sync_status;
+                    sync_status.set(Some("Starting import...".to_string()));
+                    spawn(async move {
+                        if let Err(e) = import_bundle(&bundle_path).await {
+                            sync_status.set(None);
+                            window
+                                .alert_with_message(&format!("Failed to import bundle: {}", e))
+                                .ok();
+                        }
+                    });
+                },
+                "Import bundle"
+            }
+            button {
+                class: "sync-button",
+                disabled: !is_ready || syncing,
+                onclick: move |_| {
+                    let window = web_sys::window().unwrap();
+                    let Ok(Some(output)) = window
+                        .prompt_with_message_and_default("Export bundle to", "nlab.bundle")
+                    else {
+                        return;
+                    };
+                    let Ok(Some(since)) = window
+                        .prompt_with_message(
+                            "Only include commits after (commit, tag or YYYY-MM-DD; empty for full history)",
+                        )
+                    else {
+                        return;
+                    };
+                    let since = Some(since.trim().to_string()).filter(|since| !since.is_empty());
+                    let mut sync_status = sync_status;
+                    sync_status.set(Some("Starting export...".to_string()));
+                    spawn(async move {
+                        if let Err(e) = export_bundle(output.trim(), since).await {
+                            sync_status.set(None);
+                            window
+                                .alert_with_message(&format!("Failed to export bundle: {}", e))
+                                .ok();
+                        }
+                    });
+                },
+                "Export bundle"
+            }
+            button {
+                class: "sync-button",
+                disabled: !is_ready || syncing,
+                onclick: move |_| {
+                    let window = web_sys::window().unwrap();
+                    let Ok(Some(output)) = window
+                        .prompt_with_message_and_default(
+                            "Export link graph to (.graphml, or .dot for Graphviz)",
+                            "nlab-links.graphml",
+                        )
+                    else {
+                        return;
+                    };
+                    let output = output.trim().to_string();
+                    if output.is_empty() {
+                        return;
+                    }
+                    let format = if output.ends_with(".dot") || output.ends_with(".gv") {
+                        "dot"
+                    } else {
+                        "graphml"
+                    };
+                    let mut sync_status = sync_status;
+                    sync_status.set(Some("Starting export...".to_string()));
+                    spawn(async move {
+                        if let Err(e) = export_link_graph(&output, format).await {
+                            sync_status.set(None);
+                            window
+                                .alert_with_message(&format!("Failed to export link graph: {}", e))
+                                .ok();
+                        }
+                    });
+                },
+                "Export link graph"
+            }
+            if syncing {
+                CancelButton {}
+            }
+        }
+    }
+}
+
+/// 自动同步的间隔（小时）和显示的名称
+const SCHEDULE_OPTIONS: [(u64, &str); 4] = [
+    (0, "Only at startup"),
+    (6, "Every 6 hours"),
+    (24, "Daily"),
+    (168, "Weekly"),
+];
+
+/// 定时同步和开机启动的设置，修改后立即保存
+#[component]
+fn ScheduleBar() -> Element {
+    let mut settings = use_signal(ScheduleConfig::default);
+    let mut show_log = use_signal(|| false);
+    let mut show_disk = use_signal(|| false);
+    use_future(move || async move {
+        if let Ok(loaded) = get_schedule().await {
+            settings.set(loaded);
+        }
+    });
+
+    let save = move |updated: ScheduleConfig| {
+        let mut settings = settings;
+        let previous = settings();
+        settings.set(updated.clone());
+        spawn(async move {
+            if let Err(e) = set_schedule(updated).await {
+                settings.set(previous);
+                web_sys::window()
+                    .unwrap()
+                    .alert_with_message(&format!("Failed to save schedule: {}", e))
+                    .ok();
+            }
+        });
+    };
+
+    rsx! {
+        div { class: "schedule-bar",
+            label {
+                "Automatic sync: "
+                select {
+                    onchange: move |event| {
+                        let interval_hours = event.value().parse().unwrap_or(0);
+                        save(ScheduleConfig { interval_hours, ..settings() });
+                    },
+                    for (hours, label) in SCHEDULE_OPTIONS {
+                        option {
+                            value: "{hours}",
+                            selected: settings().interval_hours == hours,
+                            "{label}"
+                        }
+                    }
+                }
+            }
+            label {
+                input {
+                    r#type: "checkbox",
+                    checked: settings().launch_at_login,
+                    onchange: move |event| {
+                        save(ScheduleConfig { launch_at_login: event.checked(), ..settings() });
+                    },
+                }
+                "Launch at login"
+            }
+            button {
+                onclick: move |_| show_log.set(!show_log()),
+                if show_log() { "Hide sync log" } else { "Sync log" }
+            }
+            button {
+                onclick: move |_| show_disk.set(!show_disk()),
+                if show_disk() { "Hide disk usage" } else { "Disk usage" }
+            }
+        }
+        if show_log() {
+            SyncLogPanel {}
+        }
+        if show_disk() {
+            MaintenancePanel {}
+        }
+    }
+}
+
+/// 最近的同步、构建、修复和维护记录
+#[component]
+fn SyncLogPanel() -> Element {
+    let history = use_resource(get_sync_history);
+
+    rsx! {
+        div { class: "sync-log",
+            match &*history.read() {
+                None => rsx! { "Loading..." },
+                Some(Err(e)) => rsx! { "Failed to load sync log: {e}" },
+                Some(Ok(runs)) if runs.is_empty() => rsx! { "No syncs recorded yet" },
+                Some(Ok(runs)) => rsx! {
+                    table {
+                        tr {
+                            th { "Started" }
+                            th { "Run" }
+                            th { "Commits" }
+                            th { "Pages" }
+                            th { "Result" }
+                        }
+                        for run in runs.iter() {
+                            tr { class: if run.error.is_some() { "failed" } else { "" },
+                                td { title: "{run_duration(run)}", "{format_date(run.started_at)}" }
+                                td { title: run.mirror.clone().unwrap_or_default(),
+                                    "{run.kind}"
+                                    if let Some(outcome) = &run.outcome {
+                                        " ({outcome})"
+                                    }
+                                }
+                                td { "{run_commits(run)}" }
+                                td {
+                                    "+{run.pages_added} ~{run.pages_modified} -{run.pages_deleted}"
+                                    if run.parse_failures > 0 {
+                                        ", {run.parse_failures} skipped"
+                                    }
+                                }
+                                td { "{run.error.as_deref().unwrap_or(\"ok\")}" }
+                            }
+                        }
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// 各数据目录的大小，以及整理磁盘空间的操作
+#[component]
+fn MaintenancePanel() -> Element {
+    let usage = use_resource(get_disk_usage);
+    let mut remove_worktree = use_signal(|| Option::<bool>::None);
+
+    let start = move |_| {
+        let remove = remove_worktree().unwrap_or(false);
+        spawn(async move {
+            if let Err(e) = run_maintenance(remove).await {
+                web_sys::window()
+                    .unwrap()
+                    .alert_with_message(&format!("Failed to start maintenance: {}", e))
+                    .ok();
+            }
+        });
+    };
+
+    rsx! {
+        div { class: "maintenance-panel",
+            match &*usage.read() {
+                None => rsx! { "Measuring..." },
+                Some(Err(e)) => rsx! { "Failed to measure disk usage: {e}" },
+                Some(Ok(usage)) => rsx! {
+                    table {
+                        for (label, size) in [
+                            ("Git mirror", usage.mirror),
+                            ("Page database", usage.database),
+                            ("Search index", usage.index),
+                            ("Snapshots", usage.snapshots),
+                            ("Total", usage.total()),
+                        ] {
+                            tr {
+                                td { "{label}" }
+                                td { "{format_size(size)}" }
+                            }
+                        }
+                    }
+                    label {
+                        input {
+                            r#type: "checkbox",
+                            checked: remove_worktree().unwrap_or(!usage.worktree),
+                            onchange: move |event| remove_worktree.set(Some(event.checked())),
+                        }
+                        "Remove the mirror's working tree (pages are read from git objects)"
+                    }
+                },
+            }
+            button { onclick: start, "Run maintenance" }
+        }
+    }
+}
+
+/// 首次运行可选的来源：类型、标题和路径输入框的提示
+const SETUP_KINDS: [(&str, &str, &str); 4] = [
+    (
+        "clone",
+        "Clone from a mirror",
+        "Mirror URL or .bundle file (leave empty for the configured mirrors)",
+    ),
+    (
+        "existing_clone",
+        "Import an existing clone",
+        "Path to a clone of nlab-content-html",
+    ),
+    (
+        "archive",
+        "Import an archive",
+        "Page directory or .tar.gz/.zip archive",
+    ),
+    (
+        "index_bundle",
+        "Import a prebuilt index bundle",
+        ".tar.gz/.zip containing nlab_page_data.db and nlab_page_index",
+    ),
+];
 
-    // check if the result is an error message
-    if ret.is_undefined() || ret.is_null() {
-        return Err("Received invalid response from backend".to_string());
+fn setup_choice(kind: &str, location: String, shallow: bool) -> SetupChoice {
+    match kind {
+        "existing_clone" => SetupChoice::ExistingClone { path: location },
+        "archive" => SetupChoice::Archive { path: location },
+        "index_bundle" => SetupChoice::IndexBundle { path: location },
+        _ => SetupChoice::Clone {
+            url: location,
+            shallow,
+        },
     }
+}
 
-    // try to parse the result as an error message
-    if let Ok(error_msg) = serde_wasm_bindgen::from_value::<String>(ret.clone()) {
-        return Err(error_msg);
-    }
+/// 首次运行：选择数据目录和来源，显示需要的空间，确认后才开始克隆或导入
+#[component]
+fn Onboarding(default_data_dir: String, on_start: EventHandler<()>) -> Element {
+    let mut data_dir = use_signal(|| default_data_dir.clone());
+    let mut kind = use_signal(|| "clone");
+    let mut location = use_signal(String::new);
+    let mut shallow = use_signal(|| false);
+    let mut error = use_signal(|| Option::<String>::None);
 
-    // parse the result as search results
-    let results: Vec<SearchIndex> = serde_wasm_bindgen::from_value(ret)
-        .map_err(|e| format!("Failed to parse search results: {:?}", e))?;
+    let estimate = use_resource(move || {
+        let data_dir = data_dir();
+        let kind = kind();
+        let missing = kind != "clone" && location().trim().is_empty();
+        let choice = setup_choice(kind, location(), shallow());
+        async move {
+            if missing {
+                return Err("Choose what to import".to_string());
+            }
+            estimate_setup(&data_dir, &choice).await
+        }
+    });
+    let enough_space = matches!(
+        &*estimate.read(),
+        Some(Ok(estimate)) if estimate.available.is_none_or(|available| available >= estimate.required)
+    );
 
-    Ok(results)
-}
+    let start = move |_| {
+        let data_dir = data_dir();
+        let choice = setup_choice(kind(), location(), shallow());
+        spawn(async move {
+            match complete_setup(&data_dir, &choice).await {
+                Ok(()) => on_start.call(()),
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    };
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-struct OpenArgs {
-    url: String,
-}
-async fn open_url(url: &str) -> Result<(), String> {
-    let args = serde_wasm_bindgen::to_value(&OpenArgs {
-        url: url.to_string(),
-    })
-    .map_err(|e| format!("Failed to serialize args: {:?}", e))?;
+    let hint = SETUP_KINDS
+        .iter()
+        .find(|(value, _, _)| *value == kind())
+        .map_or("", |(_, _, hint)| *hint);
 
-    let ret = invoke("open_url", args).await;
-    if let Some(err) = ret.as_string() {
-        eprintln!("Error opening URL: {}", err);
-        return Err(err);
+    rsx! {
+        div { class: "onboarding",
+            h2 { "Set up nLab-listary" }
+            label {
+                "Data directory"
+                input {
+                    value: "{data_dir}",
+                    oninput: move |event| data_dir.set(event.value()),
+                }
+            }
+            for (value, title, _) in SETUP_KINDS {
+                label { class: "onboarding-choice",
+                    input {
+                        r#type: "radio",
+                        name: "setup-kind",
+                        checked: kind() == value,
+                        onchange: move |_| kind.set(value),
+                    }
+                    "{title}"
+                }
+            }
+            input {
+                placeholder: "{hint}",
+                value: "{location}",
+                oninput: move |event| location.set(event.value()),
+            }
+            if kind() == "clone" {
+                label { class: "onboarding-choice",
+                    input {
+                        r#type: "checkbox",
+                        checked: shallow(),
+                        onchange: move |event| shallow.set(event.checked()),
+                    }
+                    "Shallow clone (latest commit and pages only)"
+                }
+            }
+            div { class: "onboarding-space",
+                match &*estimate.read() {
+                    None => rsx! { "Estimating required space..." },
+                    Some(Err(e)) => rsx! { "{e}" },
+                    Some(Ok(estimate)) => rsx! {
+                        "Requires about {format_size(estimate.required)}"
+                        if let Some(available) = estimate.available {
+                            ", {format_size(available)} available"
+                        }
+                    },
+                }
+            }
+            if let Some(e) = error() {
+                div { class: "status-message error", "{e}" }
+            }
+            button { disabled: !enough_space, onclick: start, "Start" }
+        }
     }
+}
 
-    Ok(())
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
 }
 
-async fn event_listener(
-    mut is_ready: Signal<bool>,
-    mut init_status: Signal<String>,
-    mut init_error: Signal<Option<String>>,
-) {
-    let status_closure = Closure::wrap(Box::new(move |event: JsValue| {
-        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
-            if let Some(status) = payload.as_string() {
-                init_status.set(status);
-            }
+fn run_commits(run: &SyncRun) -> String {
+    match (&run.old_commit, &run.new_commit) {
+        (Some(old), Some(new)) if old != new => {
+            format!("{} → {}", short_commit(old), short_commit(new))
         }
-    }) as Box<dyn FnMut(JsValue)>);
-
-    let _ = listen("init-status", status_closure.as_ref().unchecked_ref()).await;
-    status_closure.forget();
+        (_, Some(new)) => short_commit(new).to_string(),
+        _ => String::new(),
+    }
+}
 
-    let complete_closure = Closure::wrap(Box::new(move |_: JsValue| {
-        is_ready.set(true);
-    }) as Box<dyn FnMut(JsValue)>);
+fn run_duration(run: &SyncRun) -> String {
+    format!(
+        "took {}",
+        format_duration((run.finished_at - run.started_at) as f64)
+    )
+}
 
-    let _ = listen("init-complete", complete_closure.as_ref().unchecked_ref()).await;
-    complete_closure.forget();
+/// 输入日期、标签或提交号，在实时结果旁边显示当时的 nLab
+#[component]
+fn SnapshotBar(
+    is_ready: bool,
+    sync_status: Signal<Option<String>>,
+    snapshot: Signal<Option<SnapshotInfo>>,
+) -> Element {
+    let mut revision = use_signal(String::new);
+    let busy = sync_status().is_some();
 
-    let error_closure = Closure::wrap(Box::new(move |event: JsValue| {
-        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
-            if let Some(error) = payload.as_string() {
-                init_error.set(Some(error));
+    rsx! {
+        div { class: "snapshot-bar",
+            input {
+                class: "snapshot-input",
+                r#type: "text",
+                placeholder: "Compare with nLab as of (YYYY-MM-DD, tag or commit)",
+                disabled: !is_ready,
+                value: "{revision}",
+                oninput: move |event| revision.set(event.value()),
+            }
+            button {
+                disabled: !is_ready || busy || revision().trim().is_empty(),
+                onclick: move |_| {
+                    let mut sync_status = sync_status;
+                    let revision = revision().trim().to_string();
+                    sync_status.set(Some(format!("Loading snapshot of {}...", revision)));
+                    spawn(async move {
+                        if let Err(e) = build_snapshot(&revision).await {
+                            sync_status.set(None);
+                            web_sys::window()
+                                .unwrap()
+                                .alert_with_message(&format!("Failed to load snapshot: {}", e))
+                                .ok();
+                        }
+                    });
+                },
+                "Compare"
+            }
+            if snapshot().is_some() {
+                button {
+                    onclick: move |_| {
+                        let mut snapshot = snapshot;
+                        snapshot.set(None);
+                    },
+                    "Close"
+                }
             }
         }
-    }) as Box<dyn FnMut(JsValue)>);
+    }
+}
 
-    let _ = listen("init-error", error_closure.as_ref().unchecked_ref()).await;
-    error_closure.forget();
+fn short_commit(commit: &str) -> &str {
+    &commit[..commit.len().min(8)]
 }
 
-pub fn App() -> Element {
-    let is_ready = use_signal(|| false); // Always ready for now
-    let init_status = use_signal(|| String::from("Initializing..."));
-    let init_error = use_signal(|| Option::<String>::None);
-    let mut input_value = use_signal(|| String::from(""));
+/// 把 Unix 时间戳格式化为 `YYYY-MM-DD`
+fn format_date(timestamp: i64) -> String {
+    let date = js_sys::Date::new(&JsValue::from_f64(timestamp as f64 * 1000.0));
+    format!(
+        "{:04}-{:02}-{:02}",
+        date.get_utc_full_year(),
+        date.get_utc_month() + 1,
+        date.get_utc_date()
+    )
+}
 
-    use_effect(move || {
-        spawn(async move {
-            event_listener(is_ready, init_status, init_error).await;
-        });
-    });
+/// 同步预览：列出将要新增、修改和删除的页面，确认后执行同步
+#[component]
+fn PreviewPanel(
+    preview: UpdatePreview,
+    sync_status: Signal<Option<String>>,
+    on_close: EventHandler<()>,
+) -> Element {
+    let count = |kind: &str| preview.pages.iter().filter(|p| p.kind == kind).count();
+    let summary = format!(
+        "{} added, {} modified, {} deleted",
+        count("added"),
+        count("modified"),
+        count("deleted")
+    );
+    let up_to_date = preview.head == preview.target;
 
-    let search_results = use_resource(move || {
-        let query = input_value.read().clone();
-        async move {
-            if query.is_empty() {
-                return Ok(vec![]);
+    rsx! {
+        div { class: "preview-panel",
+            div { class: "history-header",
+                span {
+                    if up_to_date {
+                        "Already up to date with {preview.mirror}"
+                    } else {
+                        "Update {short_commit(&preview.head)} → {short_commit(&preview.target)}: {summary}"
+                    }
+                }
+                span {
+                    if !up_to_date {
+                        button {
+                            class: "sync-button",
+                            disabled: sync_status().is_some(),
+                            onclick: move |_| {
+                                let mut sync_status = sync_status;
+                                sync_status.set(Some("Starting sync...".to_string()));
+                                on_close.call(());
+                                spawn(async move {
+                                    if let Err(e) = sync_local_repo().await {
+                                        sync_status.set(None);
+                                        web_sys::window()
+                                            .unwrap()
+                                            .alert_with_message(&format!("Failed to start sync: {}", e))
+                                            .ok();
+                                    }
+                                });
+                            },
+                            "Apply"
+                        }
+                    }
+                    button {
+                        onclick: move |_| on_close.call(()),
+                        "Dismiss"
+                    }
+                }
+            }
+            for page in preview.pages.iter() {
+                div { class: "preview-page {page.kind}",
+                    span { class: "preview-kind", "{page.kind}" }
+                    if page.title.is_empty() {
+                        "{page.file_path}"
+                    } else {
+                        "{page.title}"
+                    }
+                }
             }
-            get_search_results(&query).await
         }
-    });
+    }
+}
 
+#[component]
+fn CancelButton() -> Element {
     rsx! {
-        document::Link { rel: "stylesheet", href: CSS }
-        document::Title { "nLab-listary" }
+        button {
+            class: "cancel-button",
+            onclick: move |_| {
+                spawn(async move {
+                    if let Err(e) = cancel_operation().await {
+                        web_sys::window()
+                            .unwrap()
+                            .alert_with_message(&format!("Failed to cancel: {}", e))
+                            .ok();
+                    }
+                });
+            },
+            "Cancel"
+        }
+    }
+}
 
-        div { class: "app",
-            if let Some(error) = init_error() {
-                div { class: "status-message error",
-                    "Failed to initialize: {error}"
-                }
-            }
+#[component]
+fn ProgressBar(progress: Progress, phase_start: Option<PhaseStart>) -> Element {
+    let label = match progress.phase.as_str() {
+        "clone" => "Receiving objects",
+        "fetch" => "Fetching objects",
+        "checkout" => "Checking out files",
+        "parse" => "Parsing pages",
+        "index" => "Indexing documents",
+        "pack" => "Packing objects",
+        "extract" => "Extracting archive",
+        _ => "Working",
+    };
+    let percent = if progress.total > 0 {
+        progress.current as f64 * 100.0 / progress.total as f64
+    } else {
+        0.0
+    };
+    let size = if progress.received_bytes > 0 {
+        format!(
+            " ({:.1} MB)",
+            progress.received_bytes as f64 / 1024.0 / 1024.0
+        )
+    } else {
+        String::new()
+    };
+    let eta = phase_start
+        .and_then(|start| estimate_remaining(&progress, &start))
+        .map(|seconds| format!(", about {} left", format_duration(seconds)))
+        .unwrap_or_default();
 
-            if !is_ready() && init_error().is_none() {
-                div { class: "loading-banner",
-                    "{init_status()}"
+    rsx! {
+        div { class: "progress",
+            div { class: "progress-label",
+                "{label}: {progress.current}/{progress.total}{size}{eta}"
+            }
+            div { class: "progress-track",
+                div {
+                    class: "progress-fill",
+                    style: "width: {percent:.1}%"
                 }
             }
+        }
+    }
+}
 
-            SearchBox {
-                is_ready: is_ready(),
-                input_value: input_value(),
-                oninput: move |event: FormEvent| input_value.set(event.value())
-            }
+/// 按当前阶段的平均速度估算剩余秒数
+fn estimate_remaining(progress: &Progress, start: &PhaseStart) -> Option<f64> {
+    let done = progress.current.checked_sub(start.start_current)?;
+    let elapsed = (js_sys::Date::now() - start.started_at) / 1000.0;
+    if done == 0 || elapsed < 1.0 || progress.current >= progress.total {
+        return None;
+    }
+    let rate = done as f64 / elapsed;
+    Some((progress.total - progress.current) as f64 / rate)
+}
 
-            SearchResultsList {
-                is_ready: is_ready(),
-                input_value: input_value(),
-                search_results: search_results
-            }
-        }
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    if seconds >= 60 {
+        format!("{}m {}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}s", seconds)
     }
 }
 
+/// 搜索结果中的分类和领域及其页面数：点击名字只看这一项，点击 "−" 排除这一项
 #[component]
-fn SearchBox(is_ready: bool, input_value: String, oninput: EventHandler<FormEvent>) -> Element {
+fn FacetBar(
+    counts: Resource<Result<Vec<FacetCount>, String>>,
+    filters: Signal<FacetFilters>,
+) -> Element {
+    let mut filters = filters;
+    let active = filters();
+    let counts = match &*counts.read_unchecked() {
+        Some(Ok(counts)) => counts.clone(),
+        _ => Vec::new(),
+    };
+    if counts.is_empty() && active.is_empty() {
+        return rsx! {};
+    }
+
+    let chips = active
+        .include
+        .iter()
+        .map(|filter| (filter.clone(), false))
+        .chain(active.exclude.iter().map(|filter| (filter.clone(), true)));
+
     rsx! {
-        div { class: "search-container",
-            input {
-                id: "search_bar",
-                class: "search-input",
-                r#type: "text",
-                placeholder: if is_ready { "Search pages in nLab..." } else { "Initializing..." },
-                disabled: !is_ready,
-                value: "{input_value}",
-                autofocus: true,
-                oninput: move |event| oninput.call(event),
+        div { class: "facet-bar",
+            if !active.is_empty() {
+                div { class: "facet-group",
+                    for (filter, excluded) in chips {
+                        span {
+                            class: if excluded { "facet-chip excluded" } else { "facet-chip" },
+                            if excluded { "not " }
+                            "{filter.kind}: {filter.value}"
+                            button {
+                                onclick: move |_| filters.with_mut(|f| f.remove(&filter)),
+                                "×"
+                            }
+                        }
+                    }
+                    button {
+                        class: "history-button",
+                        onclick: move |_| filters.set(FacetFilters::default()),
+                        "Clear filters"
+                    }
+                }
+            }
+            for (kind, label) in [("category", "Categories"), ("context", "Contexts")] {
+                if counts.iter().any(|count| count.kind == kind) {
+                    div { class: "facet-group",
+                        span { class: "facet-kind", "{label}:" }
+                        for count in counts.iter().filter(|count| count.kind == kind) {
+                            span { class: "facet-value",
+                                span {
+                                    class: "facet-name",
+                                    onclick: {
+                                        let filter = FacetFilter {
+                                            kind: count.kind.clone(),
+                                            value: count.value.clone(),
+                                        };
+                                        move |_| filters.with_mut(|f| f.set(filter.clone(), false))
+                                    },
+                                    "{count.value} ({count.count})"
+                                }
+                                button {
+                                    title: "Hide these pages",
+                                    onclick: {
+                                        let filter = FacetFilter {
+                                            kind: count.kind.clone(),
+                                            value: count.value.clone(),
+                                        };
+                                        move |_| filters.with_mut(|f| f.set(filter.clone(), true))
+                                    },
+                                    "−"
+                                }
+                            }
+                        }
+                    }
+                }
             }
         }
     }
@@ -189,6 +2047,8 @@ fn SearchResultsList(
     is_ready: bool,
     input_value: String,
     search_results: Resource<Result<Vec<SearchIndex>, String>>,
+    on_history: EventHandler<SearchIndex>,
+    on_links: EventHandler<SearchIndex>,
 ) -> Element {
     rsx! {
         div { class: "results",
@@ -210,7 +2070,9 @@ fn SearchResultsList(
                         } else {
                             for result in results {
                                 ResultItem {
-                                    result: result.clone()
+                                    result: result.clone(),
+                                    on_history: on_history,
+                                    on_links: on_links
                                 }
                             }
                         }
@@ -232,7 +2094,14 @@ fn SearchResultsList(
 }
 
 #[component]
-fn ResultItem(result: SearchIndex) -> Element {
+fn ResultItem(
+    result: SearchIndex,
+    on_history: EventHandler<SearchIndex>,
+    on_links: EventHandler<SearchIndex>,
+) -> Element {
+    let page = result.clone();
+    let linked_page = result.clone();
+
     rsx! {
         div {
             class: "result-item",
@@ -251,10 +2120,240 @@ fn ResultItem(result: SearchIndex) -> Element {
 
             div { class: "result-title",
                 "{result.title}"
+                if let Some(section) = &result.section {
+                    span { class: "result-section", " § {section}" }
+                }
             }
             div { class: "result-url",
                 "{result.url}"
             }
+            button {
+                class: "history-button",
+                onclick: move |event| {
+                    // 不触发外层打开网页的点击
+                    event.stop_propagation();
+                    on_history.call(page.clone());
+                },
+                "History"
+            }
+            button {
+                class: "history-button",
+                onclick: move |event| {
+                    event.stop_propagation();
+                    on_links.call(linked_page.clone());
+                },
+                "Links"
+            }
+        }
+    }
+}
+
+/// 页面的修改历史，以及任意两个版本之间的正文差异
+#[component]
+fn HistoryPanel(page: SearchIndex, on_close: EventHandler<()>) -> Element {
+    let mut old = use_signal(|| Option::<String>::None);
+    let mut new = use_signal(|| Option::<String>::None);
+
+    let file_path = page.file_path.clone();
+    let history = use_resource(move || {
+        let file_path = file_path.clone();
+        async move { get_page_history(&file_path).await }
+    });
+
+    let file_path = page.file_path.clone();
+    let diff = use_resource(move || {
+        let file_path = file_path.clone();
+        let revisions = history_revisions(&history);
+        // 默认比较最近两次修改
+        let old_commit = selected_commit(old(), &revisions, 1);
+        let new_commit = selected_commit(new(), &revisions, 0);
+        async move {
+            match (old_commit, new_commit) {
+                (Some(old), Some(new)) => get_page_diff(&file_path, &old, &new).await,
+                _ => Ok(vec![]),
+            }
+        }
+    });
+
+    let revisions = history_revisions(&history);
+    let old_commit = selected_commit(old(), &revisions, 1);
+    let new_commit = selected_commit(new(), &revisions, 0);
+
+    rsx! {
+        div { class: "history-panel",
+            div { class: "history-header",
+                span { "History of {page.title}" }
+                button {
+                    onclick: move |_| on_close.call(()),
+                    "Close"
+                }
+            }
+            match &*history.read_unchecked() {
+                None => rsx! {
+                    div { class: "status-message", "Loading history..." }
+                },
+                Some(Err(error)) => rsx! {
+                    div { class: "status-message error", "Error: {error}" }
+                },
+                Some(Ok(revisions)) if revisions.len() < 2 => rsx! {
+                    div { class: "status-message",
+                        "This page has not been edited since it was created"
+                    }
+                },
+                Some(Ok(revisions)) => rsx! {
+                    div { class: "history-select",
+                        "From "
+                        select {
+                            onchange: move |event| old.set(Some(event.value())),
+                            for revision in revisions.iter() {
+                                option {
+                                    value: "{revision.commit}",
+                                    selected: old_commit.as_ref() == Some(&revision.commit),
+                                    "{revision_label(revision)}"
+                                }
+                            }
+                        }
+                        " to "
+                        select {
+                            onchange: move |event| new.set(Some(event.value())),
+                            for revision in revisions.iter() {
+                                option {
+                                    value: "{revision.commit}",
+                                    selected: new_commit.as_ref() == Some(&revision.commit),
+                                    "{revision_label(revision)}"
+                                }
+                            }
+                        }
+                    }
+                    match &*diff.read_unchecked() {
+                        None => rsx! {
+                            div { class: "status-message", "Comparing..." }
+                        },
+                        Some(Err(error)) => rsx! {
+                            div { class: "status-message error", "Error: {error}" }
+                        },
+                        Some(Ok(lines)) if lines.is_empty() => rsx! {
+                            div { class: "status-message", "No text changes" }
+                        },
+                        Some(Ok(lines)) => rsx! {
+                            div { class: "diff",
+                                for line in lines.iter() {
+                                    div { class: "diff-line {line.kind}",
+                                        "{diff_prefix(&line.kind)}{line.text}"
+                                    }
+                                }
+                            }
+                        },
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// 页面中的链接和链接到该页面的页面，点击页面名在浏览器中打开，点击 Links 切换到该页面
+#[component]
+fn LinksPanel(page: SearchIndex, on_close: EventHandler<()>) -> Element {
+    let mut current = use_signal(|| (page.id, page.title.clone()));
+    let links = use_resource(move || async move { get_page_links(current().0).await });
+    let title = current().1;
+
+    rsx! {
+        div { class: "history-panel",
+            div { class: "history-header",
+                span { "Links of {title}" }
+                button {
+                    onclick: move |_| on_close.call(()),
+                    "Close"
+                }
+            }
+            match &*links.read_unchecked() {
+                None => rsx! {
+                    div { class: "status-message", "Loading links..." }
+                },
+                Some(Err(error)) => rsx! {
+                    div { class: "status-message error", "Error: {error}" }
+                },
+                Some(Ok(links)) => rsx! {
+                    for (heading, pages) in [
+                        ("Links from this page", links.outgoing.clone()),
+                        ("What links here", links.incoming.clone()),
+                    ] {
+                        div { class: "links-heading", "{heading} ({pages.len()})" }
+                        if pages.is_empty() {
+                            div { class: "link-item missing", "None" }
+                        }
+                        for linked in pages {
+                            div {
+                                class: if linked.id.is_some() { "link-item" } else { "link-item missing" },
+                                span {
+                                    class: "link-title",
+                                    title: "{linked.url}",
+                                    onclick: {
+                                        let url = linked.url.clone();
+                                        move |_| {
+                                            let url = url.clone();
+                                            spawn(async move {
+                                                if let Err(e) = open_url(&url).await {
+                                                    web_sys::window()
+                                                        .unwrap()
+                                                        .alert_with_message(&format!("Failed to open URL: {}", e))
+                                                        .ok();
+                                                }
+                                            });
+                                        }
+                                    },
+                                    "{linked.title}"
+                                }
+                                if let Some(id) = linked.id {
+                                    button {
+                                        class: "history-button",
+                                        onclick: {
+                                            let title = linked.title.clone();
+                                            move |_| current.set((id, title.clone()))
+                                        },
+                                        "Links"
+                                    }
+                                }
+                            }
+                        }
+                    }
+                },
+            }
         }
     }
 }
+
+fn history_revisions(history: &Resource<Result<Vec<PageRevision>, String>>) -> Vec<PageRevision> {
+    match &*history.read() {
+        Some(Ok(revisions)) => revisions.clone(),
+        _ => Vec::new(),
+    }
+}
+
+/// 用户选择的提交，未选择时使用第 `default_index` 个修改
+fn selected_commit(
+    choice: Option<String>,
+    revisions: &[PageRevision],
+    default_index: usize,
+) -> Option<String> {
+    choice.or_else(|| revisions.get(default_index).map(|r| r.commit.clone()))
+}
+
+fn revision_label(revision: &PageRevision) -> String {
+    format!(
+        "{} {} {}",
+        format_date(revision.time),
+        short_commit(&revision.commit),
+        revision.summary
+    )
+}
+
+fn diff_prefix(kind: &str) -> &'static str {
+    match kind {
+        "added" => "+ ",
+        "removed" => "- ",
+        "context" => "  ",
+        _ => "",
+    }
+}